@@ -0,0 +1,440 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::padded::Padded;
+
+use crate::debug::DebugOptions;
+use crate::device_select::{DeviceSelection, DeviceSelector};
+use crate::error::{Result, SandError};
+use crate::simulation::reactions::ReactionTable;
+use crate::simulation::sand::{sand_shader::Material, PADDING};
+use crate::simulation::scene::{self, Scene};
+use crate::window::{PresentPreference, WindowOptions};
+
+/// Invocations per work group, has to match local_size_x in sand_particle.glsl.
+pub const WORK_GROUP_SIZE: u32 = 64;
+pub const DEFAULT_WORLD_SIZE: u32 = 1024;
+pub const DEFAULT_SEED: u64 = 0x5eed;
+
+pub const USAGE: &str = "\
+usage: new [options]
+
+world
+  --world-size <n>       particles the world can hold (default 1024)
+  --work-groups <n>      compute work groups per tick, each runs 64 particles
+                         (default: just enough for --world-size)
+  --scene <path>         initial particles and level geometry, see simulation/scene.rs for the format
+  --seed <n>             seed for the generated scene and, with --deterministic, the simulation (default 24301)
+  --deterministic        seed the simulation's rng from --seed instead of the clock, so a run can be repeated
+                         exactly (checksums are logged every frame at debug level under `checksum`)
+  --config <path>        simulation and render parameters (see sand.toml), reloaded when it changes
+  --reactions <path>     what materials turn into when they touch (default: the built in reactions.toml)
+
+running
+  --headless <ticks>     run this many ticks without a window, then exit
+  --output <path>        write the world as a scene file after --headless or when the window closes
+  --checksums <path>     with --headless, write every tick's world checksum, one per line
+  --record <path>        write every input (painting, explosions, bodies, force fields, materials, pause/step, config reloads) with the tick it lands on
+  --replay <path>        feed a recording back in, pass the same --scene, --config and --reactions it was made with.
+                         works with --headless too, compare --checksums to find where runs diverge
+  --present <mode>       vsync, low-latency or uncapped (default low-latency), V cycles at runtime
+  --hdr                  use an extended colour space if the surface offers one
+  --no-fps               don't print the fps/gpu timings readout
+  --frame-csv <path>     write every frame's timings, for benchmarking
+
+gpu
+  --gpu <selector>       index, name, discrete, integrated, virtual or cpu (or SAND_GPU)
+  --list-devices         print every gpu and whether it's usable, then exit
+  --validation           enable the vulkan validation layer, debug builds only (or SAND_VALIDATION)
+
+logging
+  --log-file <path>      write diagnostics here instead of stderr, RUST_LOG sets the levels
+                         (e.g. RUST_LOG=debug or RUST_LOG=info,vulkan=warn, RUST_LOG=sleep=debug logs how many
+                         particles every frame's tick moved, the rest are asleep)
+
+  -h, --help             print this and exit
+";
+
+/// What the command line asks for, main prints `USAGE` for `Help`.
+#[derive(Clone, Debug)]
+pub enum Command {
+    Run(Options),
+    Help,
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub device: DeviceSelection,
+    pub debug: DebugOptions,
+    pub world_size: u32,
+    pub work_groups: u32, // x only, y and z are always 1
+    pub scene: Option<PathBuf>,
+    pub reactions: Option<PathBuf>,
+    pub seed: u64,
+    pub deterministic: bool,
+    pub headless_ticks: Option<u64>,
+    pub checksums: Option<PathBuf>,
+    pub log_file: Option<PathBuf>,
+    pub window: WindowOptions, // output, config and replay paths live in here as the window uses them
+}
+
+impl Options {
+    /// Parses `std::env::args`.
+    pub fn from_env() -> Result<Command> {
+        Options::parse(std::env::args().skip(1))
+    }
+
+    /// `args` without the program name. Env vars are the defaults, flags win over them.
+    /// Parsing stops at `--help`, nothing after it is looked at.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command> {
+        let mut device = DeviceSelection::from_env();
+        let mut validation = false;
+        let mut world_size = DEFAULT_WORLD_SIZE;
+        let mut work_groups = None;
+        let mut scene = None;
+        let mut reactions = None;
+        let mut seed = DEFAULT_SEED;
+        let mut deterministic = false;
+        let mut checksums = None;
+        let mut headless_ticks = None;
+        let mut log_file = None;
+        let mut window = WindowOptions::default();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // --flag=value works as well as --flag value
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+                _ => (arg, None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| SandError::Config(format!("{flag} needs a value")))
+            };
+            match flag.as_str() {
+                "-h" | "--help" => return Ok(Command::Help),
+                "--world-size" => world_size = parse_number(&flag, &value()?)?,
+                "--work-groups" => work_groups = Some(parse_number(&flag, &value()?)?),
+                "--scene" => scene = Some(PathBuf::from(value()?)),
+                "--reactions" => reactions = Some(PathBuf::from(value()?)),
+                "--seed" => seed = parse_number(&flag, &value()?)?,
+                "--deterministic" => deterministic = true,
+                "--checksums" => checksums = Some(PathBuf::from(value()?)),
+                "--headless" => headless_ticks = Some(parse_number(&flag, &value()?)?),
+                "--config" => window.config = Some(PathBuf::from(value()?)),
+                "--record" => window.record = Some(PathBuf::from(value()?)),
+                "--replay" => window.replay = Some(PathBuf::from(value()?)),
+                "--output" => window.world_output = Some(PathBuf::from(value()?)),
+                "--present" => {
+                    let mode = value()?;
+                    window.present_preference = PresentPreference::parse(&mode).ok_or_else(|| {
+                        SandError::Config(format!(
+                            "--present {mode:?} isn't vsync, low-latency or uncapped"
+                        ))
+                    })?;
+                }
+                "--hdr" => window.prefer_hdr = true,
+                "--no-fps" => {
+                    window.fps_display = false;
+                    window.gpu_timings_display = false;
+                }
+                "--frame-csv" => window.frame_csv = Some(PathBuf::from(value()?)),
+                "--gpu" => device.selector = DeviceSelector::parse(&value()?),
+                "--list-devices" => device.list_devices = true,
+                "--validation" => validation = true,
+                "--log-file" => log_file = Some(PathBuf::from(value()?)),
+                _ => return Err(SandError::Config(format!("unknown option {flag:?}"))),
+            }
+        }
+
+        if world_size == 0 {
+            return Err(SandError::Config("--world-size has to be at least 1".to_string()));
+        }
+        let work_groups = work_groups.unwrap_or_else(|| world_size.div_ceil(WORK_GROUP_SIZE));
+        if work_groups == 0 {
+            return Err(SandError::Config("--work-groups has to be at least 1".to_string()));
+        }
+        // the buffer is sized by the dispatch, so it has to cover every particle
+        if work_groups as u64 * (WORK_GROUP_SIZE as u64) < world_size as u64 {
+            return Err(SandError::Config(format!(
+                "--work-groups {work_groups} only runs {} particles but --world-size is {world_size}",
+                work_groups as u64 * WORK_GROUP_SIZE as u64
+            )));
+        }
+
+        Ok(Command::Run(Options {
+            device,
+            debug: DebugOptions {
+                validation: validation || DebugOptions::from_env().validation,
+            },
+            world_size,
+            work_groups,
+            scene,
+            reactions,
+            seed,
+            deterministic,
+            headless_ticks,
+            checksums,
+            log_file,
+            window,
+        }))
+    }
+
+    /// Keys the shader's rng. Only fixed with --deterministic, otherwise every run differs.
+    pub fn simulation_seed(&self) -> u32 {
+        let seed = if self.deterministic {
+            self.seed
+        } else {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default();
+            self.seed ^ now.as_nanos() as u64
+        };
+        (seed ^ (seed >> 32)) as u32
+    }
+
+    pub fn work_group_counts(&self) -> [u32; 3] {
+        [self.work_groups, 1, 1]
+    }
+
+    /// Particles the world buffer holds, always a whole number of work groups.
+    pub fn capacity(&self) -> u64 {
+        self.work_groups as u64 * WORK_GROUP_SIZE as u64
+    }
+
+    /// Checks the dispatch and world buffer fit in what the gpu allows, before anything is allocated.
+    pub fn validate(&self, physical_device: &Arc<PhysicalDevice>) -> Result<()> {
+        let properties = physical_device.properties();
+        let max_work_groups = properties.max_compute_work_group_count[0];
+        if self.work_groups > max_work_groups {
+            return Err(SandError::Config(format!(
+                "{} allows at most {max_work_groups} work groups, asked for {}",
+                properties.device_name, self.work_groups
+            )));
+        }
+        let buffer_bytes = self.capacity() * std::mem::size_of::<Padded<Material, PADDING>>() as u64;
+        let max_buffer_bytes = properties.max_storage_buffer_range as u64;
+        if buffer_bytes > max_buffer_bytes {
+            return Err(SandError::Config(format!(
+                "the world buffer would be {buffer_bytes} bytes but {} allows {max_buffer_bytes} \
+                 ({} particles), use a smaller --world-size",
+                properties.device_name,
+                max_buffer_bytes / std::mem::size_of::<Padded<Material, PADDING>>() as u64
+            )));
+        }
+        Ok(())
+    }
+
+    /// The particles and geometry from `--scene`, or a generated row from `--seed` with no geometry.
+    pub fn initial_scene(&self) -> Result<Scene> {
+        let scene = match &self.scene {
+            Some(path) => scene::load(path)?,
+            None => Scene {
+                particles: scene::generate(self.world_size, self.seed),
+                ..Default::default()
+            },
+        };
+        if scene.particles.len() as u64 > self.world_size as u64 {
+            return Err(SandError::Config(format!(
+                "the scene has {} particles but --world-size is {}",
+                scene.particles.len(),
+                self.world_size
+            )));
+        }
+        Ok(scene)
+    }
+
+    /// The rules from `--reactions`, or the built in ones.
+    pub fn reaction_table(&self) -> Result<ReactionTable> {
+        ReactionTable::load_or_default(self.reactions.as_deref())
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| SandError::Config(format!("{flag} {value:?} isn't a valid number")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vulkano::device::physical::PhysicalDeviceType;
+
+    fn parse(args: &[&str]) -> Result<Command> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn options(args: &[&str]) -> Options {
+        match parse(args) {
+            Ok(Command::Run(options)) => options,
+            Ok(Command::Help) => panic!("{args:?} asked for help"),
+            Err(e) => panic!("{args:?} failed with {e}"),
+        }
+    }
+
+    fn rejects(args: &[&str], expected: &str) {
+        match parse(args) {
+            Err(SandError::Config(message)) => {
+                assert!(message.contains(expected), "{message:?} doesn't mention {expected:?}")
+            }
+            Err(e) => panic!("{args:?} failed with {e}"),
+            Ok(command) => panic!("{args:?} parsed to {command:?}"),
+        }
+    }
+
+    #[test]
+    fn no_flags_gives_the_defaults() {
+        let options = options(&[]);
+        assert_eq!(options.world_size, DEFAULT_WORLD_SIZE);
+        assert_eq!(options.work_groups, DEFAULT_WORLD_SIZE / WORK_GROUP_SIZE);
+        assert_eq!(options.seed, DEFAULT_SEED);
+        assert!(!options.deterministic);
+        assert_eq!(options.headless_ticks, None);
+        assert_eq!(options.scene, None);
+        assert!(!options.device.list_devices);
+        assert_eq!(options.window.present_preference, PresentPreference::LowLatency);
+    }
+
+    #[test]
+    fn help_stops_parsing() {
+        let helps: [&[&str]; 3] = [&["-h"], &["--help"], &["--seed", "1", "--help", "--bogus"]];
+        for args in helps {
+            assert!(matches!(parse(args), Ok(Command::Help)), "{args:?}");
+        }
+    }
+
+    #[test]
+    fn world_flags_are_parsed() {
+        let options = options(&[
+            "--world-size",
+            "100",
+            "--work-groups=4",
+            "--scene",
+            "level.txt",
+            "--reactions",
+            "reactions.toml",
+            "--seed",
+            "18446744073709551615",
+            "--deterministic",
+            "--config",
+            "sand.toml",
+        ]);
+        assert_eq!(options.world_size, 100);
+        assert_eq!(options.work_groups, 4);
+        assert_eq!(options.scene, Some(PathBuf::from("level.txt")));
+        assert_eq!(options.reactions, Some(PathBuf::from("reactions.toml")));
+        assert_eq!(options.seed, u64::MAX);
+        assert!(options.deterministic);
+        assert_eq!(options.window.config, Some(PathBuf::from("sand.toml")));
+    }
+
+    #[test]
+    fn running_flags_are_parsed() {
+        let options = options(&[
+            "--headless",
+            "600",
+            "--output=out.txt",
+            "--checksums",
+            "checksums.txt",
+            "--record",
+            "run.txt",
+            "--replay",
+            "old.txt",
+            "--present",
+            "vsync",
+            "--hdr",
+            "--no-fps",
+            "--frame-csv",
+            "frames.csv",
+            "--log-file",
+            "sand.log",
+        ]);
+        assert_eq!(options.headless_ticks, Some(600));
+        assert_eq!(options.window.world_output, Some(PathBuf::from("out.txt")));
+        assert_eq!(options.checksums, Some(PathBuf::from("checksums.txt")));
+        assert_eq!(options.window.record, Some(PathBuf::from("run.txt")));
+        assert_eq!(options.window.replay, Some(PathBuf::from("old.txt")));
+        assert_eq!(options.window.present_preference, PresentPreference::Vsync);
+        assert!(options.window.prefer_hdr);
+        assert!(!options.window.fps_display);
+        assert!(!options.window.gpu_timings_display);
+        assert_eq!(options.window.frame_csv, Some(PathBuf::from("frames.csv")));
+        assert_eq!(options.log_file, Some(PathBuf::from("sand.log")));
+    }
+
+    #[test]
+    fn gpu_flags_are_parsed() {
+        let options = options(&["--gpu", "integrated", "--list-devices", "--validation"]);
+        assert_eq!(
+            options.device.selector,
+            DeviceSelector::Type(PhysicalDeviceType::IntegratedGpu)
+        );
+        assert!(options.device.list_devices);
+        assert!(options.debug.validation);
+    }
+
+    #[test]
+    fn the_last_of_a_repeated_flag_wins() {
+        let options = options(&["--gpu", "1", "--gpu=radeon", "--seed", "1", "--seed", "2"]);
+        assert_eq!(options.device.selector, DeviceSelector::Name("radeon".to_string()));
+        assert_eq!(options.seed, 2);
+    }
+
+    #[test]
+    fn flags_without_their_value_are_rejected() {
+        for flag in [
+            "--world-size",
+            "--work-groups",
+            "--scene",
+            "--reactions",
+            "--seed",
+            "--checksums",
+            "--headless",
+            "--config",
+            "--record",
+            "--replay",
+            "--output",
+            "--present",
+            "--frame-csv",
+            "--gpu",
+            "--log-file",
+        ] {
+            rejects(&[flag], &format!("{flag} needs a value"));
+        }
+    }
+
+    #[test]
+    fn bad_numbers_are_rejected() {
+        rejects(&["--world-size", "lots"], "--world-size \"lots\" isn't a valid number");
+        rejects(&["--world-size", "-1"], "--world-size \"-1\" isn't a valid number");
+        rejects(&["--work-groups=1.5"], "--work-groups \"1.5\" isn't a valid number");
+        rejects(&["--seed", "0x5eed"], "--seed \"0x5eed\" isn't a valid number");
+        rejects(&["--seed", "18446744073709551616"], "isn't a valid number");
+        rejects(&["--headless", ""], "--headless \"\" isn't a valid number");
+    }
+
+    #[test]
+    fn unknown_flags_and_modes_are_rejected() {
+        rejects(&["--wordl-size", "10"], "unknown option \"--wordl-size\"");
+        rejects(&["world"], "unknown option \"world\"");
+        rejects(&["--present", "fast"], "--present \"fast\" isn't vsync, low-latency or uncapped");
+    }
+
+    #[test]
+    fn sizes_out_of_range_are_rejected() {
+        rejects(&["--world-size", "0"], "--world-size has to be at least 1");
+        rejects(&["--work-groups", "0"], "--work-groups has to be at least 1");
+        rejects(
+            &["--world-size", "129", "--work-groups", "2"],
+            "--work-groups 2 only runs 128 particles but --world-size is 129",
+        );
+        // a partly used last work group is fine
+        let options = options(&["--world-size", "129"]);
+        assert_eq!(options.work_groups, 3);
+        assert_eq!(options.capacity(), 192);
+    }
+}
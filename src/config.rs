@@ -0,0 +1,268 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, SandError};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Knobs that would otherwise be glsl literals, see sand.toml for what each one does.
+/// Anything left out of the file keeps its default.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub simulation: SimulationConfig,
+    pub render: RenderConfig,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+    pub gravity: [f32; 2], // pixels/s², +y is down the screen
+    pub damping: f32,      // fraction of the velocity kept every tick
+    pub timestep: f32,     // seconds per tick
+    pub jitter: f32,       // pixels/s of random velocity added every tick, from the seeded rng
+    pub heat_radius: f32,  // pixels, particles closer than this exchange heat
+    pub reaction_radius: f32, // pixels, particles closer than this can react, see reactions.toml
+    pub sph_radius: f32,   // pixels, the smoothing length of fluid kinds, see materials.rs
+    pub sleep_speed: f32,  // pixels/s, particles slower than this count as still
+    pub sleep_ticks: u32,  // still this many ticks in a row and a particle sleeps until something near it moves, 0 never
+}
+
+impl Default for SimulationConfig {
+    fn default() -> SimulationConfig {
+        SimulationConfig {
+            gravity: [0f32, 0f32], // there's no floor yet, so nothing falls by default
+            damping: 1f32,
+            timestep: 1f32 / 60f32,
+            jitter: 0f32,
+            heat_radius: 4f32,
+            reaction_radius: 3f32,
+            sph_radius: 8f32,
+            sleep_speed: 10f32,
+            sleep_ticks: 30,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderConfig {
+    pub particle_radius: f32, // pixels
+    pub colour_scale: [f32; 3], // multiplies every particle's colour, in srgb
+    pub show_geometry: bool,    // draw the scene's collision geometry over everything, G toggles it
+}
+
+impl Default for RenderConfig {
+    fn default() -> RenderConfig {
+        RenderConfig {
+            particle_radius: 2f32,
+            colour_scale: [1f32, 1f32, 1f32],
+            show_geometry: false,
+        }
+    }
+}
+
+impl Config {
+    /// Parses and validates, the error has every problem with the file not just the first.
+    pub fn load(path: &Path) -> Result<Config> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| SandError::Config(format!("couldn't read config {}: {e}", path.display())))?;
+        let config: Config = toml::from_str(&text)
+            .map_err(|e| SandError::Config(format!("{}: {e}", path.display())))?;
+        let problems = config.problems();
+        if !problems.is_empty() {
+            return Err(SandError::Config(format!(
+                "{}:\n  {}",
+                path.display(),
+                problems.join("\n  ")
+            )));
+        }
+        Ok(config)
+    }
+
+    /// A broken config at startup shouldn't stop the run, it's reported and the defaults are used.
+    pub fn load_or_default(path: Option<&Path>) -> Config {
+        let Some(path) = path else {
+            return Config::default();
+        };
+        match Config::load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                log::error!("{e}\nusing the default config");
+                Config::default()
+            }
+        }
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let simulation = &self.simulation;
+        if !simulation.gravity.iter().all(|g| g.is_finite()) {
+            problems.push(format!("simulation.gravity {:?} has to be finite", simulation.gravity));
+        }
+        if !(0f32..=1f32).contains(&simulation.damping) {
+            problems.push(format!("simulation.damping {} has to be between 0 and 1", simulation.damping));
+        }
+        if !(simulation.timestep > 0f32 && simulation.timestep.is_finite()) {
+            problems.push(format!("simulation.timestep {} has to be above 0", simulation.timestep));
+        }
+        if !(simulation.jitter >= 0f32 && simulation.jitter.is_finite()) {
+            problems.push(format!("simulation.jitter {} can't be negative", simulation.jitter));
+        }
+        if !(simulation.heat_radius > 0f32 && simulation.heat_radius.is_finite()) {
+            problems.push(format!("simulation.heat_radius {} has to be above 0", simulation.heat_radius));
+        }
+        if !(simulation.reaction_radius > 0f32 && simulation.reaction_radius.is_finite()) {
+            problems.push(format!("simulation.reaction_radius {} has to be above 0", simulation.reaction_radius));
+        }
+        if !(simulation.sph_radius > 0f32 && simulation.sph_radius.is_finite()) {
+            problems.push(format!("simulation.sph_radius {} has to be above 0", simulation.sph_radius));
+        }
+        if !(simulation.sleep_speed >= 0f32 && simulation.sleep_speed.is_finite()) {
+            problems.push(format!("simulation.sleep_speed {} can't be negative", simulation.sleep_speed));
+        }
+        let render = &self.render;
+        if !(render.particle_radius > 0f32 && render.particle_radius.is_finite()) {
+            problems.push(format!("render.particle_radius {} has to be above 0", render.particle_radius));
+        }
+        if !render.colour_scale.iter().all(|c| c.is_finite() && *c >= 0f32) {
+            problems.push(format!("render.colour_scale {:?} can't be negative", render.colour_scale));
+        }
+        problems
+    }
+}
+
+/// Polls the config file's modification time, cheap enough to call every frame.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_poll: Instant,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf) -> ConfigWatcher {
+        let modified = modified(&path);
+        ConfigWatcher {
+            path,
+            modified,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Some when the file changed and is valid, a broken edit is printed and the caller keeps what it has.
+    pub fn poll(&mut self) -> Option<Config> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        match Config::load(&self.path) {
+            Ok(config) => {
+                log::info!("reloaded {}", self.path.display());
+                Some(config)
+            }
+            Err(e) => {
+                log::error!("{e}\nkeeping the previous config");
+                None
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(edit: impl FnOnce(&mut Config)) -> Vec<String> {
+        let mut config = Config::default();
+        edit(&mut config);
+        config.problems()
+    }
+
+    // exactly one problem, naming `field`
+    fn rejects(field: &str, edit: impl FnOnce(&mut Config)) {
+        let problems = problems(edit);
+        assert_eq!(problems.len(), 1, "{problems:?}");
+        assert!(problems[0].starts_with(field), "{problems:?}");
+    }
+
+    #[test]
+    fn the_defaults_are_valid() {
+        assert_eq!(Config::default().problems(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn sand_toml_parses() {
+        let config: Config = toml::from_str(include_str!("../sand.toml")).unwrap();
+        assert_eq!(config.problems(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn every_invalid_simulation_field_is_reported() {
+        rejects("simulation.gravity", |c| c.simulation.gravity = [0f32, f32::INFINITY]);
+        rejects("simulation.gravity", |c| c.simulation.gravity = [f32::NAN, 0f32]);
+        rejects("simulation.damping", |c| c.simulation.damping = 1.5f32);
+        rejects("simulation.damping", |c| c.simulation.damping = -0.1f32);
+        rejects("simulation.damping", |c| c.simulation.damping = f32::NAN);
+        rejects("simulation.timestep", |c| c.simulation.timestep = 0f32);
+        rejects("simulation.timestep", |c| c.simulation.timestep = f32::INFINITY);
+        rejects("simulation.jitter", |c| c.simulation.jitter = -1f32);
+        rejects("simulation.jitter", |c| c.simulation.jitter = f32::NAN);
+        rejects("simulation.heat_radius", |c| c.simulation.heat_radius = 0f32);
+        rejects("simulation.reaction_radius", |c| c.simulation.reaction_radius = -3f32);
+        rejects("simulation.sph_radius", |c| c.simulation.sph_radius = f32::NAN);
+        rejects("simulation.sleep_speed", |c| c.simulation.sleep_speed = -1f32);
+    }
+
+    #[test]
+    fn every_invalid_render_field_is_reported() {
+        rejects("render.particle_radius", |c| c.render.particle_radius = 0f32);
+        rejects("render.particle_radius", |c| c.render.particle_radius = f32::INFINITY);
+        rejects("render.colour_scale", |c| c.render.colour_scale = [1f32, -1f32, 1f32]);
+        rejects("render.colour_scale", |c| c.render.colour_scale = [1f32, 1f32, f32::NAN]);
+    }
+
+    #[test]
+    fn edge_values_are_valid() {
+        assert!(problems(|c| c.simulation.damping = 0f32).is_empty());
+        assert!(problems(|c| c.simulation.damping = 1f32).is_empty());
+        assert!(problems(|c| c.simulation.jitter = 0f32).is_empty());
+        assert!(problems(|c| c.simulation.sleep_speed = 0f32).is_empty());
+        assert!(problems(|c| c.render.colour_scale = [0f32; 3]).is_empty());
+    }
+
+    #[test]
+    fn every_problem_is_reported_not_just_the_first() {
+        let problems = problems(|c| {
+            c.simulation.timestep = 0f32;
+            c.render.particle_radius = -1f32;
+        });
+        assert_eq!(problems.len(), 2, "{problems:?}");
+    }
+
+    #[test]
+    fn misspelled_keys_are_rejected() {
+        let error = toml::from_str::<Config>("[simulation]\ngravty = [0.0, 10.0]\n").unwrap_err();
+        assert!(error.to_string().contains("gravty"), "{error}");
+        let error = toml::from_str::<Config>("[rendr]\nparticle_radius = 2.0\n").unwrap_err();
+        assert!(error.to_string().contains("rendr"), "{error}");
+    }
+
+    #[test]
+    fn left_out_fields_keep_their_defaults() {
+        let config: Config = toml::from_str("[simulation]\ndamping = 0.5\n").unwrap();
+        assert_eq!(config.simulation.damping, 0.5f32);
+        assert_eq!(config.simulation.timestep, SimulationConfig::default().timestep);
+        assert_eq!(config.render, RenderConfig::default());
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use vulkano::device::DeviceOwned;
+use vulkano::instance::debug::{
+    DebugUtilsMessageSeverity, DebugUtilsMessageType, DebugUtilsMessenger,
+    DebugUtilsMessengerCreateInfo, Message,
+};
+use vulkano::instance::{Instance, InstanceExtensions};
+use vulkano::{VulkanLibrary, VulkanObject};
+
+pub const VALIDATION_ENV_VAR: &str = "SAND_VALIDATION";
+const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DebugOptions {
+    pub validation: bool, // only honoured in debug builds as the layer is slow
+}
+
+impl DebugOptions {
+    /// `SAND_VALIDATION=1`, `cli::Options::parse` adds `--validation`.
+    pub fn from_env() -> DebugOptions {
+        DebugOptions {
+            validation: std::env::var(VALIDATION_ENV_VAR).map_or(false, |value| value != "0"),
+        }
+    }
+}
+
+/// Adds whatever validation needs to the instance, returns the layers to enable.
+/// Missing pieces are reported and skipped so a machine without the sdk can still run.
+pub fn enable_validation(
+    library: &Arc<VulkanLibrary>,
+    options: DebugOptions,
+    extensions: &mut InstanceExtensions,
+) -> Vec<String> {
+    if !options.validation {
+        return Vec::new();
+    }
+    if !cfg!(debug_assertions) {
+        log::warn!("validation was requested but is only available in debug builds");
+        return Vec::new();
+    }
+    extensions.ext_debug_utils = library.supported_extensions().ext_debug_utils;
+    if !extensions.ext_debug_utils {
+        log::warn!("ext_debug_utils isn't supported, validation messages won't be reported");
+    }
+    let has_layer = library
+        .layer_properties()
+        .map(|mut layers| layers.any(|layer| layer.name() == VALIDATION_LAYER))
+        .unwrap_or(false);
+    if has_layer {
+        vec![VALIDATION_LAYER.to_owned()]
+    } else {
+        log::warn!("{VALIDATION_LAYER} isn't installed, is the vulkan sdk there?");
+        Vec::new()
+    }
+}
+
+/// Keep the messenger alive for as long as messages should be reported.
+pub fn create_messenger(instance: &Arc<Instance>) -> Option<DebugUtilsMessenger> {
+    if !instance.enabled_extensions().ext_debug_utils {
+        return None;
+    }
+    // the callback must not call into vulkan, it only prints
+    let messenger = unsafe {
+        DebugUtilsMessenger::new(
+            instance.clone(),
+            DebugUtilsMessengerCreateInfo {
+                message_severity: DebugUtilsMessageSeverity::ERROR
+                    | DebugUtilsMessageSeverity::WARNING
+                    | DebugUtilsMessageSeverity::INFO,
+                message_type: DebugUtilsMessageType::GENERAL
+                    | DebugUtilsMessageType::VALIDATION
+                    | DebugUtilsMessageType::PERFORMANCE,
+                ..DebugUtilsMessengerCreateInfo::user_callback(Arc::new(report))
+            },
+        )
+    };
+    match messenger {
+        Ok(messenger) => Some(messenger),
+        Err(e) => {
+            log::warn!("failed to create debug messenger: {e}");
+            None
+        }
+    }
+}
+
+// logged under the "vulkan" target so RUST_LOG=vulkan=... filters it apart from our own messages
+fn report(msg: &Message) {
+    let level = if msg.severity.intersects(DebugUtilsMessageSeverity::ERROR) {
+        log::Level::Error
+    } else if msg.severity.intersects(DebugUtilsMessageSeverity::WARNING) {
+        log::Level::Warn
+    } else {
+        log::Level::Debug
+    };
+    let layer = msg.layer_prefix.unwrap_or("vulkan");
+    log::log!(target: "vulkan", level, "[{layer}] {}", msg.description);
+}
+
+/// Names an object in validation messages and debuggers like renderdoc, does nothing without ext_debug_utils.
+pub fn name<T: VulkanObject + DeviceOwned>(object: &T, name: &str) {
+    let device = object.device();
+    if !device.instance().enabled_extensions().ext_debug_utils {
+        return;
+    }
+    if let Err(e) = device.set_debug_utils_object_name(object, Some(name)) {
+        log::debug!("failed to name {name}: {e}");
+    }
+}
@@ -1,87 +1,138 @@
-use crate::sync::future::FenceSignalFuture;
-use crate::sync::future::NowFuture;
+use crate::debug;
+use crate::error::{Result, SandError};
+use crate::profiler::{GpuProfiler, Stage};
 use std::sync::Arc;
 use vulkano::buffer::Subbuffer;
 use vulkano::command_buffer::PrimaryAutoCommandBuffer;
 use vulkano::command_buffer::allocator::{
     StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
 };
-use vulkano::command_buffer::CommandBufferExecFuture;
+use vulkano::command_buffer::{CommandBufferExecFuture, DispatchIndirectCommand};
 use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage};
 use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
 use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
 use vulkano::device::{Device, Queue};
 use vulkano::pipeline::{ComputePipeline, Pipeline, PipelineBindPoint};
 use vulkano::shader::ShaderModule;
-use vulkano::sync::{self, GpuFuture};
+use vulkano::sync::future::SemaphoreSignalFuture;
+use vulkano::sync::GpuFuture;
 
-pub fn deploy(
-    device: Arc<Device>,
+/// How many invocations a tick stage gets.
+#[derive(Clone)]
+pub enum Dispatch {
+    /// `work_group_counts`, one invocation per particle slot.
+    All,
+    /// However many work groups an earlier stage of the same tick wrote here.
+    Indirect(Subbuffer<[DispatchIndirectCommand]>),
+    /// Always this many work groups, for stages that run over something other than the particles.
+    Groups([u32; 3]),
+}
+
+// anything chained after this waits on the semaphore, so it sees the finished dispatch
+pub fn deploy<F: GpuFuture>(
+    after: F,
     queue: Arc<Queue>,
     command: Arc<PrimaryAutoCommandBuffer>,
-) -> FenceSignalFuture<CommandBufferExecFuture<NowFuture>> {
-
-    sync::now(device)
+) -> Result<SemaphoreSignalFuture<CommandBufferExecFuture<F>>> {
+    Ok(after
         .then_execute(queue, command)
-        .unwrap()
-        .then_signal_fence_and_flush()
-        .unwrap()
+        .map_err(|e| SandError::submission("sand tick", e))?
+        .then_signal_semaphore())
 }
 
-pub fn get_deploy_command<T>(
-    shader: &Arc<ShaderModule>,
+// one command per frame slot, a MultipleSubmit buffer can't be resubmitted while the last frame is still using it.
+// each slot gets its own tick state so the cpu can write the next tick number without waiting on the others.
+// `stages` are dispatched in order in the same command buffer. `bindings` are (binding, buffer) and the same in
+// every slot, the tick state goes to binding 2. each stage gets whichever of them its shader uses
+pub fn get_deploy_commands<S: ?Sized>(
+    stages: &[(&str, Arc<ShaderModule>, Dispatch)],
     device: &Arc<Device>,
     queue: &Arc<Queue>,
-    buffer: &Subbuffer<[T]>,
+    bindings: &[(u32, Subbuffer<[u8]>)],
+    tick_states: &[Subbuffer<S>],
     work_group_counts: [u32; 3],
-) -> vulkano::command_buffer::PrimaryAutoCommandBuffer {
-    let compute_pipeline = ComputePipeline::new(
-        device.clone(),
-        shader.entry_point("main").unwrap(),
-        &(),
-        None,
-        |_| {},
-    )
-    .expect("failed to create compute pipeline");
+    profiler: Option<&GpuProfiler>,
+) -> Result<Vec<Arc<PrimaryAutoCommandBuffer>>> {
+    let pipelines = stages
+        .iter()
+        .map(|(name, shader, _)| {
+            let entry_point = shader
+                .entry_point("main")
+                .ok_or_else(|| SandError::pipeline("sand tick pipeline", format!("{name} has no main")))?;
+            let compute_pipeline = ComputePipeline::new(device.clone(), entry_point, &(), None, |_| {})
+                .map_err(|e| SandError::pipeline("sand tick pipeline", e))?;
+            debug::name(compute_pipeline.as_ref(), &format!("sand {name} pipeline"));
+            Ok(compute_pipeline)
+        })
+        .collect::<Result<Vec<_>>>()?;
 
     let descriptor_set_allocator = StandardDescriptorSetAllocator::new(device.clone());
-    let pipeline_layout = compute_pipeline.layout();
-    let descriptor_set_layouts = pipeline_layout.set_layouts();
     let descriptor_set_layout_index = 0;
-    let descriptor_set_layout = descriptor_set_layouts
-        .get(descriptor_set_layout_index)
-        .unwrap();
-
-    let descriptor_set = match PersistentDescriptorSet::new(
-        &descriptor_set_allocator,
-        descriptor_set_layout.clone(),
-        [WriteDescriptorSet::buffer(0, buffer.clone())], // 0 is the binding
-    ) {
-        Ok(res) => res,
-        Err(e) => panic!("Error with {e:?}"),
-    };
 
     let command_buffer_allocator = StandardCommandBufferAllocator::new(
         device.clone(),
         StandardCommandBufferAllocatorCreateInfo::default(),
     );
-    let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
-        &command_buffer_allocator,
-        queue.queue_family_index(),
-        CommandBufferUsage::MultipleSubmit,
-    )
-    .unwrap();
+    tick_states
+        .iter()
+        .enumerate()
+        .map(|(slot, tick_state)| {
+            let slot = slot as u32;
+            let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+                &command_buffer_allocator,
+                queue.queue_family_index(),
+                CommandBufferUsage::MultipleSubmit,
+            )
+            .map_err(|e| SandError::command("sand tick", e))?;
 
-    command_buffer_builder
-        .bind_pipeline_compute(compute_pipeline.clone())
-        .bind_descriptor_sets(
-            PipelineBindPoint::Compute,
-            compute_pipeline.layout().clone(),
-            descriptor_set_layout_index as u32,
-            descriptor_set,
-        )
-        .dispatch(work_group_counts)
-        .unwrap();
+            if let Some(profiler) = profiler {
+                profiler.begin(&mut command_buffer_builder, Stage::Tick, slot)?;
+            }
+            // the builder puts barriers between the dispatches, so each stage sees the last one's writes, indirect counts too
+            for (compute_pipeline, (_, _, dispatch)) in pipelines.iter().zip(stages) {
+                let descriptor_set_layout = compute_pipeline
+                    .layout()
+                    .set_layouts()
+                    .get(descriptor_set_layout_index)
+                    .ok_or_else(|| SandError::pipeline("sand tick descriptor set", "shader has no set 0"))?;
+                // the layout only has the bindings the stage actually reads
+                let writes = bindings
+                    .iter()
+                    .map(|(binding, buffer)| WriteDescriptorSet::buffer(*binding, buffer.clone()))
+                    .chain([WriteDescriptorSet::buffer(2, tick_state.clone())])
+                    .filter(|write| descriptor_set_layout.bindings().contains_key(&write.binding()));
+                let descriptor_set = PersistentDescriptorSet::new(
+                    &descriptor_set_allocator,
+                    descriptor_set_layout.clone(),
+                    writes,
+                )
+                .map_err(|e| SandError::pipeline("sand tick descriptor set", e))?;
+                command_buffer_builder
+                    .bind_pipeline_compute(compute_pipeline.clone())
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Compute,
+                        compute_pipeline.layout().clone(),
+                        descriptor_set_layout_index as u32,
+                        descriptor_set,
+                    );
+                let dispatched = match dispatch {
+                    Dispatch::All => command_buffer_builder.dispatch(work_group_counts),
+                    Dispatch::Indirect(commands) => command_buffer_builder.dispatch_indirect(commands.clone()),
+                    Dispatch::Groups(groups) => command_buffer_builder.dispatch(*groups),
+                };
+                dispatched.map_err(|e| SandError::command("sand tick", e))?;
+            }
+            if let Some(profiler) = profiler {
+                profiler.end(&mut command_buffer_builder, Stage::Tick, slot)?;
+            }
 
-    command_buffer_builder.build().unwrap()
+            let command_buffer = Arc::new(
+                command_buffer_builder
+                    .build()
+                    .map_err(|e| SandError::command("sand tick", e))?,
+            );
+            debug::name(command_buffer.as_ref(), &format!("sand tick {slot}"));
+            Ok(command_buffer)
+        })
+        .collect()
 }
@@ -0,0 +1,296 @@
+use std::fmt::Write;
+use std::sync::Arc;
+
+use vulkano::device::physical::{PhysicalDevice, PhysicalDeviceType};
+use vulkano::device::{DeviceExtensions, QueueFlags};
+use vulkano::instance::Instance;
+use vulkano::swapchain::Surface;
+use vulkano::Version;
+
+use crate::error::{Result, SandError};
+
+pub const GPU_ENV_VAR: &str = "SAND_GPU";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceSelector {
+    Auto,                     // best device type that works
+    Index(usize),             // position in --list-devices
+    Name(String),             // case insensitive substring of the device name
+    Type(PhysicalDeviceType), // first working device of this type
+}
+
+impl DeviceSelector {
+    pub fn parse(value: &str) -> DeviceSelector {
+        if let Ok(index) = value.parse() {
+            return DeviceSelector::Index(index);
+        }
+        match value.to_ascii_lowercase().as_str() {
+            "auto" => DeviceSelector::Auto,
+            "discrete" => DeviceSelector::Type(PhysicalDeviceType::DiscreteGpu),
+            "integrated" => DeviceSelector::Type(PhysicalDeviceType::IntegratedGpu),
+            "virtual" => DeviceSelector::Type(PhysicalDeviceType::VirtualGpu),
+            "cpu" => DeviceSelector::Type(PhysicalDeviceType::Cpu),
+            _ => DeviceSelector::Name(value.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceSelection {
+    pub selector: DeviceSelector,
+    pub list_devices: bool, // print every device and whether it's usable, then exit
+}
+
+impl DeviceSelection {
+    /// Only `SAND_GPU`, `cli::Options::parse` applies `--gpu` and `--list-devices` on top.
+    pub fn from_env() -> DeviceSelection {
+        DeviceSelection {
+            selector: match std::env::var(GPU_ENV_VAR) {
+                Ok(value) => DeviceSelector::parse(&value),
+                Err(_) => DeviceSelector::Auto,
+            },
+            list_devices: false,
+        }
+    }
+}
+
+/// `D` is the device `select` hands back, only tests use anything but the physical device.
+pub struct Candidate<D = Arc<PhysicalDevice>> {
+    pub index: usize,
+    pub name: String,
+    pub device_type: PhysicalDeviceType,
+    pub api_version: Version,
+    pub device: D,
+    pub queue_family_index: std::result::Result<u32, String>, // Err is why the device can't be used
+}
+
+impl<D> Candidate<D> {
+    pub fn describe(&self) -> String {
+        let mut line = format!(
+            "[{}] {} ({:?}, vulkan {}.{})",
+            self.index, self.name, self.device_type, self.api_version.major, self.api_version.minor,
+        );
+        if let Err(reason) = &self.queue_family_index {
+            write!(line, " - unusable: {reason}").unwrap();
+        }
+        line
+    }
+
+    fn matches(&self, selector: &DeviceSelector) -> bool {
+        match selector {
+            DeviceSelector::Auto => true,
+            DeviceSelector::Index(index) => self.index == *index,
+            DeviceSelector::Name(name) => self
+                .name
+                .to_ascii_lowercase()
+                .contains(&name.to_ascii_lowercase()),
+            DeviceSelector::Type(device_type) => self.device_type == *device_type,
+        }
+    }
+}
+
+/// Without a surface (headless) any compute family will do.
+pub fn candidates(
+    instance: &Arc<Instance>,
+    surface: Option<&Surface>,
+    device_extensions: &DeviceExtensions,
+) -> Result<Vec<Candidate>> {
+    let devices = instance
+        .enumerate_physical_devices()
+        .map_err(|e| SandError::DeviceSelection(format!("failed to get devices: {e}")))?;
+    Ok(devices
+        .enumerate()
+        .map(|(index, device)| {
+            let queue_family_index = usable_queue_family(&device, surface, device_extensions);
+            let properties = device.properties();
+            Candidate {
+                index,
+                name: properties.device_name.clone(),
+                device_type: properties.device_type,
+                api_version: properties.api_version,
+                device,
+                queue_family_index,
+            }
+        })
+        .collect())
+}
+
+fn usable_queue_family(
+    device: &Arc<PhysicalDevice>,
+    surface: Option<&Surface>,
+    device_extensions: &DeviceExtensions,
+) -> std::result::Result<u32, String> {
+    if !device.supported_extensions().contains(device_extensions) {
+        return Err("missing required device extensions (swapchain)".to_string());
+    }
+    let Some(surface) = surface else {
+        return device
+            .queue_family_properties()
+            .iter()
+            .position(|q| q.queue_flags.contains(QueueFlags::COMPUTE))
+            .map(|q| q as u32)
+            .ok_or_else(|| "no compute queue family".to_string());
+    };
+    device
+        .queue_family_properties()
+        .iter()
+        .enumerate()
+        .position(|(i, q)| {
+            q.queue_flags.contains(QueueFlags::GRAPHICS)
+                && device.surface_support(i as u32, surface).unwrap_or(false)
+        })
+        .map(|q| q as u32)
+        .ok_or_else(|| "no graphics queue family that can present to the window".to_string())
+}
+
+fn type_rank(device_type: PhysicalDeviceType) -> u32 {
+    match device_type {
+        PhysicalDeviceType::DiscreteGpu => 0,
+        PhysicalDeviceType::IntegratedGpu => 1,
+        PhysicalDeviceType::VirtualGpu => 2,
+        PhysicalDeviceType::Cpu => 3,
+        _ => 4,
+    }
+}
+
+/// Picks the best usable device matching `selector`, the error lists every candidate so the user can pick another.
+pub fn select<D: Clone>(candidates: &[Candidate<D>], selector: &DeviceSelector) -> Result<(D, u32)> {
+    let chosen = candidates
+        .iter()
+        .filter(|candidate| candidate.matches(selector))
+        .filter_map(|candidate| {
+            candidate
+                .queue_family_index
+                .as_ref()
+                .ok()
+                .map(|&queue_family_index| (candidate, queue_family_index))
+        })
+        .min_by_key(|(candidate, _)| type_rank(candidate.device_type));
+
+    match chosen {
+        Some((candidate, queue_family_index)) => {
+            Ok((candidate.device.clone(), queue_family_index))
+        }
+        None => {
+            let mut message = format!("no usable device matches {selector:?}, candidates are:");
+            for candidate in candidates {
+                write!(message, "\n  {}", candidate.describe()).unwrap();
+            }
+            write!(
+                message,
+                "\npick one with --gpu <index|name|discrete|integrated|virtual|cpu> or {GPU_ENV_VAR}"
+            )
+            .unwrap();
+            Err(SandError::DeviceSelection(message))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(
+        index: usize,
+        name: &str,
+        device_type: PhysicalDeviceType,
+        queue_family_index: std::result::Result<u32, String>,
+    ) -> Candidate<usize> {
+        Candidate {
+            index,
+            name: name.to_string(),
+            device_type,
+            api_version: Version::V1_3,
+            device: index,
+            queue_family_index,
+        }
+    }
+
+    fn candidates() -> Vec<Candidate<usize>> {
+        vec![
+            candidate(0, "llvmpipe (LLVM 15.0.7, 256 bits)", PhysicalDeviceType::Cpu, Ok(0)),
+            candidate(1, "Intel(R) UHD Graphics 630", PhysicalDeviceType::IntegratedGpu, Ok(1)),
+            candidate(2, "NVIDIA GeForce RTX 3080", PhysicalDeviceType::DiscreteGpu, Ok(2)),
+            candidate(
+                3,
+                "AMD Radeon Pro W6800",
+                PhysicalDeviceType::DiscreteGpu,
+                Err("missing required device extensions (swapchain)".to_string()),
+            ),
+        ]
+    }
+
+    fn selected(selector: DeviceSelector) -> (usize, u32) {
+        select(&candidates(), &selector).unwrap()
+    }
+
+    fn no_match(selector: DeviceSelector) -> String {
+        match select(&candidates(), &selector) {
+            Err(SandError::DeviceSelection(message)) => message,
+            other => panic!("{selector:?} gave {:?}", other.map(|(device, _)| device)),
+        }
+    }
+
+    #[test]
+    fn selectors_are_parsed() {
+        assert_eq!(DeviceSelector::parse("2"), DeviceSelector::Index(2));
+        assert_eq!(DeviceSelector::parse("auto"), DeviceSelector::Auto);
+        assert_eq!(DeviceSelector::parse("AUTO"), DeviceSelector::Auto);
+        assert_eq!(
+            DeviceSelector::parse("Discrete"),
+            DeviceSelector::Type(PhysicalDeviceType::DiscreteGpu)
+        );
+        assert_eq!(
+            DeviceSelector::parse("integrated"),
+            DeviceSelector::Type(PhysicalDeviceType::IntegratedGpu)
+        );
+        assert_eq!(
+            DeviceSelector::parse("virtual"),
+            DeviceSelector::Type(PhysicalDeviceType::VirtualGpu)
+        );
+        assert_eq!(DeviceSelector::parse("CPU"), DeviceSelector::Type(PhysicalDeviceType::Cpu));
+        assert_eq!(DeviceSelector::parse("GeForce"), DeviceSelector::Name("GeForce".to_string()));
+        assert_eq!(DeviceSelector::parse("-1"), DeviceSelector::Name("-1".to_string()));
+    }
+
+    #[test]
+    fn auto_picks_the_best_usable_type() {
+        assert_eq!(selected(DeviceSelector::Auto), (2, 2));
+    }
+
+    #[test]
+    fn index_picks_that_device() {
+        assert_eq!(selected(DeviceSelector::Index(1)), (1, 1));
+        assert_eq!(selected(DeviceSelector::Index(0)), (0, 0));
+    }
+
+    #[test]
+    fn name_is_a_case_insensitive_substring() {
+        assert_eq!(selected(DeviceSelector::Name("intel".to_string())), (1, 1));
+        assert_eq!(selected(DeviceSelector::Name("RTX".to_string())), (2, 2));
+    }
+
+    #[test]
+    fn type_picks_a_usable_device_of_that_type() {
+        assert_eq!(selected(DeviceSelector::Type(PhysicalDeviceType::Cpu)), (0, 0));
+        assert_eq!(selected(DeviceSelector::Type(PhysicalDeviceType::DiscreteGpu)), (2, 2));
+    }
+
+    #[test]
+    fn unusable_and_missing_devices_are_errors() {
+        no_match(DeviceSelector::Index(3));
+        no_match(DeviceSelector::Index(9));
+        no_match(DeviceSelector::Name("radeon".to_string()));
+    }
+
+    #[test]
+    fn no_match_lists_every_candidate() {
+        let message = no_match(DeviceSelector::Type(PhysicalDeviceType::VirtualGpu));
+        for candidate in candidates() {
+            assert!(message.contains(&candidate.describe()), "{message}");
+        }
+        assert!(message.contains("unusable: missing required device extensions"), "{message}");
+        assert!(message.contains("--gpu"), "{message}");
+        assert!(message.contains(GPU_ENV_VAR), "{message}");
+    }
+}
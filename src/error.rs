@@ -0,0 +1,93 @@
+use std::fmt;
+
+/// Everything that can go wrong setting up or running the gpu side, each variant says which stage failed
+/// and `what` names the object so the message makes sense without a backtrace.
+#[derive(Debug)]
+pub enum SandError {
+    Config(String),          // bad command line or scene file, says what and where
+    Library(String),         // no vulkan loader/driver
+    Instance(String),        // instance, surface or window creation
+    DeviceSelection(String), // no (matching) usable gpu, already lists the candidates
+    Device(String),          // logical device creation
+    Allocation { what: &'static str, reason: String },
+    Pipeline { what: &'static str, reason: String }, // shaders, pipelines, descriptor sets
+    Command { what: &'static str, reason: String },  // recording command buffers
+    Swapchain { what: &'static str, reason: String },
+    Submission { what: &'static str, reason: String },
+}
+
+pub type Result<T> = std::result::Result<T, SandError>;
+
+impl SandError {
+    pub fn allocation(what: &'static str, reason: impl fmt::Display) -> SandError {
+        SandError::Allocation {
+            what,
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn pipeline(what: &'static str, reason: impl fmt::Display) -> SandError {
+        SandError::Pipeline {
+            what,
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn command(what: &'static str, reason: impl fmt::Display) -> SandError {
+        SandError::Command {
+            what,
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn swapchain(what: &'static str, reason: impl fmt::Display) -> SandError {
+        SandError::Swapchain {
+            what,
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn submission(what: &'static str, reason: impl fmt::Display) -> SandError {
+        SandError::Submission {
+            what,
+            reason: reason.to_string(),
+        }
+    }
+
+    // what the user can actually do about it, if anything
+    fn hint(&self) -> Option<&'static str> {
+        match self {
+            SandError::Config(_) => Some("run with --help to see every option"),
+            SandError::Library(_) => Some("install a vulkan driver (or the vulkan sdk) for your gpu"),
+            SandError::DeviceSelection(_) => Some("run with --list-devices to see every gpu"),
+            SandError::Device(_) | SandError::Allocation { .. } => {
+                Some("try another gpu with --gpu, or a smaller world")
+            }
+            SandError::Submission { .. } => Some("the gpu may have been lost, updating the driver can help"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SandError::Config(reason) => write!(f, "{reason}")?,
+            SandError::Library(reason) => write!(f, "couldn't load vulkan: {reason}")?,
+            SandError::Instance(reason) => write!(f, "couldn't create the vulkan instance/window: {reason}")?,
+            SandError::DeviceSelection(reason) => write!(f, "{reason}")?,
+            SandError::Device(reason) => write!(f, "couldn't create the device: {reason}")?,
+            SandError::Allocation { what, reason } => write!(f, "couldn't allocate {what}: {reason}")?,
+            SandError::Pipeline { what, reason } => write!(f, "couldn't create {what}: {reason}")?,
+            SandError::Command { what, reason } => write!(f, "couldn't record {what}: {reason}")?,
+            SandError::Swapchain { what, reason } => write!(f, "swapchain {what} failed: {reason}")?,
+            SandError::Submission { what, reason } => write!(f, "couldn't submit {what}: {reason}")?,
+        }
+        if let Some(hint) = self.hint() {
+            write!(f, "\nhint: {hint}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SandError {}
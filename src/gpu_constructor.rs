@@ -1,38 +1,38 @@
 use std::sync::Arc;
-use vulkano::device::physical::{PhysicalDevice, PhysicalDeviceType};
-use vulkano::device::{
-    Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo, QueueFlags,
-};
-use vulkano::instance::{Instance, InstanceCreateInfo};
+
+use vulkano::device::{Device, DeviceCreateInfo, DeviceExtensions, QueueCreateInfo, QueueFlags};
+use vulkano::instance::{Instance, InstanceCreateInfo, InstanceExtensions};
 use vulkano::swapchain::Surface;
 use vulkano::VulkanLibrary;
 use vulkano_win::VkSurfaceBuild;
-use winit::dpi::PhysicalSize;
 use winit::event_loop::EventLoop;
 use winit::window::{Window, WindowBuilder};
 
-pub fn construct_gpu() -> (
-    Arc<VulkanLibrary>,
-    Arc<PhysicalDevice>,
-    u32,
-    Arc<Instance>,
-    Arc<Device>,
-    impl ExactSizeIterator + Iterator<Item = Arc<Queue>>,
-    Arc<Window>,
-    Arc<Surface>,
-	EventLoop<()>,
-	PhysicalSize<u32>,
-) {
-    let library = VulkanLibrary::new().expect("no local Vulkan library/DLL");
-    let required_extensions = vulkano_win::required_extensions(&library);
+use crate::debug::{self, DebugOptions};
+use crate::device_select::{self, DeviceSelection};
+use crate::error::{Result, SandError};
+use crate::pass_structs::{ComputeContext, GpuContext};
+
+pub fn construct_gpu(
+    selection: &DeviceSelection,
+    debug_options: DebugOptions,
+) -> Result<GpuContext> {
+    let library = VulkanLibrary::new().map_err(|e| SandError::Library(e.to_string()))?;
+    let mut enabled_extensions = vulkano_win::required_extensions(&library);
+    // lets the surface offer hdr/extended colour spaces, see utils::choose_surface_format
+    enabled_extensions.ext_swapchain_colorspace =
+        library.supported_extensions().ext_swapchain_colorspace;
+    let enabled_layers = debug::enable_validation(&library, debug_options, &mut enabled_extensions);
     let instance = Instance::new(
         library.clone(),
         InstanceCreateInfo {
-            enabled_extensions: required_extensions,
+            enabled_extensions,
+            enabled_layers,
             ..Default::default()
         },
     )
-    .expect("failed to make instance");
+    .map_err(|e| SandError::Instance(e.to_string()))?;
+    let debug_messenger = debug::create_messenger(&instance);
     let device_extensions = DeviceExtensions {
         khr_swapchain: true,
         ..DeviceExtensions::empty()
@@ -41,64 +41,56 @@ pub fn construct_gpu() -> (
 
     let surface = WindowBuilder::new()
         .build_vk_surface(&event_loop, instance.clone())
-        .unwrap();
+        .map_err(|e| SandError::Instance(e.to_string()))?;
 
-    let window = surface
-        .object()
-        .unwrap()
-        .clone()
-        .downcast::<Window>()
-        .unwrap();
-    let (physical_device, queue_family_index) = instance
-        .enumerate_physical_devices()
-        .expect("failed to get devices")
-        .filter(|p| p.supported_extensions().contains(&device_extensions))
-        .filter_map(|p| {
-            p.queue_family_properties()
-                .iter()
-                .enumerate()
-                .position(|(i, q)| {
-                    q.queue_flags.contains(QueueFlags::GRAPHICS)
-                        && p.surface_support(i as u32, &surface).unwrap_or(false)
-                })
-                .map(|q| (p, q as u32))
-        })
-        .min_by_key(|(p, _)| match p.properties().device_type {
-            PhysicalDeviceType::DiscreteGpu => 0,
-            PhysicalDeviceType::IntegratedGpu => 1,
-            PhysicalDeviceType::VirtualGpu => 2,
-            PhysicalDeviceType::Cpu => 3,
-            _ => 4,
-        })
-        .expect("no device available");
+    let window = surface_window(&surface)?;
+    let candidates = device_select::candidates(&instance, Some(&surface), &device_extensions)?;
+    // the family select picked supports graphics and presenting to this surface
+    let (physical_device, queue_family_index) =
+        device_select::select(&candidates, &selection.selector)?;
     let name = &physical_device.properties().device_name;
-    println!("{name:?}");
+    log::info!("using {name}");
     for family in physical_device.queue_family_properties() {
-        println!(
+        log::debug!(
             "Found a queue family with {:?} queue(s)",
             family.queue_count
         );
     }
 
-    let queue_family_index = physical_device
+    // a compute only family usually means the gpu can run it alongside graphics
+    let compute_family_index = physical_device
         .queue_family_properties()
         .iter()
-        .enumerate()
-        .position(|(_queue_family_index, queue_family_properties)| {
+        .position(|queue_family_properties| {
             queue_family_properties
                 .queue_flags
-                .contains(QueueFlags::GRAPHICS)
+                .contains(QueueFlags::COMPUTE)
+                && !queue_family_properties
+                    .queue_flags
+                    .intersects(QueueFlags::GRAPHICS)
         })
-        .expect("couldn't find a graphical queue family") as u32;
+        .map(|i| i as u32);
+    match compute_family_index {
+        Some(index) => log::info!("Using queue family {index} for async compute"),
+        None => log::info!("No dedicated compute queue family, sharing the graphics queue"),
+    }
 
-    let result = Device::new(
+    // here we pass the desired queue families to use by index, the graphics queue always comes out first
+    let mut queue_create_infos = vec![QueueCreateInfo {
+        queue_family_index,
+        ..Default::default()
+    }];
+    if let Some(queue_family_index) = compute_family_index {
+        queue_create_infos.push(QueueCreateInfo {
+            queue_family_index,
+            ..Default::default()
+        });
+    }
+
+    let (device, mut queues) = Device::new(
         physical_device.clone(),
         DeviceCreateInfo {
-            // here we pass the desired queue family to use by index
-            queue_create_infos: vec![QueueCreateInfo {
-                queue_family_index,
-                ..Default::default()
-            }],
+            queue_create_infos,
             enabled_extensions: DeviceExtensions {
                 khr_storage_buffer_storage_class: true,
                 khr_swapchain: true,
@@ -107,18 +99,104 @@ pub fn construct_gpu() -> (
             ..Default::default()
         },
     )
-    .expect("failed to create device");
-    println!("Device acquired");
-    (
+    .map_err(|e| SandError::Device(e.to_string()))?;
+    log::debug!("Device acquired");
+
+    // queues come out in the order of queue_create_infos, so graphics first
+    let render_queue = queues
+        .next()
+        .ok_or_else(|| SandError::Device("no render queue was created".to_string()))?;
+    // only there if the gpu has a dedicated compute family, otherwise everything goes through one queue
+    let compute_queue = queues.next().unwrap_or_else(|| render_queue.clone());
+    let window_size = window.inner_size();
+    Ok(GpuContext {
         library,
-        physical_device,
-        queue_family_index,
         instance,
-        result.0,
-        result.1,
-        window.clone(),
+        physical_device,
+        device,
+        render_queue,
+        compute_queue,
+        window,
         surface,
         event_loop,
-		window.inner_size(),
+        window_size,
+        debug_messenger,
+    })
+}
+
+/// The window `build_vk_surface` stored as the surface's object.
+pub(crate) fn surface_window(surface: &Surface) -> Result<Arc<Window>> {
+    surface
+        .object()
+        .ok_or_else(|| SandError::Instance("the surface has no window".to_string()))?
+        .clone()
+        .downcast::<Window>()
+        .map_err(|_| SandError::Instance("the surface's object isn't a winit window".to_string()))
+}
+
+/// Every device and whether it's usable, one line each, for --list-devices. It runs before the window
+/// exists, so for a windowed run presenting isn't checked, only the extensions and a compute family.
+pub fn list_devices(headless: bool) -> Result<Vec<String>> {
+    let library = VulkanLibrary::new().map_err(|e| SandError::Library(e.to_string()))?;
+    let instance = Instance::new(library, InstanceCreateInfo::default())
+        .map_err(|e| SandError::Instance(e.to_string()))?;
+    let device_extensions = DeviceExtensions {
+        khr_swapchain: !headless,
+        ..DeviceExtensions::empty()
+    };
+    let candidates = device_select::candidates(&instance, None, &device_extensions)?;
+    Ok(candidates.iter().map(|candidate| candidate.describe()).collect())
+}
+
+/// No window or surface, just a device and one compute queue for headless runs and tests.
+pub fn construct_headless(
+    selection: &DeviceSelection,
+    debug_options: DebugOptions,
+) -> Result<ComputeContext> {
+    let library = VulkanLibrary::new().map_err(|e| SandError::Library(e.to_string()))?;
+    let mut enabled_extensions = InstanceExtensions::empty();
+    let enabled_layers = debug::enable_validation(&library, debug_options, &mut enabled_extensions);
+    let instance = Instance::new(
+        library.clone(),
+        InstanceCreateInfo {
+            enabled_extensions,
+            enabled_layers,
+            ..Default::default()
+        },
     )
+    .map_err(|e| SandError::Instance(e.to_string()))?;
+    let debug_messenger = debug::create_messenger(&instance);
+
+    let device_extensions = DeviceExtensions::empty();
+    let candidates = device_select::candidates(&instance, None, &device_extensions)?;
+    let (physical_device, queue_family_index) =
+        device_select::select(&candidates, &selection.selector)?;
+    log::info!("using {} (headless)", physical_device.properties().device_name);
+
+    let (device, mut queues) = Device::new(
+        physical_device.clone(),
+        DeviceCreateInfo {
+            queue_create_infos: vec![QueueCreateInfo {
+                queue_family_index,
+                ..Default::default()
+            }],
+            enabled_extensions: DeviceExtensions {
+                khr_storage_buffer_storage_class: true,
+                ..DeviceExtensions::empty()
+            },
+            ..Default::default()
+        },
+    )
+    .map_err(|e| SandError::Device(e.to_string()))?;
+    let compute_queue = queues
+        .next()
+        .ok_or_else(|| SandError::Device("no compute queue was created".to_string()))?;
+    Ok(ComputeContext {
+        library,
+        instance,
+        physical_device,
+        device,
+        compute_queue,
+        debug_messenger,
+    })
 }
@@ -0,0 +1,414 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::config::SimulationConfig;
+use crate::error::{Result, SandError};
+use crate::simulation::bodies::{self, MAX_BODIES};
+use crate::simulation::fields::{Field, MAX_FIELDS};
+use crate::simulation::impulse::Impulse;
+use crate::simulation::materials;
+use crate::simulation::scene;
+use crate::simulation::world::World;
+
+// the body brush's block, particles along each side and pixels between them
+const BODY_BRUSH_SIZE: u32 = 5;
+const BODY_BRUSH_SPACING: f32 = 3.0;
+
+/// Everything the user can do to the simulation. The window turns winit events into these, recordings
+/// store them and replays feed them back, so anything that changes the world has to go through here.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    Paint { pos: [f32; 2] },   // one particle of the current material, in pixels
+    Explode { pos: [f32; 2] }, // Impulse::brush centred here
+    Body { pos: [f32; 2] },    // a rigid block of the current material centred here
+    Field(Field),              // a force field to add
+    ClearFields,
+    Material(u32),             // index into materials::KINDS
+    Pause,
+    Resume,
+    Step, // one tick while paused
+    Params(SimulationConfig), // a config reload's simulation section
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // float formatting round trips exactly, a replay has to paint the same bits
+            Input::Paint { pos } => write!(f, "paint {} {}", pos[0], pos[1]),
+            Input::Explode { pos } => write!(f, "explode {} {}", pos[0], pos[1]),
+            Input::Body { pos } => write!(f, "body {} {}", pos[0], pos[1]),
+            // the same as a scene file's line
+            Input::Field(field) => write!(f, "field {}", scene::field_line(field)),
+            Input::ClearFields => write!(f, "clear-fields"),
+            Input::Material(material) => write!(f, "material {material}"),
+            Input::Pause => write!(f, "pause"),
+            Input::Resume => write!(f, "resume"),
+            Input::Step => write!(f, "step"),
+            Input::Params(config) => {
+                // every field by name, so adding one to the config doesn't compile until it's written here
+                let SimulationConfig {
+                    gravity,
+                    damping,
+                    timestep,
+                    jitter,
+                    heat_radius,
+                    reaction_radius,
+                    sph_radius,
+                    sleep_speed,
+                    sleep_ticks,
+                } = config;
+                write!(
+                    f,
+                    "params {} {} {damping} {timestep} {jitter} {heat_radius} {reaction_radius} {sph_radius} \
+                     {sleep_speed} {sleep_ticks}",
+                    gravity[0], gravity[1]
+                )
+            }
+        }
+    }
+}
+
+impl Input {
+    /// The other way from `Display`, None for anything that isn't an input.
+    fn parse(line: &str) -> Option<Input> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["paint", x, y] => Some(Input::Paint {
+                pos: [x.parse().ok()?, y.parse().ok()?],
+            }),
+            ["explode", x, y] => Some(Input::Explode {
+                pos: [x.parse().ok()?, y.parse().ok()?],
+            }),
+            ["body", x, y] => Some(Input::Body {
+                pos: [x.parse().ok()?, y.parse().ok()?],
+            }),
+            ["field", entry, values @ ..] => {
+                let values: Vec<f32> = values.iter().map(|v| v.parse().ok()).collect::<Option<_>>()?;
+                scene::parse_field(entry, &values).ok().map(Input::Field)
+            }
+            ["clear-fields"] => Some(Input::ClearFields),
+            ["material", material] => {
+                let material: u32 = material.parse().ok()?;
+                ((material as usize) < materials::KINDS.len()).then_some(Input::Material(material))
+            }
+            ["pause"] => Some(Input::Pause),
+            ["resume"] => Some(Input::Resume),
+            ["step"] => Some(Input::Step),
+            [
+                "params",
+                gravity_x,
+                gravity_y,
+                damping,
+                timestep,
+                jitter,
+                heat_radius,
+                reaction_radius,
+                sph_radius,
+                sleep_speed,
+                sleep_ticks,
+            ] => Some(Input::Params(SimulationConfig {
+                gravity: [gravity_x.parse().ok()?, gravity_y.parse().ok()?],
+                damping: damping.parse().ok()?,
+                timestep: timestep.parse().ok()?,
+                jitter: jitter.parse().ok()?,
+                heat_radius: heat_radius.parse().ok()?,
+                reaction_radius: reaction_radius.parse().ok()?,
+                sph_radius: sph_radius.parse().ok()?,
+                sleep_speed: sleep_speed.parse().ok()?,
+                sleep_ticks: sleep_ticks.parse().ok()?,
+            })),
+            _ => None,
+        }
+    }
+}
+
+/// What inputs have done so far that isn't stored in the world itself.
+#[derive(Clone, Debug, Default)]
+pub struct InputState {
+    pub material: u32,
+    pub paused: bool,
+    step: bool,
+}
+
+impl InputState {
+    /// A `Params` input rewrites the params every tick reads, so nothing can be in flight when one is applied.
+    pub fn apply(&mut self, input: &Input, world: &mut World) -> Result<()> {
+        match input {
+            Input::Paint { pos } => {
+                let spawned =
+                    world.spawn(materials::kind(self.material).particle(self.material, *pos));
+                if !spawned {
+                    log::warn!("the world is full, raise --world-size to paint more");
+                }
+            }
+            Input::Explode { pos } => world.impulse(Impulse::brush(*pos)),
+            Input::Body { pos } => {
+                let block = bodies::block(self.material, *pos, BODY_BRUSH_SIZE, BODY_BRUSH_SPACING);
+                if !world.spawn_body(block) {
+                    log::warn!("the world is full or has used up its {MAX_BODIES} body numbers, no more bodies");
+                }
+            }
+            Input::Field(field) => {
+                if !world.add_field(*field) {
+                    log::warn!("the world already has {MAX_FIELDS} force fields, clear them to place more");
+                }
+            }
+            Input::ClearFields => world.clear_fields(),
+            Input::Material(material) => self.material = *material,
+            Input::Pause => self.paused = true,
+            Input::Resume => self.paused = false,
+            Input::Step => self.step = true,
+            Input::Params(config) => world.set_params(config)?,
+        }
+        Ok(())
+    }
+
+    /// Whether this frame runs a tick, uses up a pending step.
+    pub fn should_tick(&mut self) -> bool {
+        !self.paused || std::mem::take(&mut self.step)
+    }
+}
+
+// one input per line after the header, an input tagged n is applied right before tick n runs:
+//   seed <n>
+//   <tick> <input>
+// the world also depends on the scene, config and reactions, those have to be passed again when replaying.
+// config reloads after the start are recorded as params inputs
+
+/// Writes inputs as they happen, flushed every time so a crash still leaves a usable recording.
+pub struct Recorder {
+    file: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, seed: u32) -> Result<Recorder> {
+        let file = File::create(path)
+            .map_err(|e| SandError::Config(format!("couldn't create {}: {e}", path.display())))?;
+        let mut recorder = Recorder {
+            file: BufWriter::new(file),
+        };
+        recorder.write(format_args!("seed {seed}"))?;
+        Ok(recorder)
+    }
+
+    pub fn record(&mut self, tick: u64, input: &Input) -> Result<()> {
+        self.write(format_args!("{tick} {input}"))
+    }
+
+    fn write(&mut self, line: fmt::Arguments) -> Result<()> {
+        writeln!(self.file, "{line}")
+            .and_then(|()| self.file.flush())
+            .map_err(|e| SandError::Config(format!("couldn't write the recording: {e}")))
+    }
+}
+
+/// A recording being fed back, the world has to be created with `seed`.
+pub struct Replay {
+    pub seed: u32,
+    inputs: VecDeque<(u64, Input)>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Replay> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| SandError::Config(format!("couldn't read {}: {e}", path.display())))?;
+        let bad_line = |line_i: usize, reason: &str| {
+            SandError::Config(format!("{}:{}: {reason}", path.display(), line_i + 1))
+        };
+        let mut lines = text.lines().enumerate();
+        let seed = match lines.next().map(|(_, line)| line.split_whitespace().collect::<Vec<_>>()) {
+            Some(words) if words.len() == 2 && words[0] == "seed" => words[1]
+                .parse()
+                .map_err(|_| bad_line(0, "the seed isn't a number"))?,
+            _ => return Err(bad_line(0, "expected seed <n>, is this a recording?")),
+        };
+        let mut inputs = VecDeque::new();
+        for (line_i, line) in lines {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (tick, input) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let tick: u64 = tick
+                .parse()
+                .map_err(|_| bad_line(line_i, "expected <tick> <input>"))?;
+            let input = Input::parse(input).ok_or_else(|| bad_line(line_i, "unknown input"))?;
+            inputs.push_back((tick, input));
+        }
+        Ok(Replay { seed, inputs })
+    }
+
+    /// Inputs to apply before tick `tick`, in the order they were recorded.
+    pub fn take(&mut self, tick: u64) -> Vec<Input> {
+        let mut taken = Vec::new();
+        while self.inputs.front().is_some_and(|(at, _)| *at <= tick) {
+            taken.extend(self.inputs.pop_front().map(|(_, input)| input));
+        }
+        taken
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::impulse::Falloff;
+
+    // every variant, with floats that don't print short so the round trip has to be exact
+    fn every_input() -> Vec<Input> {
+        let inputs = vec![
+            Input::Paint { pos: [0.1, -2.5e-7] },
+            Input::Explode { pos: [1f32 / 3f32, 640.0] },
+            Input::Body { pos: [-12.75, 1e9] },
+            Input::Field(Field::Wind {
+                centre: [100.0, 0.3],
+                half_size: [2f32 / 3f32, 40.0],
+                acceleration: [-9.81, 0.0],
+            }),
+            Input::Field(Field::Attractor {
+                centre: [5.5, 6.25],
+                radius: 48.0,
+                strength: -150.0,
+                falloff: Falloff::None,
+            }),
+            Input::Field(Field::Vortex {
+                centre: [0.0, 0.0],
+                radius: 0.001,
+                strength: 1e-3,
+                falloff: Falloff::Quadratic,
+            }),
+            Input::ClearFields,
+            Input::Material(materials::KINDS.len() as u32 - 1),
+            Input::Pause,
+            Input::Resume,
+            Input::Step,
+            Input::Params(SimulationConfig {
+                gravity: [0.1, -98.1],
+                damping: 1f32 / 3f32,
+                timestep: 1f32 / 60f32,
+                jitter: 2.5e-7,
+                heat_radius: 4.0,
+                reaction_radius: 3.3,
+                sph_radius: 8.0,
+                sleep_speed: 0.0,
+                sleep_ticks: u32::MAX,
+            }),
+        ];
+        // doesn't compile once a variant is added without one above
+        for input in &inputs {
+            match input {
+                Input::Paint { .. }
+                | Input::Explode { .. }
+                | Input::Body { .. }
+                | Input::Field(_)
+                | Input::ClearFields
+                | Input::Material(_)
+                | Input::Pause
+                | Input::Resume
+                | Input::Step
+                | Input::Params(_) => {}
+            }
+        }
+        inputs
+    }
+
+    #[test]
+    fn every_input_round_trips_through_its_line() {
+        for input in every_input() {
+            assert_eq!(Input::parse(&input.to_string()), Some(input.clone()), "{input}");
+        }
+    }
+
+    #[test]
+    fn unknown_and_malformed_inputs_dont_parse() {
+        let past_the_last_kind = format!("material {}", materials::KINDS.len());
+        for line in [
+            "",
+            "fly",
+            "paint 1",
+            "paint 1 2 3",
+            "paint x 2",
+            "material -1",
+            past_the_last_kind.as_str(),
+            "field wind 0 0 0 10 1 1",
+            "field sink 0 0 10 10",
+            "clear-fields now",
+            "pause now",
+            "params 0 0 1 0.016 0 4 3 8 10",
+            "params 0 0 1 0.016 0 4 3 8 10 30 1",
+            "params 0 0 1 0.016 0 4 3 8 10 -30",
+        ] {
+            assert_eq!(Input::parse(line), None, "{line:?}");
+        }
+    }
+
+    // each test gets its own file, they run in parallel
+    fn load(name: &str, text: &str) -> Result<Replay> {
+        let path = std::env::temp_dir().join(format!("sand-input-{}-{name}.txt", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let replay = Replay::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        replay
+    }
+
+    fn rejects(name: &str, text: &str, expected: &str) {
+        match load(name, text) {
+            Ok(_) => panic!("{text:?} loaded"),
+            Err(SandError::Config(message)) => {
+                assert!(message.contains(expected), "{message:?} doesn't mention {expected:?}")
+            }
+            Err(e) => panic!("{text:?} failed with {e}"),
+        }
+    }
+
+    #[test]
+    fn bad_headers_are_rejected() {
+        rejects("empty", "", ":1: expected seed");
+        rejects("no-header", "0 paint 1 2\n", ":1: expected seed");
+        rejects("no-seed", "seed\n", ":1: expected seed");
+        rejects("two-seeds", "seed 1 2\n", ":1: expected seed");
+        rejects("bad-seed", "seed -1\n", ":1: the seed isn't a number");
+    }
+
+    #[test]
+    fn bad_lines_are_rejected() {
+        rejects("no-tick", "seed 1\npaint 1 2\n", ":2: expected <tick> <input>");
+        rejects("negative-tick", "seed 1\n\n-3 pause\n", ":3: expected <tick> <input>");
+        rejects("no-input", "seed 1\n5\n", ":2: unknown input");
+        rejects("unknown-input", "seed 1\n5 fly\n", ":2: unknown input");
+        rejects("bad-input", "seed 1\n5 paint 1\n", ":2: unknown input");
+    }
+
+    #[test]
+    fn a_recording_loads_back_in_tick_order() {
+        let path = std::env::temp_dir().join(format!("sand-input-{}-recording.txt", std::process::id()));
+        let inputs = every_input();
+        let mut recorder = Recorder::create(&path, 0xdead).unwrap();
+        for (tick, input) in inputs.iter().enumerate() {
+            recorder.record(tick as u64 / 2, input).unwrap();
+        }
+        drop(recorder);
+        let replay = Replay::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let Ok(mut replay) = replay else {
+            panic!("the recording didn't load");
+        };
+
+        assert_eq!(replay.seed, 0xdead);
+        let mut replayed = Vec::new();
+        for tick in 0..inputs.len() as u64 {
+            let taken = replay.take(tick);
+            // two inputs per tick, both before the tick they were recorded for
+            assert!(taken.len() <= 2);
+            replayed.extend(taken);
+        }
+        assert!(replay.is_done());
+        assert_eq!(replayed, inputs);
+    }
+}
@@ -0,0 +1,16 @@
+//! Falling sand on the gpu with vulkano. `gpu_constructor::construct_gpu` sets up vulkan and a window,
+//! `simulation::world::World` owns the particles and ticks them, `window::make_window` puts it on screen.
+
+pub mod cli;
+pub mod config;
+pub mod debug;
+pub mod deploy_shader;
+pub mod device_select;
+pub mod error;
+pub mod gpu_constructor;
+pub mod input;
+pub mod logging;
+pub mod pass_structs;
+pub mod profiler;
+pub mod simulation;
+pub mod window;
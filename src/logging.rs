@@ -0,0 +1,27 @@
+use std::fs::File;
+use std::path::Path;
+
+use env_logger::{Builder, Env, Target};
+
+use crate::error::{Result, SandError};
+
+/// Diagnostics go through `log`, the fps readout stays on stdout so the two never mix. `RUST_LOG` picks
+/// what's shown per module, e.g. `RUST_LOG=info,sand::window=debug` or `RUST_LOG=vulkan=warn`
+/// for validation messages only. Without it everything at info and above is shown.
+pub fn init(log_file: Option<&Path>) -> Result<()> {
+    let mut builder = Builder::from_env(Env::default().default_filter_or("info"));
+    match log_file {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| {
+                SandError::Config(format!("couldn't create log file {}: {e}", path.display()))
+            })?;
+            builder.target(Target::Pipe(Box::new(file))).write_style(env_logger::WriteStyle::Never);
+        }
+        None => {
+            builder.target(Target::Stderr);
+        }
+    }
+    builder
+        .try_init()
+        .map_err(|e| SandError::Config(format!("couldn't start logging: {e}")))
+}
@@ -1,75 +1,138 @@
-use vulkano::buffer::BufferContents;
+use std::time::Instant;
 
-use vulkano::memory::allocator::{GenericMemoryAllocator, StandardMemoryAllocator};
+use vulkano::memory::allocator::StandardMemoryAllocator;
 use vulkano::padded::Padded;
-use vulkano::sync::{self};
 
-mod deploy_shader;
-mod gpu_constructor;
-mod pass_structs;
-mod simulation;
-mod window;
-
-use simulation::sand::{sand_shader::Material, PADDING};
-use winit::event_loop;
-
-#[derive(BufferContents)]
-#[repr(C)]
-struct TestStruct {
-    first: i32,
-    second: i32,
-    res: i32,
-}
-
-// device, queues,
+use sand::config::Config;
+use sand::error;
+use sand::input::{InputState, Replay};
+use sand::simulation::fields::Field;
+use sand::simulation::geometry::Geometry;
+use sand::simulation::reactions::ReactionTable;
+use sand::simulation::sand::{sand_shader::Material, PADDING};
+use sand::simulation::scene;
+use sand::simulation::world::World;
+use sand::{cli, gpu_constructor, logging, window};
 
 fn main() {
-    let mut world: Vec<Padded<Material, PADDING>> = Vec::new();
-    let work_groups = [2usize.pow(4) as u32, 1, 1];
-    for i in 1..(64 * work_groups[0]) {
-        let i_f = i as f32;
-        world.push(Padded(Material {
-            id: i,
-            colour: [i_f / 100f32, i_f / 100f32, i_f / 100f32],
-            pos: [i_f, 100f32],
-            ..Default::default()
-        }));
+    if let Err(e) = run() {
+        eprintln!("error: {e}");
+        std::process::exit(1);
     }
+}
 
-    let (
-        library,
-        physical_device,
-        _queue_family_index,
-        _instance,
-        device,
-        mut queues,
-        window,
-        surface,
-		event_loop,
-		window_size,
-    ) = gpu_constructor::construct_gpu();
-    // -=-=-=-=-=
-
-    let queue = queues.next().unwrap();
+fn run() -> error::Result<()> {
+    let options = match cli::Options::from_env()? {
+        cli::Command::Run(options) => options,
+        cli::Command::Help => {
+            print!("{}", cli::USAGE);
+            return Ok(());
+        }
+    };
+    logging::init(options.log_file.as_deref())?;
+    if options.device.list_devices {
+        for device in gpu_constructor::list_devices(options.headless_ticks.is_some())? {
+            println!("{device}");
+        }
+        return Ok(());
+    }
+    // read the scene before touching the gpu, a typo shouldn't cost a window
+    let scene = options.initial_scene()?;
+    let particles: Vec<Padded<Material, PADDING>> =
+        scene.particles.into_iter().map(Padded).collect();
+    let reactions = options.reaction_table()?;
 
-    let memory_allocator: GenericMemoryAllocator<
-        std::sync::Arc<vulkano::memory::allocator::FreeListAllocator>,
-    > = StandardMemoryAllocator::new_default(device.clone());
+    if let Some(ticks) = options.headless_ticks {
+        return run_headless(&options, particles, &scene.geometry, scene.fields, &reactions, ticks);
+    }
 
-    // let data2 = 0..64; //staging, gpu 1, gpu 2, download (eventually)
+    let context = gpu_constructor::construct_gpu(&options.device, options.debug)?;
+    options.validate(&context.physical_device)?;
+    let memory_allocator = StandardMemoryAllocator::new_default(context.device.clone());
 
     window::make_window(
-        library,
+        context,
         memory_allocator,
-        device,
-        queue,
-        world,
-        work_groups,
-        physical_device,
-        window,
-        surface,
-		event_loop,
-		window_size,
-    );
+        particles,
+        scene.geometry,
+        scene.fields,
+        options.work_group_counts(),
+        reactions,
+        options.simulation_seed(),
+        options.window,
+    )
     //main.rs is done now as window now has control
 }
+
+fn run_headless(
+    options: &cli::Options,
+    particles: Vec<Padded<Material, PADDING>>,
+    geometry: &Geometry,
+    fields: Vec<Field>,
+    reactions: &ReactionTable,
+    ticks: u64,
+) -> error::Result<()> {
+    let context = gpu_constructor::construct_headless(&options.device, options.debug)?;
+    options.validate(&context.physical_device)?;
+    let memory_allocator = StandardMemoryAllocator::new_default(context.device.clone());
+    let mut replay = options.window.replay.as_deref().map(Replay::load).transpose()?;
+    let seed = replay.as_ref().map_or(options.simulation_seed(), |replay| replay.seed);
+    let mut world = World::new(
+        &context.device,
+        &context.compute_queue,
+        &[context.compute_queue.queue_family_index()],
+        &memory_allocator,
+        particles,
+        options.work_group_counts(),
+        &Config::load_or_default(options.window.config.as_deref()).simulation,
+        reactions,
+        geometry,
+        seed,
+        None,
+    )?;
+    world.set_fields(fields)?;
+
+    let start = Instant::now();
+    let checksums = match &mut replay {
+        // inputs only land between ticks, so step one at a time when there are any
+        Some(replay) => {
+            let mut input_state = InputState::default();
+            let mut checksums = Vec::new();
+            for _ in 0..ticks {
+                for input in replay.take(world.ticks()) {
+                    input_state.apply(&input, &mut world)?;
+                }
+                checksums.extend(world.run_ticks(1)?);
+            }
+            checksums
+        }
+        None => world.run_ticks(ticks)?,
+    };
+    let elapsed = start.elapsed().as_secs_f64();
+    println!(
+        "{ticks} ticks in {:.3}s ({:.1} ticks/s)",
+        elapsed,
+        ticks as f64 / elapsed.max(f64::EPSILON)
+    );
+
+    if let Some(checksum) = checksums.last() {
+        log::info!("checksum after {ticks} ticks with seed {seed}: {checksum:08x}");
+    }
+    if let Some(path) = &options.checksums {
+        let lines: String = checksums
+            .iter()
+            .enumerate()
+            .map(|(tick, checksum)| format!("{tick} {checksum:08x}\n"))
+            .collect();
+        std::fs::write(path, lines).map_err(|e| {
+            error::SandError::Config(format!("couldn't write {}: {e}", path.display()))
+        })?;
+        log::info!("checksums written to {}", path.display());
+    }
+
+    if let Some(path) = &options.window.world_output {
+        scene::save(path, &world.download(&memory_allocator)?, geometry, world.fields())?;
+        log::info!("world written to {}", path.display());
+    }
+    Ok(())
+}
@@ -4,7 +4,10 @@ use vulkano::device::physical::PhysicalDevice;
 use vulkano::device::Device;
 use vulkano::device::Queue;
 
+use vulkano::instance::debug::DebugUtilsMessenger;
+use vulkano::instance::Instance;
 use vulkano::swapchain::Surface;
+use vulkano::VulkanLibrary;
 use winit::dpi::PhysicalSize;
 use winit::event_loop::EventLoop;
 use winit::window::Window;
@@ -18,49 +21,31 @@ pub struct WindowInitialized {
     pub queue: Arc<Queue>,
 }
 
-// #[repr(C)]
-// #[derive(BufferContents, Clone, Debug)]
-// pub struct Material {
-//     // MUST BE KEPT IN SYNC WITH GLSL VERSION
-//     pub id: u32,
-//     pub _pad: u64,        // misalignment dirty fix
-//     pub colour: [f32; 3], // made using id normally except it can also change
-//     pub _pad2: u16,
-//     pub pos: [f32; 2],
-//     pub vel: [f32; 2],
-//     pub mass: f32, // made using id
-//     pub _pad3: u16,
-//     pub target: [f32; 2], // ^ for statics
-//     pub force: f32,       // ^ how much force towards target is left?
-//     pub stable: f32,      // ^ how much displacement is the maximum before force weakens?
-//     pub tags: u32,        // ^ is a bit mask of tags in the future maybe, currently useless
-//     pub gas: u32,         // ^ 0 is normal gravity, 1 is antigravity, other values will do smth idk
-// }
+/// Everything `gpu_constructor::construct_gpu` sets up, hand it to `window::make_window` or pull out
+/// the device and queues to run a `simulation::world::World` yourself.
+pub struct GpuContext {
+    pub library: Arc<VulkanLibrary>,
+    pub instance: Arc<Instance>,
+    pub physical_device: Arc<PhysicalDevice>,
+    pub device: Arc<Device>,
+    pub render_queue: Arc<Queue>,
+    /// Same as `render_queue` unless the gpu has a dedicated compute family.
+    pub compute_queue: Arc<Queue>,
+    pub window: Arc<Window>,
+    pub surface: Arc<Surface>,
+    pub event_loop: EventLoop<()>,
+    pub window_size: PhysicalSize<u32>,
+    /// Has to outlive everything else when validation is on.
+    pub debug_messenger: Option<DebugUtilsMessenger>,
+}
 
-// impl Default for Material {
-//     fn default() -> Material {
-//         Material {
-//             id: 0,
-//             _pad: 64,
-//             colour: [1f32, 0f32, 1f32],
-//             _pad2: 16,
-//             pos: [0f32, 0f32],
-//             vel: [0f32, 0f32],
-//             mass: 1f32,
-//             _pad3: 16,
-//             target: [0f32, 0f32],
-//             force: 0f32,
-//             stable: 0f32,
-//             tags: 0,
-//             gas: 0,
-//         }
-//     }
-// }
-// pub struct GpuConstructed {
-// 	pub vulkan_library: Arc<VulkanLibrary>,
-//     pub physical_device: Arc<PhysicalDevice>,
-// 	pub queue_family_index: u32,
-//     pub instance: Arc<Instance>,
-//     pub device: Arc<Device>,
-//     pub queues: dyn Iterator<Item = Arc<Queue>>,
-// }
+/// What `gpu_constructor::construct_headless` sets up, enough to run a `World` without a window.
+pub struct ComputeContext {
+    pub library: Arc<VulkanLibrary>,
+    pub instance: Arc<Instance>,
+    pub physical_device: Arc<PhysicalDevice>,
+    pub device: Arc<Device>,
+    pub compute_queue: Arc<Queue>,
+    /// Has to outlive everything else when validation is on.
+    pub debug_messenger: Option<DebugUtilsMessenger>,
+}
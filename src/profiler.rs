@@ -0,0 +1,175 @@
+use std::sync::Arc;
+
+use vulkano::command_buffer::allocator::CommandBufferAllocator;
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::device::{Device, Queue};
+use vulkano::query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType};
+use vulkano::sync::PipelineStage;
+
+use crate::debug;
+use crate::error::{Result, SandError};
+
+// swapchain images past this share query slots, which is fine as long as there are fewer frames in flight
+pub const FRAME_SLOTS: u32 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Copy,
+    Tick,
+    Render,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 3] = [Stage::Copy, Stage::Tick, Stage::Render];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Copy => "copy",
+            Stage::Tick => "tick",
+            Stage::Render => "render",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    // every stage gets a begin/end pair, tick and render get one pair per swapchain image
+    fn first_query(self, slot: u32) -> u32 {
+        match self {
+            Stage::Copy => 0,
+            Stage::Tick => 2 + 2 * (slot % FRAME_SLOTS),
+            Stage::Render => 2 + 2 * FRAME_SLOTS + 2 * (slot % FRAME_SLOTS),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StageTimings {
+    pub copy_ms: f64,
+    pub tick_ms: f64,
+    pub render_ms: f64,
+}
+
+pub struct GpuProfiler {
+    pool: Arc<QueryPool>,
+    period_ns: f64,
+    valid_mask: u64,
+    timings_ms: [f64; 3],
+}
+
+impl GpuProfiler {
+    /// Returns `None` when any of the queue families can't write timestamps, callers should just skip profiling then.
+    pub fn new(device: &Arc<Device>, queues: &[&Arc<Queue>]) -> Option<GpuProfiler> {
+        let physical_device = device.physical_device();
+        let mut valid_bits = 64;
+        for queue in queues {
+            let family_bits = physical_device.queue_family_properties()
+                [queue.queue_family_index() as usize]
+                .timestamp_valid_bits?;
+            valid_bits = valid_bits.min(family_bits);
+        }
+        let valid_mask = if valid_bits >= 64 {
+            u64::MAX
+        } else {
+            (1u64 << valid_bits) - 1
+        };
+
+        let pool = match QueryPool::new(
+            device.clone(),
+            QueryPoolCreateInfo {
+                query_count: 2 + 4 * FRAME_SLOTS,
+                ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+            },
+        ) {
+            Ok(pool) => pool,
+            Err(e) => {
+                log::warn!("failed to create timestamp query pool, gpu timings are off: {e}");
+                return None;
+            }
+        };
+        debug::name(pool.as_ref(), "timestamp queries");
+
+        Some(GpuProfiler {
+            pool,
+            period_ns: physical_device.properties().timestamp_period as f64,
+            valid_mask,
+            timings_ms: [0f64; 3],
+        })
+    }
+
+    /// Must be recorded outside of a render pass as it also resets the queries for this stage.
+    pub fn begin<L, A: CommandBufferAllocator>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+        stage: Stage,
+        slot: u32,
+    ) -> Result<()> {
+        let first = stage.first_query(slot);
+        // safe as long as nothing else is using these queries, each stage and slot owns its own pair
+        unsafe {
+            builder
+                .reset_query_pool(self.pool.clone(), first..first + 2)
+                .map_err(|e| SandError::command("timestamp reset", e))?
+                .write_timestamp(self.pool.clone(), first, PipelineStage::TopOfPipe)
+                .map_err(|e| SandError::command("timestamp", e))?;
+        }
+        Ok(())
+    }
+
+    pub fn end<L, A: CommandBufferAllocator>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+        stage: Stage,
+        slot: u32,
+    ) -> Result<()> {
+        let first = stage.first_query(slot);
+        unsafe {
+            builder
+                .write_timestamp(self.pool.clone(), first + 1, PipelineStage::BottomOfPipe)
+                .map_err(|e| SandError::command("timestamp", e))?;
+        }
+        Ok(())
+    }
+
+    /// Reads back the timestamps for a stage, only call this once the command buffer that wrote them has finished.
+    /// Returns `None` (and keeps the last value) if the results aren't available yet.
+    pub fn resolve(&mut self, stage: Stage, slot: u32) -> Option<f64> {
+        let first = stage.first_query(slot);
+        let mut results = [0u64; 2];
+        let available = self
+            .pool
+            .queries_range(first..first + 2)
+            .unwrap()
+            .get_results(&mut results, QueryResultFlags::empty())
+            .unwrap_or(false);
+        if !available {
+            return None;
+        }
+        let ticks = (results[1] & self.valid_mask).wrapping_sub(results[0] & self.valid_mask)
+            & self.valid_mask;
+        let ms = ticks as f64 * self.period_ns / 1_000_000f64;
+        self.timings_ms[stage.index()] = ms;
+        Some(ms)
+    }
+
+    pub fn stage_ms(&self, stage: Stage) -> f64 {
+        self.timings_ms[stage.index()]
+    }
+
+    pub fn timings(&self) -> StageTimings {
+        StageTimings {
+            copy_ms: self.stage_ms(Stage::Copy),
+            tick_ms: self.stage_ms(Stage::Tick),
+            render_ms: self.stage_ms(Stage::Render),
+        }
+    }
+
+    pub fn readout(&self) -> String {
+        Stage::ALL
+            .iter()
+            .map(|stage| format!("{} {:.3}ms", stage.name(), self.stage_ms(*stage)))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+}
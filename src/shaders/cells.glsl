@@ -0,0 +1,54 @@
+// shared by the sleep and cell list stages and every neighbour search, needs sand_bindings.glsl and sand_random.glsl.
+// mirrored in simulation/cells.rs
+
+const uint CHUNK_COUNT = 4096u;// simulation/cells.rs CHUNK_COUNT, how many cells and chunks.woken stamps the chunks share
+const float CHUNK_LIMIT = 1048576.;// chunk coordinates are clamped to this, so far away particles still convert to ints
+
+// as wide as the furthest any stage looks for neighbours, so everything a particle can touch this tick
+// is in its own chunk or one of the 8 around it
+float chunk_size() {
+	return max(params.heat_radius, max(params.reaction_radius, params.sph_radius));
+}
+
+ivec2 chunk_of(vec2 pos) {
+	return ivec2(clamp(floor(pos / chunk_size()), vec2(-CHUNK_LIMIT), vec2(CHUNK_LIMIT)));
+}
+
+// the world has no edges so chunks share the cells by hash, two chunks in one cell only make the search look
+// at more particles than it needs to
+uint chunk_slot(ivec2 chunk) {
+	return pcg(uint(chunk.x) ^ pcg(uint(chunk.y))) % CHUNK_COUNT;
+}
+
+// the cell list: every live particle filed under its chunk slot, and every body particle under its body too,
+// rebuilt every tick from the positions before anything moves. sand_sleep.glsl counts them, sand_cells.glsl turns
+// the counts into ranges, sand_activity.glsl files them and sand_cell_sort.glsl puts every range in slot order.
+// a neighbour search then only looks through the cells of neighbour_slots, in that order, and the body fit through
+// its body's cell, so the sums come out the same every run
+
+// the chunk slots' cells then one per body number
+const uint CELL_COUNT = CHUNK_COUNT + MAX_BODIES;
+
+uint body_cell(uint body) {
+	return CHUNK_COUNT + body;
+}
+
+// pos's chunk slot and the 8 around it, a slot two of them hash to is only listed once. returns how many
+uint neighbour_slots(vec2 pos, out uint slots[9]) {
+	ivec2 chunk = chunk_of(pos);
+	uint count = 0u;
+	for (int y = -1; y <= 1; y++) {
+		for (int x = -1; x <= 1; x++) {
+			uint slot = chunk_slot(chunk + ivec2(x, y));
+			bool listed = false;
+			for (uint i = 0u; i < count; i++) {
+				listed = listed || slots[i] == slot;
+			}
+			if (!listed) {
+				slots[count] = slot;
+				count++;
+			}
+		}
+	}
+	return count;
+}
@@ -0,0 +1,28 @@
+// what every particle adds to tick_state.checksum, by sand_particle.glsl for the awake ones and
+// sand_activity.glsl for the rest. needs sand_random.glsl for pcg.
+// mirrored in simulation/rng.rs
+
+uint hash_particle(uint idx, Material m) {
+	uint h = pcg(idx);
+	h = pcg(h ^ floatBitsToUint(m.colour.r));
+	h = pcg(h ^ floatBitsToUint(m.colour.g));
+	h = pcg(h ^ floatBitsToUint(m.colour.b));
+	h = pcg(h ^ m.id);
+	h = pcg(h ^ floatBitsToUint(m.pos.x));
+	h = pcg(h ^ floatBitsToUint(m.pos.y));
+	h = pcg(h ^ floatBitsToUint(m.vel.x));
+	h = pcg(h ^ floatBitsToUint(m.vel.y));
+	h = pcg(h ^ floatBitsToUint(m.target.x));
+	h = pcg(h ^ floatBitsToUint(m.target.y));
+	h = pcg(h ^ floatBitsToUint(m.mass));
+	h = pcg(h ^ floatBitsToUint(m.force));
+	h = pcg(h ^ floatBitsToUint(m.stable));
+	h = pcg(h ^ m.tags);
+	h = pcg(h ^ m.gas);
+	h = pcg(h ^ m.kind);
+	h = pcg(h ^ floatBitsToUint(m.temperature));
+	h = pcg(h ^ m.body);
+	h = pcg(h ^ floatBitsToUint(m.rest.x));
+	h = pcg(h ^ floatBitsToUint(m.rest.y));
+	return h;
+}
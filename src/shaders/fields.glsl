@@ -0,0 +1,33 @@
+// force fields placed by the scene file or at runtime, summed into the acceleration sand_particle.glsl
+// integrates. needs `fields` bound first. mirrored in simulation/fields.rs
+
+const uint FIELD_NONE = 0u;// fills the unused slots, pushes nothing
+const uint FIELD_WIND = 1u;// the same acceleration everywhere inside a box
+const uint FIELD_ATTRACTOR = 2u;// towards pos, away from it with a negative strength
+const uint FIELD_VORTEX = 3u;// around pos, anticlockwise on screen with a positive strength
+
+vec2 field_acceleration(vec2 p) {
+	precise vec2 total = vec2(0.);
+	for (uint i = 0; i < fields.field.length(); i++) {
+		Field field = fields.field[i];
+		if (field.kind == FIELD_WIND) {
+			vec2 d = abs(p - field.pos);
+			if (d.x < field.half_size.x && d.y < field.half_size.y) {
+				total += field.acceleration;
+			}
+			continue;
+		}
+		if (field.kind != FIELD_ATTRACTOR && field.kind != FIELD_VORTEX) {
+			continue;
+		}
+		float r = distance(p, field.pos);
+		// right on the centre there's no direction to pull in
+		if (r >= field.radius || r == 0.) {
+			continue;
+		}
+		vec2 towards = (field.pos - p) / r;
+		vec2 direction = field.kind == FIELD_ATTRACTOR ? towards : vec2(-towards.y, towards.x);
+		total += direction * (field.strength * falloff_at(field.falloff, 1. - r / field.radius));
+	}
+	return total;
+}
@@ -0,0 +1,75 @@
+// signed distances to level geometry, negative inside. needs `shapes` and `vertices` bound first.
+// mirrored in simulation/geometry.rs
+
+const uint SHAPE_NONE = 0u;// fills the buffer when the scene has no geometry, never collides
+const uint SHAPE_BOX = 1u;
+const uint SHAPE_CIRCLE = 2u;
+const uint SHAPE_CAPSULE = 3u;
+const uint SHAPE_POLYGON = 4u;
+const uint SHAPE_BOUNDS = 5u;// a box turned inside out, keeps particles in
+
+const float NO_SHAPE_DISTANCE = 1e30;
+// pixels either side of a point when taking the gradient for the surface normal
+const float NORMAL_EPSILON = 0.5;
+
+// the sdfs are from https://iquilezles.org/articles/distfunctions2d/
+float box_distance(vec2 p, vec2 centre, vec2 half_size) {
+	vec2 d = abs(p - centre) - half_size;
+	return length(max(d, 0.)) + min(max(d.x, d.y), 0.);
+}
+
+float capsule_distance(vec2 p, vec2 a, vec2 b) {
+	vec2 pa = p - a;
+	vec2 ba = b - a;
+	// a == b is a circle, not a divide by zero
+	float h = dot(ba, ba) > 0. ? clamp(dot(pa, ba) / dot(ba, ba), 0., 1.) : 0.;
+	return length(pa - ba * h);
+}
+
+// any simple polygon, either winding
+float polygon_distance(vec2 p, uint first, uint count) {
+	vec2 start = vertices.v[first];
+	float d = dot(p - start, p - start);
+	float s = 1.;
+	for (uint i = 0, j = count - 1; i < count; j = i, i++) {
+		vec2 vi = vertices.v[first + i];
+		vec2 vj = vertices.v[first + j];
+		vec2 e = vj - vi;
+		vec2 w = p - vi;
+		// a repeated corner is an edge of length 0, the distance to the corner itself
+		vec2 b = w - e * (dot(e, e) > 0. ? clamp(dot(w, e) / dot(e, e), 0., 1.) : 0.);
+		d = min(d, dot(b, b));
+		bvec3 c = bvec3(p.y >= vi.y, p.y < vj.y, e.x * w.y > e.y * w.x);
+		if (all(c) || all(not(c))) {
+			s = -s;
+		}
+	}
+	return s * sqrt(d);
+}
+
+float shape_distance(Shape shape, vec2 p) {
+	switch (shape.kind) {
+		case SHAPE_BOX:
+			return box_distance(p, shape.a, shape.b) - shape.radius;
+		case SHAPE_CIRCLE:
+			return distance(p, shape.a) - shape.radius;
+		case SHAPE_CAPSULE:
+			return capsule_distance(p, shape.a, shape.b) - shape.radius;
+		case SHAPE_POLYGON:
+			return polygon_distance(p, shape.first_vertex, shape.vertex_count) - shape.radius;
+		case SHAPE_BOUNDS:
+			return -box_distance(p, shape.a, shape.b);
+		default:
+			return NO_SHAPE_DISTANCE;
+	}
+}
+
+// points out of the shape, zero where the gradient vanishes (the exact centre of a circle)
+vec2 shape_normal(Shape shape, vec2 p) {
+	vec2 dx = vec2(NORMAL_EPSILON, 0.);
+	vec2 dy = vec2(0., NORMAL_EPSILON);
+	vec2 gradient = vec2(shape_distance(shape, p + dx) - shape_distance(shape, p - dx),
+			shape_distance(shape, p + dy) - shape_distance(shape, p - dy));
+	float len = length(gradient);
+	return len > 0. ? gradient / len : vec2(0.);
+}
@@ -0,0 +1,122 @@
+// shared by every shader that reads the world, the rust side is generated from this
+// so a field added here shows up in sand_shader::Material (and needs a Default in sand.rs)
+
+#include "tags.glsl"
+
+struct Material {
+	vec3 colour;// 12
+	uint id;// 16
+	vec2 pos;// 24
+	vec2 vel;// 32
+	vec2 target;// 40
+	float mass;// 44
+	float force;// 48
+	float stable;// 52 the impulse it takes to knock a TAG_STATIC particle loose or one off its body
+	uint tags;// 56 TAG_* bits, see tags.glsl
+	uint gas;// 60
+	uint kind;// 64 index into the kind table, see simulation/materials.rs
+	float temperature;// 68 celsius
+	uint body;// 72 the rigid body it's part of, 0 for none, see sand_body.glsl
+	vec2 rest;// 80 where it sits in its body, from the centre of mass before any rotation
+};// 80 in an array too, hence PADDING = 0
+
+// per kind properties, one entry per simulation::materials::KINDS
+struct Kind {
+	vec3 colour;// 12
+	float conductivity;// 16 how fast heat moves to and from neighbours
+	float heat_capacity;// 20 how much heat it takes to change temperature
+	float fixed_temperature;// 24 with TAG_FIXED_TEMPERATURE, and what an ignited particle starts burning at
+	uint colder_kind;// 28 turns into this below colder_below
+	float colder_below;// 32
+	uint hotter_kind;// 36 turns into this above hotter_above
+	float hotter_above;// 40
+	uint gas;// 44
+	uint tags;// 48 what particles of this kind get in Material.tags
+	uint solver;// 52 SOLVER_*, how particles of this kind move
+	float rest_density;// 56 sph only, pressure pushes towards this
+	float stiffness;// 60 sph only, pressure per unit of density above rest
+	float viscosity;// 64 sph only, how much neighbours' velocities are averaged in
+	float stable;// 68 what particles of this kind get in Material.stable
+};// 80 in an array, hence KIND_PADDING = 12
+
+// one chunk slot's or body's range of cell_entries.index, see cells.glsl
+struct Cell {
+	uint count;// 4 particles filed under it this tick, until sand_cells.glsl turns it into start and end
+	uint start;// 8 first entry
+	uint end;// 12 one past the last entry, once sand_activity.glsl has filed them all
+};
+
+const uint SOLVER_GRANULAR = 0u;// every particle moves on its own
+const uint SOLVER_SPH = 1u;// moves as a fluid with the other sph particles, see sph.glsl
+
+// one per (kind, neighbour kind) pair, indexed kind * kind count + neighbour kind, see simulation/reactions.rs
+struct Reaction {
+	uint product;// 4 what this particle turns into
+	float probability;// 8 per tick, 0 for pairs that don't react
+	float min_temperature;// 12 the hotter of the two has to be at least this
+};
+
+// one piece of level geometry, see geometry.glsl for the SHAPE_* kinds and simulation/geometry.rs
+struct Shape {
+	vec2 a;// 8 centre, or a capsule's first end
+	vec2 b;// 16 half size of a box or bounds, or a capsule's other end
+	float radius;// 20 of a circle or capsule, rounds the corners of boxes and polygons
+	uint kind;// 24 SHAPE_*
+	uint first_vertex;// 28 polygons only, into the vertex buffer
+	uint vertex_count;// 32
+	float restitution;// 36 fraction of the speed into the surface that bounces back
+	float friction;// 40 fraction of the speed along the surface lost on contact
+};
+
+// a radial push queued with World::impulse, see sand_impulse.glsl and simulation/impulse.rs
+struct Impulse {
+	vec2 pos;// 8 centre
+	float radius;// 12 nothing at or past this is touched
+	float strength;// 16 impulse at the centre, a particle's velocity changes by this over its mass
+	uint falloff;// 20 FALLOFF_*
+	uint effects;// 24 IMPULSE_* bits
+};
+
+// what the sleep stage remembers about a particle slot between ticks, see sand_sleep.glsl
+struct Sleeper {
+	uint id;// 4 of the particle it was about, a new particle in the slot counts as moving
+	uint kind;// 8 as of the last tick
+	float temperature;// 12 as of the last tick
+	uint still;// 16 ticks in a row it hasn't moved, asleep once it reaches Params.sleep_ticks
+	uint busy;// 20 the reaction stage found it a partner it could react with, which keeps it awake
+};
+
+// a force field, see fields.glsl for the FIELD_* kinds and simulation/fields.rs
+struct Field {
+	vec2 pos;// 8 centre
+	vec2 half_size;// 16 wind only, the box it blows in
+	vec2 acceleration;// 24 wind only
+	float radius;// 28 attractors and vortices, nothing at or past this is pulled
+	float strength;// 32 attractors and vortices, pixels/s² at the centre
+	uint falloff;// 36 FALLOFF_*, attractors and vortices
+	uint kind;// 40 FIELD_*
+};
+
+const uint FALLOFF_NONE = 0u;// full strength out to the radius
+const uint FALLOFF_LINEAR = 1u;
+const uint FALLOFF_QUADRATIC = 2u;
+
+// how much of an impulse or field is left at t, 1 at its centre down to 0 at its radius
+float falloff_at(uint falloff, float t) {
+	return falloff == FALLOFF_LINEAR ? t : falloff == FALLOFF_QUADRATIC ? t * t : 1.;
+}
+
+const uint IMPULSE_DEBRIS = 1u << 0;// particles it knocks loose turn into powder
+const uint IMPULSE_IGNITE = 1u << 1;// flammable particles in range catch fire
+
+// one rigid body, fitted to its particles every tick by sand_body.glsl. indexed by Material.body
+struct Body {
+	vec2 centre;// 8 centre of mass
+	vec2 vel;// 16 of the centre of mass
+	vec2 rotation;// 24 cos and sin of the angle from the particles' rest positions
+	float spin;// 28 radians/s, clockwise on screen as +y is down
+	float mass;// 32 0 once every particle has come off
+};
+
+// simulation/bodies.rs MAX_BODIES, body numbers stay below it
+const uint MAX_BODIES = 1024u;
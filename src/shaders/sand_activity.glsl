@@ -0,0 +1,45 @@
+#version 450
+
+// third stage of the tick, after sand_cells.glsl. files every live particle into the cell list, lists every
+// particle that's awake this tick in active_list.index and counts the work groups they need into active_dispatch,
+// every stage after the cell sort but the body fit only runs for them. the ones left asleep are exactly
+// as they were, so they go into the checksum here
+
+#include "material.glsl"
+
+layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+#include "sand_bindings.glsl"
+#include "sand_random.glsl"
+#include "cells.glsl"
+#include "checksum.glsl"
+
+void main() {
+	uint idx = gl_GlobalInvocationID.x;
+	Material m = buf.mat[idx];
+	if (m.id == 0) {
+		return;// empty slot
+	}
+	// filed in whatever order the invocations get here, sand_cell_sort.glsl sorts every cell after
+	uint chunk = chunk_slot(chunk_of(m.pos));
+	cell_entries.index[atomicAdd(cells.cell[chunk].end, 1u)] = idx;
+	if (m.body != 0u) {
+		cell_entries.index[atomicAdd(cells.cell[body_cell(m.body)].end, 1u)] = idx;
+	}
+	// something moved next to it, it stays up a full sleep_ticks in case it's about to be pushed
+	bool woken = chunks.woken[chunk] == tick_state.tick + 1u;
+	if (woken) {
+		sleepers.sleeper[idx].still = 0u;
+	}
+	uint still = sleepers.sleeper[idx].still;
+	if (params.sleep_ticks != 0u && still >= params.sleep_ticks) {
+		atomicXor(tick_state.checksum, hash_particle(idx, m));
+		return;// asleep
+	}
+	uint slot = atomicAdd(tick_state.awake, 1u);
+	active_list.index[slot] = idx;
+	// whoever starts a work group's worth of particles asks for that work group, 64 is local_size_x
+	if (slot % 64u == 0u) {
+		atomicAdd(active_dispatch.x, 1u);
+	}
+}
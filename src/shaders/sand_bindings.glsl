@@ -0,0 +1,153 @@
+// bindings every stage of the tick shares, see World::new for what's bound where
+
+layout(binding = 0) buffer Data {
+	Material mat[];
+}
+buf;
+
+// from the config file, see config.rs
+layout(binding = 1) uniform Params {
+	vec2 gravity;// pixels/s², +y is down the screen
+	float damping;// fraction of the velocity kept every tick
+	float timestep;// seconds per tick
+	float jitter;// pixels/s of random velocity added every tick
+	float heat_radius;// pixels, particles closer than this exchange heat
+	float reaction_radius;// pixels, particles closer than this can react
+	float sph_radius;// pixels, the sph smoothing length
+	float sleep_speed;// pixels/s, anything slower counts as still
+	uint sleep_ticks;// still for this many ticks in a row and it sleeps, 0 never
+}
+params;
+
+// simulation/impulse.rs MAX_IMPULSES, a tick applies at most this many and leaves the rest for the next
+const uint MAX_IMPULSES = 16u;
+
+// one per frame slot, written by World::tick before every submission and read back after its fence
+layout(binding = 2) buffer TickState {
+	uint tick;
+	uint seed;
+	uint checksum;// xor of every particle's hash after this tick, so the order invocations finish in doesn't matter
+	uint impulse_count;
+	uint wake_all;// not 0 after anything that changes how every particle moves, a new config or force fields
+	uint awake;// counted up by sand_activity.glsl, the particles every stage after it runs for
+	uint bodies;// World's next body number, the body fit runs for every body below it
+	Impulse impulses[MAX_IMPULSES];// the first impulse_count are this tick's, see sand_impulse.glsl
+}
+tick_state;
+
+layout(binding = 3) readonly buffer Kinds {
+	Kind kinds[];
+}
+kind_table;
+
+// the heat stage writes every particle's next temperature here instead of into the world, so
+// neighbours read this tick's temperatures no matter which invocation runs first
+layout(binding = 4) buffer Heat {
+	float temperature[];
+}
+heat;
+
+// CELL_COUNT cells, every chunk slot's range of cell_entries then every body's. see cells.glsl
+layout(binding = 5) buffer Cells {
+	Cell cell[];
+}
+cells;
+
+// the slots of every live particle grouped by chunk slot, then of every body particle grouped by body,
+// in slot order within each cell. twice the world's length
+layout(binding = 6) buffer CellEntries {
+	uint index[];
+}
+cell_entries;
+
+layout(binding = 7) readonly buffer Reactions {
+	Reaction reactions[];
+}
+reaction_table;
+
+// what the reaction stage turned every particle into, applied by sand_particle.glsl like heat
+layout(binding = 8) buffer Reacted {
+	uint kind[];
+}
+reacted;
+
+// level geometry from the scene file, see geometry.glsl
+layout(binding = 9) readonly buffer Shapes {
+	Shape shape[];
+}
+shapes;
+
+// every polygon's corners, back to back
+layout(binding = 10) readonly buffer Vertices {
+	vec2 v[];
+}
+vertices;
+
+// the sph density stage's results, density then pressure, 0 for particles that aren't sph
+layout(binding = 11) buffer Density {
+	vec2 density_pressure[];
+}
+sph_density;
+
+// the sph force stage's results, applied by sand_particle.glsl
+layout(binding = 12) buffer Acceleration {
+	vec2 acceleration[];
+}
+sph_force;
+
+// MAX_FIELDS force fields, unused slots are FIELD_NONE, see fields.glsl
+layout(binding = 13) readonly buffer Fields {
+	Field field[];
+}
+fields;
+
+// the body stage's fit of every rigid body, indexed by Material.body so entry 0 is never used. MAX_BODIES of them
+layout(binding = 14) buffer Bodies {
+	Body body[];
+}
+bodies;
+
+// the work groups the body fit needs, one invocation per body number below tick_state.bodies.
+// written by sand_sleep.glsl
+layout(binding = 15) buffer BodyDispatch {
+	uint x;
+	uint y;
+	uint z;
+}
+body_dispatch;
+
+// what the sleep stage remembers about every slot, see sand_sleep.glsl
+layout(binding = 16) buffer Sleepers {
+	Sleeper sleeper[];
+}
+sleepers;
+
+// tick + 1 for every chunk something moved in or next to that tick, CHUNK_COUNT of them. see sand_sleep.glsl
+layout(binding = 17) buffer Chunks {
+	uint woken[];
+}
+chunks;
+
+// the slots of this tick's awake particles, tick_state.awake of them in no particular order
+layout(binding = 18) buffer Active {
+	uint index[];
+}
+active_list;
+
+// the work groups the awake particles need, counted by sand_activity.glsl. the stages after it are dispatched from this
+layout(binding = 19) buffer ActiveDispatch {
+	uint x;
+	uint y;
+	uint z;
+}
+active_dispatch;
+
+// the slot this invocation handles in a stage dispatched from active_dispatch, false past the last awake particle
+bool awake_slot(out uint idx) {
+	idx = 0u;
+	if (gl_GlobalInvocationID.x >= tick_state.awake) {
+		return false;
+	}
+	idx = active_list.index[gl_GlobalInvocationID.x];
+	return true;
+}
@@ -0,0 +1,58 @@
+#version 450
+
+// tenth stage of the tick, shape matching. one invocation per body number below tick_state.bodies, each sums
+// up its body's cell: the particles' centre of mass, velocity and the rotation that best lines their rest
+// positions up with where they are now. sand_particle.glsl then moves them all together from this
+
+#include "material.glsl"
+
+layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+#include "sand_bindings.glsl"
+#include "sand_random.glsl"
+#include "cells.glsl"
+
+float cross2(vec2 a, vec2 b) {
+	precise float c = a.x * b.y - a.y * b.x;
+	return c;
+}
+
+void main() {
+	uint b = gl_GlobalInvocationID.x;
+	if (b == 0u || b >= tick_state.bodies) {
+		return;// not a body, or past the last one
+	}
+	// the cell is in slot order, so the sums are too
+	Cell cell = cells.cell[body_cell(b)];
+	precise float mass = 0.;
+	precise vec2 momentum = vec2(0.);
+	precise vec2 moment = vec2(0.);
+	for (uint e = cell.start; e < cell.end; e++) {
+		Material m = buf.mat[cell_entries.index[e]];
+		mass += m.mass;
+		moment += m.pos * m.mass;
+		momentum += m.vel * m.mass;
+	}
+	if (mass == 0.) {
+		bodies.body[b] = Body(vec2(0.), vec2(0.), vec2(1., 0.), 0., 0.);
+		return;
+	}
+	precise vec2 centre = moment / mass;
+	precise vec2 vel = momentum / mass;
+
+	// the best rotation in 2d is the angle of the mass weighted sum of rest · offset and rest × offset
+	precise vec2 fit = vec2(0.);
+	precise float inertia = 0.;
+	precise float angular_momentum = 0.;
+	for (uint e = cell.start; e < cell.end; e++) {
+		Material m = buf.mat[cell_entries.index[e]];
+		vec2 offset = m.pos - centre;
+		fit += vec2(dot(m.rest, offset), cross2(m.rest, offset)) * m.mass;
+		inertia += dot(offset, offset) * m.mass;
+		angular_momentum += cross2(offset, m.vel - vel) * m.mass;
+	}
+	// a single particle, or every particle on the centre, can't be turned
+	vec2 rotation = fit == vec2(0.) ? vec2(1., 0.) : fit / length(fit);
+	float spin = inertia == 0. ? 0. : angular_momentum / inertia;
+	bodies.body[b] = Body(centre, vel, rotation, spin, mass);
+}
@@ -0,0 +1,31 @@
+#version 450
+
+// fourth stage of the tick, one invocation per cell. sand_activity.glsl filed the particles in whatever order
+// its invocations ran, this puts every cell's entries in slot order so neighbour sums don't depend on it
+
+#include "material.glsl"
+
+layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+#include "sand_bindings.glsl"
+#include "sand_random.glsl"
+#include "cells.glsl"
+
+void main() {
+	uint c = gl_GlobalInvocationID.x;
+	if (c >= CELL_COUNT) {
+		return;
+	}
+	Cell cell = cells.cell[c];
+	// insertion sort, a chunk slot's cell only holds the few particles in a chunk or two and a body's
+	// the few dozen it was made of
+	for (uint i = cell.start + 1u; i < cell.end; i++) {
+		uint idx = cell_entries.index[i];
+		uint j = i;
+		while (j > cell.start && cell_entries.index[j - 1u] > idx) {
+			cell_entries.index[j] = cell_entries.index[j - 1u];
+			j--;
+		}
+		cell_entries.index[j] = idx;
+	}
+}
@@ -0,0 +1,38 @@
+#version 450
+
+// second stage of the tick, one work group. turns the counts sand_sleep.glsl left in every cell into where
+// its entries go, back to back in chunk slot order, and resets the counts for the next tick. see cells.glsl
+
+#include "material.glsl"
+
+layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+#include "sand_bindings.glsl"
+#include "sand_random.glsl"
+#include "cells.glsl"
+
+// every invocation sums a run of CELL_COUNT / 64 cells, then starts after the runs before it
+const uint CELLS_PER_INVOCATION = CELL_COUNT / 64u;
+
+shared uint run_total[64];
+
+void main() {
+	uint first = gl_LocalInvocationID.x * CELLS_PER_INVOCATION;
+	uint total = 0u;
+	for (uint c = first; c < first + CELLS_PER_INVOCATION; c++) {
+		total += cells.cell[c].count;
+	}
+	run_total[gl_LocalInvocationID.x] = total;
+	memoryBarrierShared();
+	barrier();
+	uint start = 0u;
+	for (uint i = 0u; i < gl_LocalInvocationID.x; i++) {
+		start += run_total[i];
+	}
+	for (uint c = first; c < first + CELLS_PER_INVOCATION; c++) {
+		uint count = cells.cell[c].count;
+		// end counts up to start + count as sand_activity.glsl files the particles
+		cells.cell[c] = Cell(0u, start, start);
+		start += count;
+	}
+}
@@ -0,0 +1,48 @@
+#version 450
+
+// seventh stage of the tick, every sph particle's density from its sph neighbours and the pressure that
+// pushes it back towards its kind's rest density. positions are from before the tick
+
+#include "material.glsl"
+
+layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+#include "sand_bindings.glsl"
+#include "sand_random.glsl"
+#include "cells.glsl"
+#include "sph.glsl"
+
+void main() {
+	uint idx;
+	if (!awake_slot(idx)) {
+		return;
+	}
+	Material m = buf.mat[idx];
+	if (!is_sph(m)) {
+		sph_density.density_pressure[idx] = vec2(0.);
+		return;
+	}
+	Kind kind = kind_table.kinds[m.kind];
+	float h = params.sph_radius;
+	// the particle itself counts too, so the density is never 0. summed in cell list order
+	precise float density = 0.;
+	uint slots[9];
+	uint slot_count = neighbour_slots(m.pos, slots);
+	for (uint s = 0u; s < slot_count; s++) {
+		Cell cell = cells.cell[slots[s]];
+		for (uint e = cell.start; e < cell.end; e++) {
+			Material n = buf.mat[cell_entries.index[e]];
+			if (!is_sph(n)) {
+				continue;
+			}
+			float r = distance(m.pos, n.pos);
+			if (r >= h) {
+				continue;
+			}
+			density += n.mass * poly6(r, h);
+		}
+	}
+	// only pushes apart, pulling together makes particles clump
+	precise float pressure = kind.stiffness * max(density - kind.rest_density, 0.);
+	sph_density.density_pressure[idx] = vec2(density, pressure);
+}
@@ -0,0 +1,52 @@
+#version 450
+
+// fifth stage of the tick, after the sleep and cell list stages. heat flows between neighbours and the result
+// goes to heat.temperature, sand_particle.glsl picks it up and does the phase changes
+
+#include "material.glsl"
+
+layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+#include "sand_bindings.glsl"
+#include "sand_random.glsl"
+#include "cells.glsl"
+
+void main() {
+	uint idx;
+	if (!awake_slot(idx)) {
+		return;
+	}
+	Material m = buf.mat[idx];
+	Kind kind = kind_table.kinds[m.kind];
+	if (has_tag(m.tags, TAG_FIXED_TEMPERATURE)) {
+		heat.temperature[idx] = kind.fixed_temperature;
+		return;
+	}
+	if (!has_tag(m.tags, TAG_CONDUCTIVE)) {
+		heat.temperature[idx] = m.temperature;
+		return;
+	}
+	// every neighbour is summed in cell list order, so the result doesn't depend on scheduling
+	precise float flow = 0.;
+	uint slots[9];
+	uint slot_count = neighbour_slots(m.pos, slots);
+	for (uint s = 0u; s < slot_count; s++) {
+		Cell cell = cells.cell[slots[s]];
+		for (uint e = cell.start; e < cell.end; e++) {
+			uint other = cell_entries.index[e];
+			Material n = buf.mat[other];
+			if (other == idx || !has_tag(n.tags, TAG_CONDUCTIVE)) {
+				continue;
+			}
+			float dist = distance(m.pos, n.pos);
+			if (dist >= params.heat_radius) {
+				continue;
+			}
+			// the worse conductor of the pair limits the flow, closer neighbours exchange more
+			precise float conductance = min(kind.conductivity, kind_table.kinds[n.kind].conductivity) * (1. - dist / params.heat_radius);
+			flow += conductance * (n.temperature - m.temperature);
+		}
+	}
+	precise float temperature = m.temperature + flow * params.timestep / kind.heat_capacity;
+	heat.temperature[idx] = temperature;
+}
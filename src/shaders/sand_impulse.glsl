@@ -0,0 +1,58 @@
+#version 450
+
+// ninth stage of the tick, the impulses queued with World::impulse. every particle only reads and writes
+// its own slot, so this goes straight into the world and sand_particle.glsl integrates the new velocity
+
+#include "material.glsl"
+
+layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+#include "sand_bindings.glsl"
+
+void main() {
+	uint idx;
+	if (!awake_slot(idx)) {
+		return;
+	}
+	Material m = buf.mat[idx];
+	if (tick_state.impulse_count == 0u || has_tag(m.tags, TAG_INDESTRUCTIBLE)) {
+		return;
+	}
+	for (uint i = 0; i < min(tick_state.impulse_count, MAX_IMPULSES); i++) {
+		Impulse impulse = tick_state.impulses[i];
+		float d = distance(m.pos, impulse.pos);
+		if (d >= impulse.radius) {
+			continue;
+		}
+		precise float strength = impulse.strength * falloff_at(impulse.falloff, 1. - d / impulse.radius);
+		// through the reaction scratch so the phase change in sand_particle.glsl picks it up, and hot
+		// enough that the fire doesn't go straight out
+		if (has_tag(impulse.effects, IMPULSE_IGNITE) && has_tag(m.tags, TAG_FLAMMABLE)) {
+			reacted.kind[idx] = KIND_FIRE;
+			heat.temperature[idx] = max(heat.temperature[idx], kind_table.kinds[KIND_FIRE].fixed_temperature);
+		}
+		bool knocked_loose = false;
+		if (has_tag(m.tags, TAG_STATIC) && m.body == 0u) {
+			if (strength <= m.stable) {
+				continue;// holds
+			}
+			m.tags &= ~TAG_STATIC;
+			knocked_loose = true;
+		}
+		// a weaker push moves the whole body, the next body fit spreads it over the others
+		if (m.body != 0u && strength > m.stable) {
+			m.body = 0u;
+			m.tags &= ~TAG_STATIC;
+			knocked_loose = true;
+		}
+		if (knocked_loose && has_tag(impulse.effects, IMPULSE_DEBRIS)) {
+			m.tags = (m.tags | TAG_POWDER) & ~TAG_STICKY;
+		}
+		// right on the centre there's no direction to push in
+		if (d > 0.) {
+			precise vec2 vel = m.vel + (m.pos - impulse.pos) / d * (strength / m.mass);
+			m.vel = vel;
+		}
+	}
+	buf.mat[idx] = m;
+}
@@ -1,27 +1,103 @@
 #version 450
 
-struct Material {
-	vec3 colour;// 12
-	uint id;// 16
-	vec2 pos;// 24
-	vec2 vel;// 32
-	vec2 target;// 40
-	float mass;// 44
-	float force;// 48
-	float stable;// 52
-	uint tags;// 56
-	uint gas;// 60
-};
+#include "material.glsl"
 
 layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
 
-layout(binding = 0) buffer Data {
-Material mat[];
+#include "sand_bindings.glsl"
+#include "sand_random.glsl"
+#include "geometry.glsl"
+#include "fields.glsl"
+#include "checksum.glsl"
+
+// pushes the particle out of every shape it ended up in and bounces it off the surface, in scene order
+void collide(inout Material m) {
+	for (uint i = 0; i < shapes.shape.length(); i++) {
+		Shape shape = shapes.shape[i];
+		float d = shape_distance(shape, m.pos);
+		if (d >= 0.) {
+			continue;
+		}
+		vec2 normal = shape_normal(shape, m.pos);
+		precise vec2 pos = m.pos - normal * d;
+		m.pos = pos;
+		float into = dot(m.vel, normal);
+		if (into >= 0.) {
+			continue;// already leaving
+		}
+		// hit hard enough it comes off its body, and stays loose wherever it lands
+		if (m.body != 0u && -into * m.mass > m.stable) {
+			m.body = 0u;
+			m.tags &= ~TAG_STATIC;
+		}
+		// powder doesn't bounce, liquid slides, sticky stops dead
+		float restitution = has_tag(m.tags, TAG_POWDER) ? 0. : shape.restitution;
+		float friction = has_tag(m.tags, TAG_LIQUID) ? 0. : shape.friction;
+		precise vec2 along = m.vel - normal * into;
+		precise vec2 vel = along * (1. - friction) - normal * into * restitution;
+		m.vel = has_tag(m.tags, TAG_STICKY) ? vec2(0.) : vel;
+	}
 }
-buf;
 
 void main() {
-	uint idx = gl_GlobalInvocationID.x;
-	buf.mat[idx].tags += 1;
-	// buf.mat[idx].colour+=vec3(0.1);
-}
\ No newline at end of file
+	uint idx;
+	if (!awake_slot(idx)) {
+		return;
+	}
+	Material m = buf.mat[idx];
+	if (m.body != 0u) {
+		// the body moves as one from the body stage's fit, then every particle collides on its own and
+		// the next fit picks up what that did to the body
+		Body body = bodies.body[m.body];
+		precise vec2 vel = (body.vel + (params.gravity + field_acceleration(body.centre)) * params.timestep) * params.damping;
+		precise vec2 centre = body.centre + vel * params.timestep;
+		precise float spin = body.spin * params.damping;
+		// turning by a small angle, renormalised so it stays a rotation
+		precise vec2 turned = body.rotation + vec2(-body.rotation.y, body.rotation.x) * (spin * params.timestep);
+		vec2 rotation = turned / length(turned);
+		precise vec2 offset = vec2(rotation.x * m.rest.x - rotation.y * m.rest.y, rotation.y * m.rest.x + rotation.x * m.rest.y);
+		precise vec2 pos = centre + offset;
+		precise vec2 particle_vel = vel + vec2(-offset.y, offset.x) * spin;
+		m.pos = pos;
+		m.vel = particle_vel;
+		collide(m);
+	} else if (has_tag(m.tags, TAG_STATIC)) {
+		m.vel = vec2(0.);
+	} else {
+		// precise stops the compiler fusing into fma, which rounds differently per driver and from the cpu
+		precise vec2 kick = vec2(random(m.id, 0u), random(m.id, 1u)) * 2. - 1.;
+		// sph_force.acceleration is 0 for anything that isn't sph
+		vec2 acceleration = params.gravity + sph_force.acceleration[idx] + field_acceleration(m.pos);
+		precise vec2 vel = (m.vel + acceleration * params.timestep + kick * params.jitter) * params.damping;
+		precise vec2 pos = m.pos + vel * params.timestep;
+		m.vel = vel;
+		m.pos = pos;
+		collide(m);
+	}
+
+	// phase changes happen after the heat and reaction stages so they see this tick's temperature,
+	// and apply to whatever the particle reacted into
+	m.temperature = heat.temperature[idx];
+	uint next_kind = reacted.kind[idx];
+	Kind kind = kind_table.kinds[next_kind];
+	if (has_tag(m.tags, TAG_INDESTRUCTIBLE)) {
+		// the reaction stage already left it alone
+	} else if (has_tag(m.tags, TAG_FLAMMABLE) && m.temperature > IGNITION_TEMPERATURE) {
+		next_kind = KIND_FIRE;
+	} else if (m.temperature < kind.colder_below) {
+		next_kind = kind.colder_kind;
+	} else if (m.temperature > kind.hotter_above) {
+		next_kind = kind.hotter_kind;
+	}
+	// a new kind brings its own tags, so a melted or burnt particle behaves like what it became
+	if (next_kind != m.kind) {
+		m.kind = next_kind;
+		m.colour = kind_table.kinds[next_kind].colour;
+		m.gas = kind_table.kinds[next_kind].gas;
+		m.tags = kind_table.kinds[next_kind].tags;
+		m.stable = kind_table.kinds[next_kind].stable;
+	}
+	// m.colour+=vec3(0.1);
+	buf.mat[idx] = m;
+	atomicXor(tick_state.checksum, hash_particle(idx, m));
+}
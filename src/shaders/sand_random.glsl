@@ -0,0 +1,16 @@
+// shared by every stage that draws random numbers, needs sand_bindings.glsl for tick_state.
+// mirrored in simulation/rng.rs
+
+// pcg hash, see "Hash Functions for GPU Rendering" (Jarzynski & Olano 2020)
+uint pcg(uint v) {
+	uint state = v * 747796405u + 2891336453u;
+	uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+	return (word >> 22u) ^ word;
+}
+
+// counter based so it only depends on the seed, the particle, the tick and which draw this is,
+// never on dispatch order. [0, 1)
+float random(uint id, uint stream) {
+	uint h = pcg(tick_state.seed ^ pcg(tick_state.tick ^ pcg(id ^ pcg(stream))));
+	return float(h >> 8u) / 16777216.;
+}
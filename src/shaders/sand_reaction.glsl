@@ -0,0 +1,68 @@
+#version 450
+
+// sixth stage of the tick, after sand_heat.glsl. every particle looks through its neighbours for one
+// the reaction table pairs it with and writes what it turns into to reacted.kind,
+// sand_particle.glsl applies it. positions and kinds are read from before the tick
+
+#include "material.glsl"
+
+layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+#include "sand_bindings.glsl"
+#include "sand_random.glsl"
+#include "cells.glsl"
+
+void main() {
+	uint idx;
+	if (!awake_slot(idx)) {
+		return;
+	}
+	Material m = buf.mat[idx];
+	uint kind_count = kind_table.kinds.length();
+	uint product = m.kind;
+	if (has_tag(m.tags, TAG_INDESTRUCTIBLE)) {
+		reacted.kind[idx] = product;
+		sleepers.sleeper[idx].busy = 0u;
+		return;
+	}
+	// the neighbour with the lowest slot that has a rule is the partner, whether or not they react this tick.
+	// in a crowd the partner may have picked someone else, then only this side changes
+	uint partner = 0xffffffffu;
+	uint slots[9];
+	uint slot_count = neighbour_slots(m.pos, slots);
+	for (uint s = 0u; s < slot_count; s++) {
+		Cell cell = cells.cell[slots[s]];
+		for (uint e = cell.start; e < cell.end; e++) {
+			uint other = cell_entries.index[e];
+			// entries are in slot order, nothing further in this cell can beat the partner so far
+			if (other >= partner) {
+				break;
+			}
+			Material n = buf.mat[other];
+			if (other == idx) {
+				continue;
+			}
+			Reaction reaction = reaction_table.reactions[m.kind * kind_count + n.kind];
+			if (reaction.probability > 0. && distance(m.pos, n.pos) < params.reaction_radius) {
+				partner = other;
+				break;
+			}
+		}
+	}
+	bool busy = false;
+	if (partner != 0xffffffffu) {
+		Material n = buf.mat[partner];
+		Reaction reaction = reaction_table.reactions[m.kind * kind_count + n.kind];
+		// an asleep neighbour's heat result is still the one it went to sleep with, which is its temperature
+		float hottest = max(heat.temperature[idx], heat.temperature[partner]);
+		// keyed on both ids, smallest first, so both sides of a pair roll the same number
+		uint pair = pcg(min(m.id, n.id) ^ pcg(max(m.id, n.id)));
+		// it could react any tick now, so it stays awake for the roll
+		busy = hottest >= reaction.min_temperature;
+		if (busy && random(pair, 2u) < reaction.probability) {
+			product = reaction.product;
+		}
+	}
+	reacted.kind[idx] = product;
+	sleepers.sleeper[idx].busy = busy ? 1u : 0u;
+}
@@ -0,0 +1,77 @@
+#version 450
+
+// first stage of the tick, one invocation per slot. a particle that hasn't moved for Params.sleep_ticks ticks
+// falls asleep, one that moved stamps its chunk and the 8 around it so sand_activity.glsl wakes its neighbours.
+// editing the world counts as moving: a new particle in a slot, an impulse over it or tick_state.wake_all.
+// every live particle, asleep or not, is counted into its cell for the cell list, see cells.glsl
+
+#include "material.glsl"
+
+layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+#include "sand_bindings.glsl"
+#include "sand_random.glsl"
+#include "cells.glsl"
+
+const float SLEEP_TEMPERATURE = 0.01;// celsius, changing more than this in a tick counts as moving, simulation/sleep.rs
+
+bool under_impulse(vec2 pos) {
+	for (uint i = 0; i < min(tick_state.impulse_count, MAX_IMPULSES); i++) {
+		if (distance(pos, tick_state.impulses[i].pos) < tick_state.impulses[i].radius) {
+			return true;
+		}
+	}
+	return false;
+}
+
+void main() {
+	uint idx = gl_GlobalInvocationID.x;
+	if (idx == 0u) {
+		// nothing reads it before sand_activity.glsl counts the work groups up again
+		active_dispatch.x = 0u;
+		active_dispatch.y = 1u;
+		active_dispatch.z = 1u;
+		// 64 is sand_body.glsl's local_size_x
+		body_dispatch.x = (tick_state.bodies + 63u) / 64u;
+		body_dispatch.y = 1u;
+		body_dispatch.z = 1u;
+	}
+	Material m = buf.mat[idx];
+	if (m.id == 0) {
+		return;// empty slot
+	}
+	ivec2 chunk = chunk_of(m.pos);
+	atomicAdd(cells.cell[chunk_slot(chunk)].count, 1u);
+	if (m.body != 0u) {
+		atomicAdd(cells.cell[body_cell(m.body)].count, 1u);
+	}
+	Sleeper sleeper = sleepers.sleeper[idx];
+	precise float speed_squared = m.vel.x * m.vel.x + m.vel.y * m.vel.y;
+	// anything that changes what the neighbours see wakes them too
+	bool moving = tick_state.wake_all != 0u
+		|| sleeper.id != m.id
+		|| sleeper.kind != m.kind
+		|| abs(m.temperature - sleeper.temperature) > SLEEP_TEMPERATURE
+		|| speed_squared > params.sleep_speed * params.sleep_speed
+		|| under_impulse(m.pos);
+	// sph neighbours read each other's density from this tick and a body is fitted from all its particles,
+	// so neither ever sleeps
+	bool restless = moving
+		|| sleeper.busy != 0u
+		|| m.body != 0u
+		|| kind_table.kinds[m.kind].solver == SOLVER_SPH;
+	sleeper.id = m.id;
+	sleeper.kind = m.kind;
+	sleeper.temperature = m.temperature;
+	sleeper.still = restless ? 0u : min(sleeper.still + 1u, params.sleep_ticks);
+	sleepers.sleeper[idx] = sleeper;
+	if (!moving) {
+		return;
+	}
+	// every invocation writes the same stamp, so the order they land in doesn't matter
+	for (int y = -1; y <= 1; y++) {
+		for (int x = -1; x <= 1; x++) {
+			chunks.woken[chunk_slot(chunk + ivec2(x, y))] = tick_state.tick + 1u;
+		}
+	}
+}
@@ -0,0 +1,54 @@
+#version 450
+
+// eighth stage of the tick, pressure and viscosity between sph neighbours from the density stage's results,
+// sand_particle.glsl adds the acceleration to the particle's velocity
+
+#include "material.glsl"
+
+layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+#include "sand_bindings.glsl"
+#include "sand_random.glsl"
+#include "cells.glsl"
+#include "sph.glsl"
+
+void main() {
+	uint idx;
+	if (!awake_slot(idx)) {
+		return;
+	}
+	Material m = buf.mat[idx];
+	if (!is_sph(m)) {
+		sph_force.acceleration[idx] = vec2(0.);
+		return;
+	}
+	Kind kind = kind_table.kinds[m.kind];
+	float h = params.sph_radius;
+	vec2 own = sph_density.density_pressure[idx];
+	precise vec2 pressure = vec2(0.);
+	precise vec2 viscosity = vec2(0.);
+	// summed in cell list order like the density
+	uint slots[9];
+	uint slot_count = neighbour_slots(m.pos, slots);
+	for (uint s = 0u; s < slot_count; s++) {
+		Cell cell = cells.cell[slots[s]];
+		for (uint e = cell.start; e < cell.end; e++) {
+			uint other = cell_entries.index[e];
+			Material n = buf.mat[other];
+			if (other == idx || !is_sph(n)) {
+				continue;
+			}
+			float r = distance(m.pos, n.pos);
+			// on top of each other there's no direction to push in
+			if (r >= h || r == 0.) {
+				continue;
+			}
+			vec2 theirs = sph_density.density_pressure[other];
+			vec2 away = (m.pos - n.pos) / r;
+			pressure += away * (n.mass * (own.y + theirs.y) / (2. * theirs.x) * spiky_gradient(r, h));
+			viscosity += (n.vel - m.vel) * (n.mass / theirs.x * viscosity_laplacian(r, h));
+		}
+	}
+	precise vec2 acceleration = (pressure + viscosity * kind.viscosity) / own.x;
+	sph_force.acceleration[idx] = acceleration;
+}
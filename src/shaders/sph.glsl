@@ -0,0 +1,29 @@
+// 2d sph kernels, h is params.sph_radius and they only hold for r < h, callers skip anything further.
+
+const float PI = 3.14159265358979;
+
+// multiplied out rather than pow, which is too loose on some drivers to match the cpu
+float pow5(float h) {
+	float h2 = h * h;
+	return h2 * h2 * h;
+}
+
+// density
+float poly6(float r, float h) {
+	float x = h * h - r * r;
+	return 4. / (PI * pow5(h) * h * h * h) * x * x * x;
+}
+
+// how hard pressure pushes apart, the magnitude of the spiky kernel's gradient
+float spiky_gradient(float r, float h) {
+	return 30. / (PI * pow5(h)) * (h - r) * (h - r);
+}
+
+// viscosity
+float viscosity_laplacian(float r, float h) {
+	return 40. / (PI * pow5(h)) * (h - r);
+}
+
+bool is_sph(Material m) {
+	return kind_table.kinds[m.kind].solver == SOLVER_SPH;
+}
@@ -0,0 +1,18 @@
+// Material.tags bits, mirrors simulation/tags.rs
+
+const uint TAG_STATIC = 1u << 0;// never moves
+const uint TAG_FLAMMABLE = 1u << 1;// turns into fire above IGNITION_TEMPERATURE
+const uint TAG_CONDUCTIVE = 1u << 2;// heat only flows between two conductive particles
+const uint TAG_LIQUID = 1u << 3;// slides along geometry without friction, drawn see-through
+const uint TAG_POWDER = 1u << 4;// doesn't bounce off geometry, so it piles up
+const uint TAG_STICKY = 1u << 5;// stops dead on touching geometry
+const uint TAG_INDESTRUCTIBLE = 1u << 6;// never reacts or changes phase
+const uint TAG_FIXED_TEMPERATURE = 1u << 7;// always at its kind's fixed_temperature
+
+// simulation/materials.rs IGNITION_TEMPERATURE and FIRE, tags.rs tests that they match
+const float IGNITION_TEMPERATURE = 300.;
+const uint KIND_FIRE = 11u;
+
+bool has_tag(uint tags, uint tag) {
+	return (tags & tag) != 0u;
+}
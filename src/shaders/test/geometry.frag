@@ -0,0 +1,25 @@
+#version 460
+
+#include "render.glsl"
+
+layout(location = 0) out vec4 f_color;
+
+const vec3 GEOMETRY_COLOUR = vec3(0.2, 1., 0.4);
+
+// drawn over everything when show_geometry is on, filled see-through with a solid outline a pixel wide
+void main() {
+	if (params.show_geometry != 1) {
+		discard;
+	}
+	float d = NO_SHAPE_DISTANCE;
+	for (uint i = 0; i < shapes.shape.length(); i++) {
+		d = min(d, shape_distance(shapes.shape[i], gl_FragCoord.xy));
+	}
+	if (abs(d) < 1.) {
+		f_color = encode(vec4(GEOMETRY_COLOUR, 1.));
+	} else if (d < 0.) {
+		f_color = encode(vec4(GEOMETRY_COLOUR, 0.3));
+	} else {
+		discard;
+	}
+}
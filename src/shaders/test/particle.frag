@@ -0,0 +1,16 @@
+#version 460
+
+#include "render.glsl"
+
+layout(location = 0) flat in vec2 centre;
+layout(location = 1) flat in vec4 colour;
+
+layout(location = 0) out vec4 f_color;
+
+// rounds off particle.vert's quad
+void main() {
+	if (distance(gl_FragCoord.xy, centre) >= params.particle_radius) {
+		discard;
+	}
+	f_color = encode(colour);
+}
@@ -0,0 +1,25 @@
+#version 460
+
+#include "render.glsl"
+
+layout(location = 0) flat out vec2 centre;// pixels
+layout(location = 1) flat out vec4 colour;// linear
+
+const float LIQUID_OPACITY = 0.6;
+
+// two triangles, one quad per instance and one instance per particle slot
+const vec2 CORNERS[6] = vec2[](vec2(-1., -1.), vec2(1., -1.), vec2(-1., 1.), vec2(-1., 1.), vec2(1., -1.), vec2(1., 1.));
+
+void main() {
+	Material m = buf.mat[gl_InstanceIndex];
+	if (m.id == 0) {
+		// every corner in the same place off screen, so an empty slot has nothing to rasterize
+		gl_Position = vec4(2., 2., 0., 1.);
+		return;
+	}
+	centre = m.pos;
+	// liquids let what's behind them show through, later particles are drawn over earlier ones
+	colour = vec4(srgb_to_linear(clamp(m.colour * params.colour_scale, 0., 1.)), has_tag(m.tags, TAG_LIQUID) ? LIQUID_OPACITY : 1.);
+	vec2 corner = m.pos + CORNERS[gl_VertexIndex] * params.particle_radius;
+	gl_Position = vec4(corner / PushConstants.dims * 2. - 1., 0., 1.);
+}
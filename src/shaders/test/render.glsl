@@ -0,0 +1,49 @@
+// shared by every shader the window draws with, the bindings match build_render_pass in window/utils.rs
+
+#include "../material.glsl"
+
+layout(binding = 0) buffer Data {
+	Material mat[];
+}
+buf;
+
+layout( push_constant ) uniform PushType
+{
+	vec2 dims;
+	uint encode_srgb;// 1 when the swapchain is unorm but displayed as srgb, so we have to encode ourselves
+} PushConstants;
+
+// from the config file, see config.rs
+layout(binding = 1) uniform RenderParams {
+	vec3 colour_scale;// multiplies every particle's colour, in srgb
+	float particle_radius;// pixels
+	uint show_geometry;// 1 draws the level geometry over everything, G toggles it
+}
+params;
+
+// the world's level geometry, see sand_bindings.glsl
+layout(binding = 2) readonly buffer Shapes {
+	Shape shape[];
+}
+shapes;
+
+layout(binding = 3) readonly buffer Vertices {
+	vec2 v[];
+}
+vertices;
+
+#include "../geometry.glsl"
+
+// Material.colour is authored in srgb like every colour picker, blending and output happen in linear
+vec3 srgb_to_linear(vec3 c) {
+	return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(vec3(0.04045), c));
+}
+
+vec3 linear_to_srgb(vec3 c) {
+	return mix(c * 12.92, 1.055 * pow(c, vec3(1. / 2.4)) - 0.055, step(vec3(0.0031308), c));
+}
+
+// on a unorm swapchain the blend happens after this, so what's drawn over is mixed in srgb rather than linear
+vec4 encode(vec4 colour) {
+	return PushConstants.encode_srgb == 1 ? vec4(linear_to_srgb(colour.rgb), colour.a) : colour;
+}
@@ -0,0 +1,44 @@
+use super::sand::sand_shader::Material;
+use super::tags::Tags;
+
+/// How many body numbers a world has room for, 0 included as it means no body. MAX_BODIES in material.glsl.
+pub const MAX_BODIES: usize = 1024;
+/// The least `stable` a body's particles get, kinds that are 0 otherwise fall apart on the first thing they touch.
+/// Well under `Impulse::brush` near its centre, so explosions still break bodies up.
+pub const MIN_BODY_STABLE: f32 = 60.0;
+
+/// Makes `particles` one rigid body, `body`, shaped the way they are now. Each keeps where it sits
+/// relative to their centre of mass as its rest position, and loses `Tags::STATIC` as bodies move.
+/// A particle comes off again when a collision or impulse is stronger than its `stable`, which is raised
+/// to `MIN_BODY_STABLE`.
+pub fn make_body(particles: &mut [Material], body: u32) {
+    let mass: f32 = particles.iter().map(|particle| particle.mass).sum();
+    let mut centre = [0f32, 0f32];
+    for particle in particles.iter() {
+        for axis in 0..2 {
+            centre[axis] += particle.pos[axis] * particle.mass;
+        }
+    }
+    centre = [centre[0] / mass, centre[1] / mass];
+    for particle in particles {
+        particle.body = body;
+        particle.rest = [particle.pos[0] - centre[0], particle.pos[1] - centre[1]];
+        particle.tags = (Tags::from_bits_retain(particle.tags) - Tags::STATIC).bits();
+        particle.stable = particle.stable.max(MIN_BODY_STABLE);
+    }
+}
+
+/// A square of `size` by `size` particles of `kind` around `centre`, `spacing` pixels apart, as one body.
+/// What the body brush drops, `World::spawn_body` gives it ids and a body number.
+pub fn block(kind: u32, centre: [f32; 2], size: u32, spacing: f32) -> Vec<Material> {
+    let start = (size as f32 - 1f32) / 2f32 * spacing;
+    (0..size * size)
+        .map(|i| {
+            let pos = [
+                centre[0] - start + (i % size) as f32 * spacing,
+                centre[1] - start + (i / size) as f32 * spacing,
+            ];
+            super::materials::kind(kind).particle(kind, pos)
+        })
+        .collect()
+}
@@ -0,0 +1,64 @@
+use super::bodies::MAX_BODIES;
+use super::rng::pcg;
+use super::sand::sand_shader::Material;
+use crate::config::SimulationConfig;
+
+/// How many cells and sleep stamps the chunks share by hash. Mirrors CHUNK_COUNT in cells.glsl.
+pub const CHUNK_COUNT: usize = 4096;
+/// Cells in the gpu's cell list, the chunk slots' then one per body number. CELL_COUNT in cells.glsl.
+pub const CELL_COUNT: usize = CHUNK_COUNT + MAX_BODIES;
+// mirrors cells.glsl
+const CHUNK_LIMIT: f32 = 1048576f32;
+
+/// As wide as the furthest any stage looks for neighbours, chunk_size in cells.glsl.
+pub fn chunk_size(config: &SimulationConfig) -> f32 {
+    config.heat_radius.max(config.reaction_radius.max(config.sph_radius))
+}
+
+pub fn chunk_of(config: &SimulationConfig, pos: [f32; 2]) -> [i32; 2] {
+    let size = chunk_size(config);
+    pos.map(|p| (p / size).floor().clamp(-CHUNK_LIMIT, CHUNK_LIMIT) as i32)
+}
+
+pub fn chunk_slot(chunk: [i32; 2]) -> usize {
+    (pcg(chunk[0] as u32 ^ pcg(chunk[1] as u32)) % CHUNK_COUNT as u32) as usize
+}
+
+/// `pos`'s chunk slot and the 8 around it in the order the shaders look through them, a slot two of them
+/// hash to only once. neighbour_slots in cells.glsl.
+pub fn neighbour_slots(config: &SimulationConfig, pos: [f32; 2]) -> Vec<usize> {
+    let chunk = chunk_of(config, pos);
+    let mut slots = Vec::with_capacity(9);
+    for y in -1..=1 {
+        for x in -1..=1 {
+            let slot = chunk_slot([chunk[0] + x, chunk[1] + y]);
+            if !slots.contains(&slot) {
+                slots.push(slot);
+            }
+        }
+    }
+    slots
+}
+
+/// The cell list a tick builds before anything moves, every live particle's index under its chunk slot
+/// in index order, like the gpu's after sand_cell_sort.glsl.
+pub struct Cells {
+    entries: Vec<Vec<usize>>,
+}
+
+impl Cells {
+    pub fn new(particles: &[Material], config: &SimulationConfig) -> Cells {
+        let mut entries = vec![Vec::new(); CHUNK_COUNT];
+        for (idx, m) in particles.iter().enumerate().filter(|(_, m)| m.id != 0) {
+            entries[chunk_slot(chunk_of(config, m.pos))].push(idx);
+        }
+        Cells { entries }
+    }
+
+    /// Everything a neighbour search from `pos` has to look at, in the order the shaders add it up.
+    pub fn around(&self, config: &SimulationConfig, pos: [f32; 2]) -> impl Iterator<Item = usize> + '_ {
+        neighbour_slots(config, pos)
+            .into_iter()
+            .flat_map(move |slot| self.entries[slot].iter().copied())
+    }
+}
@@ -0,0 +1,280 @@
+use super::impulse::Falloff;
+use super::sand::sand_shader;
+
+/// Force fields a world holds at once, the field buffer always has this many slots.
+pub const MAX_FIELDS: usize = 32;
+
+// Field.kind, mirrors fields.glsl
+pub const FIELD_NONE: u32 = 0;
+pub const FIELD_WIND: u32 = 1;
+pub const FIELD_ATTRACTOR: u32 = 2;
+pub const FIELD_VORTEX: u32 = 3;
+
+/// An acceleration on top of gravity over part of the world, in pixels/s².
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    /// The same acceleration everywhere inside the box.
+    Wind {
+        centre: [f32; 2],
+        half_size: [f32; 2],
+        acceleration: [f32; 2],
+    },
+    /// Pulls towards `centre`, a negative strength pushes away from it instead.
+    Attractor {
+        centre: [f32; 2],
+        radius: f32,
+        strength: f32,
+        falloff: Falloff,
+    },
+    /// Swirls around `centre`, anticlockwise on screen with a positive strength.
+    Vortex {
+        centre: [f32; 2],
+        radius: f32,
+        strength: f32,
+        falloff: Falloff,
+    },
+}
+
+impl Field {
+    // what the field keys place at the cursor, see window.rs
+    pub fn attractor_brush(centre: [f32; 2]) -> Field {
+        Field::Attractor {
+            centre,
+            radius: 120.0,
+            strength: 400.0,
+            falloff: Falloff::Linear,
+        }
+    }
+
+    pub fn repulsor_brush(centre: [f32; 2]) -> Field {
+        Field::Attractor {
+            centre,
+            radius: 120.0,
+            strength: -400.0,
+            falloff: Falloff::Linear,
+        }
+    }
+
+    pub fn vortex_brush(centre: [f32; 2]) -> Field {
+        Field::Vortex {
+            centre,
+            radius: 120.0,
+            strength: 300.0,
+            falloff: Falloff::Linear,
+        }
+    }
+
+    /// Blows to the right across a box centred on `centre`.
+    pub fn wind_brush(centre: [f32; 2]) -> Field {
+        Field::Wind {
+            centre,
+            half_size: [150.0, 60.0],
+            acceleration: [250.0, 0.0],
+        }
+    }
+
+    /// What it adds to a particle's acceleration at `p`, the cpu copy of the loop body in fields.glsl.
+    pub fn acceleration(&self, p: [f32; 2]) -> [f32; 2] {
+        match *self {
+            Field::Wind {
+                centre,
+                half_size,
+                acceleration,
+            } => {
+                let inside = (p[0] - centre[0]).abs() < half_size[0]
+                    && (p[1] - centre[1]).abs() < half_size[1];
+                if inside {
+                    acceleration
+                } else {
+                    [0f32, 0f32]
+                }
+            }
+            Field::Attractor {
+                centre,
+                radius,
+                strength,
+                falloff,
+            }
+            | Field::Vortex {
+                centre,
+                radius,
+                strength,
+                falloff,
+            } => {
+                let r = ((p[0] - centre[0]).powi(2) + (p[1] - centre[1]).powi(2)).sqrt();
+                if r >= radius || r == 0f32 {
+                    return [0f32, 0f32];
+                }
+                let towards = [(centre[0] - p[0]) / r, (centre[1] - p[1]) / r];
+                let direction = match self {
+                    Field::Attractor { .. } => towards,
+                    _ => [-towards[1], towards[0]],
+                };
+                let pull = strength * falloff.at(1f32 - r / radius);
+                [direction[0] * pull, direction[1] * pull]
+            }
+        }
+    }
+}
+
+/// Every field's acceleration at `p` added up in order, field_acceleration in fields.glsl.
+pub fn acceleration(fields: &[Field], p: [f32; 2]) -> [f32; 2] {
+    fields.iter().fold([0f32, 0f32], |total, field| {
+        let a = field.acceleration(p);
+        [total[0] + a[0], total[1] + a[1]]
+    })
+}
+
+impl From<&Field> for sand_shader::Field {
+    fn from(field: &Field) -> sand_shader::Field {
+        match *field {
+            Field::Wind {
+                centre,
+                half_size,
+                acceleration,
+            } => sand_shader::Field {
+                pos: centre,
+                half_size,
+                acceleration,
+                kind: FIELD_WIND,
+                ..NO_FIELD
+            },
+            Field::Attractor {
+                centre,
+                radius,
+                strength,
+                falloff,
+            } => sand_shader::Field {
+                pos: centre,
+                radius,
+                strength,
+                falloff: falloff.gpu(),
+                kind: FIELD_ATTRACTOR,
+                ..NO_FIELD
+            },
+            Field::Vortex {
+                centre,
+                radius,
+                strength,
+                falloff,
+            } => sand_shader::Field {
+                pos: centre,
+                radius,
+                strength,
+                falloff: falloff.gpu(),
+                kind: FIELD_VORTEX,
+                ..NO_FIELD
+            },
+        }
+    }
+}
+
+const NO_FIELD: sand_shader::Field = sand_shader::Field {
+    pos: [0f32, 0f32],
+    half_size: [0f32, 0f32],
+    acceleration: [0f32, 0f32],
+    radius: 0f32,
+    strength: 0f32,
+    falloff: 0,
+    kind: FIELD_NONE,
+};
+
+/// What gets uploaded, always `MAX_FIELDS` long with the unused slots pushing nothing.
+pub fn gpu_fields(fields: &[Field]) -> Vec<sand_shader::Field> {
+    let mut gpu: Vec<sand_shader::Field> = fields.iter().map(sand_shader::Field::from).collect();
+    gpu.resize(MAX_FIELDS, NO_FIELD);
+    gpu
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attractor(falloff: Falloff) -> Field {
+        Field::Attractor {
+            centre: [0f32, 0f32],
+            radius: 10f32,
+            strength: 100f32,
+            falloff,
+        }
+    }
+
+    fn close(a: [f32; 2], b: [f32; 2]) -> bool {
+        (a[0] - b[0]).abs() < 1e-4 && (a[1] - b[1]).abs() < 1e-4
+    }
+
+    #[test]
+    fn falloff_weakens_towards_the_radius() {
+        assert!(close(attractor(Falloff::None).acceleration([5f32, 0f32]), [-100f32, 0f32]));
+        assert!(close(attractor(Falloff::Linear).acceleration([5f32, 0f32]), [-50f32, 0f32]));
+        assert!(close(attractor(Falloff::Quadratic).acceleration([5f32, 0f32]), [-25f32, 0f32]));
+        assert!(close(attractor(Falloff::Linear).acceleration([0f32, -2f32]), [0f32, 80f32]));
+        assert!(close(attractor(Falloff::Quadratic).acceleration([0f32, -2f32]), [0f32, 64f32]));
+    }
+
+    #[test]
+    fn nothing_at_the_centre_or_past_the_radius() {
+        for falloff in [Falloff::None, Falloff::Linear, Falloff::Quadratic] {
+            assert_eq!(attractor(falloff).acceleration([0f32, 0f32]), [0f32, 0f32]);
+            assert_eq!(attractor(falloff).acceleration([10f32, 0f32]), [0f32, 0f32]);
+            assert_eq!(attractor(falloff).acceleration([8f32, 8f32]), [0f32, 0f32]);
+        }
+    }
+
+    #[test]
+    fn negative_strength_repels() {
+        let repulsor = Field::Attractor {
+            centre: [0f32, 0f32],
+            radius: 10f32,
+            strength: -100f32,
+            falloff: Falloff::Linear,
+        };
+        assert!(close(repulsor.acceleration([5f32, 0f32]), [50f32, 0f32]));
+    }
+
+    #[test]
+    fn vortex_swirls_anticlockwise_on_screen() {
+        let vortex = Field::Vortex {
+            centre: [0f32, 0f32],
+            radius: 10f32,
+            strength: 100f32,
+            falloff: Falloff::Linear,
+        };
+        // +y is down the screen, so right of the centre anticlockwise is up
+        assert!(close(vortex.acceleration([5f32, 0f32]), [0f32, -50f32]));
+        assert!(close(vortex.acceleration([0f32, -5f32]), [-50f32, 0f32]));
+    }
+
+    #[test]
+    fn wind_only_blows_inside_its_box() {
+        let wind = Field::Wind {
+            centre: [10f32, 10f32],
+            half_size: [5f32, 2f32],
+            acceleration: [30f32, -1f32],
+        };
+        assert_eq!(wind.acceleration([14f32, 11f32]), [30f32, -1f32]);
+        assert_eq!(wind.acceleration([15f32, 10f32]), [0f32, 0f32]);
+        assert_eq!(wind.acceleration([10f32, 7f32]), [0f32, 0f32]);
+    }
+
+    #[test]
+    fn fields_add_up() {
+        let wind = Field::Wind {
+            centre: [0f32, 0f32],
+            half_size: [20f32, 20f32],
+            acceleration: [1f32, 2f32],
+        };
+        let fields = [wind, attractor(Falloff::None), wind];
+        assert!(close(acceleration(&fields, [5f32, 0f32]), [-98f32, 4f32]));
+        assert_eq!(acceleration(&[], [5f32, 0f32]), [0f32, 0f32]);
+    }
+
+    #[test]
+    fn unused_gpu_slots_are_empty() {
+        let gpu = gpu_fields(&[attractor(Falloff::Quadratic)]);
+        assert_eq!(gpu.len(), MAX_FIELDS);
+        assert_eq!(gpu[0].kind, FIELD_ATTRACTOR);
+        assert_eq!(gpu[0].falloff, Falloff::Quadratic.gpu());
+        assert!(gpu[1..].iter().all(|field| field.kind == FIELD_NONE));
+    }
+}
@@ -0,0 +1,288 @@
+use super::sand::sand_shader::Shape;
+
+// Shape.kind, mirrors geometry.glsl
+pub const SHAPE_NONE: u32 = 0;
+pub const SHAPE_BOX: u32 = 1;
+pub const SHAPE_CIRCLE: u32 = 2;
+pub const SHAPE_CAPSULE: u32 = 3;
+pub const SHAPE_POLYGON: u32 = 4;
+pub const SHAPE_BOUNDS: u32 = 5;
+
+const NO_SHAPE_DISTANCE: f32 = 1e30;
+const NORMAL_EPSILON: f32 = 0.5;
+
+/// How particles bounce off a shape.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Surface {
+    pub restitution: f32, // fraction of the speed into the surface that bounces back
+    pub friction: f32,    // fraction of the speed along the surface lost on contact
+}
+
+impl Default for Surface {
+    fn default() -> Surface {
+        Surface {
+            restitution: 0.3,
+            friction: 0.1,
+        }
+    }
+}
+
+/// Static level geometry the tick collides particles with, in the layout it's uploaded in.
+#[derive(Clone, Debug, Default)]
+pub struct Geometry {
+    pub shapes: Vec<Shape>,
+    pub vertices: Vec<[f32; 2]>, // every polygon's corners, back to back
+}
+
+impl Geometry {
+    pub fn add_box(&mut self, centre: [f32; 2], half_size: [f32; 2], surface: Surface) {
+        self.shapes.push(shape(SHAPE_BOX, centre, half_size, 0f32, surface));
+    }
+
+    pub fn add_circle(&mut self, centre: [f32; 2], radius: f32, surface: Surface) {
+        self.shapes.push(shape(SHAPE_CIRCLE, centre, [0f32, 0f32], radius, surface));
+    }
+
+    pub fn add_capsule(&mut self, a: [f32; 2], b: [f32; 2], radius: f32, surface: Surface) {
+        self.shapes.push(shape(SHAPE_CAPSULE, a, b, radius, surface));
+    }
+
+    /// Any simple polygon, in either winding.
+    pub fn add_polygon(&mut self, corners: &[[f32; 2]], surface: Surface) {
+        self.shapes.push(Shape {
+            first_vertex: self.vertices.len() as u32,
+            vertex_count: corners.len() as u32,
+            ..shape(SHAPE_POLYGON, [0f32, 0f32], [0f32, 0f32], 0f32, surface)
+        });
+        self.vertices.extend_from_slice(corners);
+    }
+
+    /// Keeps particles inside the rectangle from `min` to `max`.
+    pub fn add_bounds(&mut self, min: [f32; 2], max: [f32; 2], surface: Surface) {
+        let centre = [(min[0] + max[0]) / 2f32, (min[1] + max[1]) / 2f32];
+        let half_size = [(max[0] - min[0]) / 2f32, (max[1] - min[1]) / 2f32];
+        self.shapes.push(shape(SHAPE_BOUNDS, centre, half_size, 0f32, surface));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty()
+    }
+
+    /// What gets uploaded. A storage buffer can't be empty, so no geometry is one shape that never collides.
+    pub fn gpu_shapes(&self) -> Vec<Shape> {
+        if self.shapes.is_empty() {
+            vec![shape(SHAPE_NONE, [0f32, 0f32], [0f32, 0f32], 0f32, Surface::default())]
+        } else {
+            self.shapes.clone()
+        }
+    }
+
+    pub fn gpu_vertices(&self) -> Vec<[f32; 2]> {
+        if self.vertices.is_empty() {
+            vec![[0f32, 0f32]]
+        } else {
+            self.vertices.clone()
+        }
+    }
+
+    // the rest is geometry.glsl on the cpu for simulation/reference.rs
+
+    /// Negative inside `shape`.
+    pub fn distance(&self, shape: &Shape, p: [f32; 2]) -> f32 {
+        match shape.kind {
+            SHAPE_BOX => box_distance(p, shape.a, shape.b) - shape.radius,
+            SHAPE_CIRCLE => length(sub(p, shape.a)) - shape.radius,
+            SHAPE_CAPSULE => capsule_distance(p, shape.a, shape.b) - shape.radius,
+            SHAPE_POLYGON => self.polygon_distance(p, shape.first_vertex, shape.vertex_count) - shape.radius,
+            SHAPE_BOUNDS => -box_distance(p, shape.a, shape.b),
+            _ => NO_SHAPE_DISTANCE,
+        }
+    }
+
+    /// Points out of `shape`, zero where the gradient vanishes.
+    pub fn normal(&self, shape: &Shape, p: [f32; 2]) -> [f32; 2] {
+        let gradient = [
+            self.distance(shape, [p[0] + NORMAL_EPSILON, p[1]])
+                - self.distance(shape, [p[0] - NORMAL_EPSILON, p[1]]),
+            self.distance(shape, [p[0], p[1] + NORMAL_EPSILON])
+                - self.distance(shape, [p[0], p[1] - NORMAL_EPSILON]),
+        ];
+        let len = length(gradient);
+        if len > 0f32 {
+            [gradient[0] / len, gradient[1] / len]
+        } else {
+            [0f32, 0f32]
+        }
+    }
+
+    fn polygon_distance(&self, p: [f32; 2], first: u32, count: u32) -> f32 {
+        let corners = &self.vertices[first as usize..(first + count) as usize];
+        let mut d = dot(sub(p, corners[0]), sub(p, corners[0]));
+        let mut s = 1f32;
+        let mut j = corners.len() - 1;
+        for i in 0..corners.len() {
+            let (vi, vj) = (corners[i], corners[j]);
+            let e = sub(vj, vi);
+            let w = sub(p, vi);
+            let h = if dot(e, e) > 0f32 { (dot(w, e) / dot(e, e)).clamp(0f32, 1f32) } else { 0f32 };
+            let b = sub(w, [e[0] * h, e[1] * h]);
+            d = d.min(dot(b, b));
+            let c = [p[1] >= vi[1], p[1] < vj[1], e[0] * w[1] > e[1] * w[0]];
+            if c.iter().all(|c| *c) || c.iter().all(|c| !*c) {
+                s = -s;
+            }
+            j = i;
+        }
+        s * d.sqrt()
+    }
+}
+
+fn shape(kind: u32, a: [f32; 2], b: [f32; 2], radius: f32, surface: Surface) -> Shape {
+    Shape {
+        a,
+        b,
+        radius,
+        kind,
+        first_vertex: 0,
+        vertex_count: 0,
+        restitution: surface.restitution,
+        friction: surface.friction,
+    }
+}
+
+fn box_distance(p: [f32; 2], centre: [f32; 2], half_size: [f32; 2]) -> f32 {
+    let d = [
+        (p[0] - centre[0]).abs() - half_size[0],
+        (p[1] - centre[1]).abs() - half_size[1],
+    ];
+    length([d[0].max(0f32), d[1].max(0f32)]) + d[0].max(d[1]).min(0f32)
+}
+
+fn capsule_distance(p: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+    let pa = sub(p, a);
+    let ba = sub(b, a);
+    let h = if dot(ba, ba) > 0f32 { (dot(pa, ba) / dot(ba, ba)).clamp(0f32, 1f32) } else { 0f32 };
+    length(sub(pa, [ba[0] * h, ba[1] * h]))
+}
+
+fn sub(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn dot(a: [f32; 2], b: [f32; 2]) -> f32 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+fn length(a: [f32; 2]) -> f32 {
+    dot(a, a).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    fn normal_is(geometry: &Geometry, shape: &Shape, p: [f32; 2], expected: [f32; 2]) {
+        let normal = geometry.normal(shape, p);
+        assert!(
+            (normal[0] - expected[0]).abs() < 0.01 && (normal[1] - expected[1]).abs() < 0.01,
+            "normal at {p:?} is {normal:?}, expected {expected:?}"
+        );
+    }
+
+    #[test]
+    fn box_distance_and_normal() {
+        let mut geometry = Geometry::default();
+        geometry.add_box([0f32, 0f32], [10f32, 5f32], Surface::default());
+        let shape = &geometry.shapes[0];
+        assert!(close(geometry.distance(shape, [15f32, 0f32]), 5f32));
+        assert!(close(geometry.distance(shape, [0f32, 0f32]), -5f32));
+        assert!(close(geometry.distance(shape, [13f32, 9f32]), 5f32)); // past the corner
+        normal_is(&geometry, shape, [15f32, 0f32], [1f32, 0f32]);
+        normal_is(&geometry, shape, [0f32, -8f32], [0f32, -1f32]);
+    }
+
+    #[test]
+    fn circle_distance_and_normal() {
+        let mut geometry = Geometry::default();
+        geometry.add_circle([10f32, 10f32], 5f32, Surface::default());
+        let shape = &geometry.shapes[0];
+        assert!(close(geometry.distance(shape, [10f32, 20f32]), 5f32));
+        assert!(close(geometry.distance(shape, [10f32, 10f32]), -5f32));
+        normal_is(&geometry, shape, [13f32, 14f32], [0.6f32, 0.8f32]);
+    }
+
+    #[test]
+    fn capsule_distance_and_normal() {
+        let mut geometry = Geometry::default();
+        geometry.add_capsule([0f32, 0f32], [10f32, 0f32], 2f32, Surface::default());
+        let shape = &geometry.shapes[0];
+        assert!(close(geometry.distance(shape, [5f32, 5f32]), 3f32));
+        assert!(close(geometry.distance(shape, [-4f32, 0f32]), 2f32)); // past an end
+        assert!(close(geometry.distance(shape, [5f32, 0f32]), -2f32));
+        normal_is(&geometry, shape, [5f32, 5f32], [0f32, 1f32]);
+        normal_is(&geometry, shape, [14f32, 0f32], [1f32, 0f32]);
+    }
+
+    #[test]
+    fn polygon_distance_is_the_same_in_either_winding() {
+        let square = [[0f32, 0f32], [10f32, 0f32], [10f32, 10f32], [0f32, 10f32]];
+        let mut reversed = square;
+        reversed.reverse();
+        let mut geometry = Geometry::default();
+        geometry.add_box([100f32, 100f32], [1f32, 1f32], Surface::default()); // so the polygons don't start at vertex 0
+        geometry.add_polygon(&square, Surface::default());
+        geometry.add_polygon(&reversed, Surface::default());
+        for shape in &geometry.shapes[1..] {
+            assert!(close(geometry.distance(shape, [5f32, 5f32]), -5f32));
+            assert!(close(geometry.distance(shape, [15f32, 5f32]), 5f32));
+            assert!(close(geometry.distance(shape, [5f32, -3f32]), 3f32));
+            normal_is(&geometry, shape, [15f32, 5f32], [1f32, 0f32]);
+            normal_is(&geometry, shape, [5f32, 12f32], [0f32, 1f32]);
+        }
+    }
+
+    #[test]
+    fn concave_polygon_distance() {
+        // an L, the notch at the top right is outside
+        let corners = [
+            [0f32, 0f32],
+            [10f32, 0f32],
+            [10f32, 4f32],
+            [4f32, 4f32],
+            [4f32, 10f32],
+            [0f32, 10f32],
+        ];
+        let mut geometry = Geometry::default();
+        geometry.add_polygon(&corners, Surface::default());
+        let shape = &geometry.shapes[0];
+        assert!(close(geometry.distance(shape, [7f32, 7f32]), 3f32));
+        assert!(close(geometry.distance(shape, [2f32, 2f32]), -2f32));
+        normal_is(&geometry, shape, [7f32, 5f32], [0f32, 1f32]);
+    }
+
+    #[test]
+    fn bounds_are_solid_outside() {
+        let mut geometry = Geometry::default();
+        geometry.add_bounds([0f32, 0f32], [100f32, 50f32], Surface::default());
+        let shape = &geometry.shapes[0];
+        assert!(close(geometry.distance(shape, [50f32, 25f32]), 25f32));
+        assert!(close(geometry.distance(shape, [95f32, 25f32]), 5f32));
+        assert!(close(geometry.distance(shape, [110f32, 25f32]), -10f32));
+        normal_is(&geometry, shape, [95f32, 25f32], [-1f32, 0f32]);
+        normal_is(&geometry, shape, [50f32, 2f32], [0f32, 1f32]);
+    }
+
+    #[test]
+    fn no_geometry_never_collides() {
+        let geometry = Geometry::default();
+        let shapes = geometry.gpu_shapes();
+        assert_eq!(shapes.len(), 1);
+        assert_eq!(geometry.distance(&shapes[0], [0f32, 0f32]), NO_SHAPE_DISTANCE);
+        assert_eq!(geometry.normal(&shapes[0], [0f32, 0f32]), [0f32, 0f32]);
+        assert_eq!(geometry.gpu_vertices().len(), 1);
+    }
+}
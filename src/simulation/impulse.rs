@@ -0,0 +1,108 @@
+use super::sand::sand_shader;
+
+/// Impulses one tick applies at most, `World::impulse` leaves the rest for the ticks after.
+/// Mirrors MAX_IMPULSES in sand_bindings.glsl.
+pub const MAX_IMPULSES: usize = 16;
+
+// Impulse.falloff and Impulse.effects on the gpu, mirrors material.glsl
+pub const FALLOFF_NONE: u32 = 0;
+pub const FALLOFF_LINEAR: u32 = 1;
+pub const FALLOFF_QUADRATIC: u32 = 2;
+pub const IMPULSE_DEBRIS: u32 = 1 << 0;
+pub const IMPULSE_IGNITE: u32 = 1 << 1;
+
+/// How an impulse or force field weakens from its centre to its radius.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Falloff {
+    /// Full strength all the way out.
+    None,
+    Linear,
+    /// Strong in the middle and little at the edge, the brush uses this.
+    Quadratic,
+}
+
+impl Falloff {
+    /// How much is left at `t`, 1 at the centre down to 0 at the radius. falloff_at in material.glsl.
+    pub fn at(self, t: f32) -> f32 {
+        match self {
+            Falloff::None => 1.0,
+            Falloff::Linear => t,
+            Falloff::Quadratic => t * t,
+        }
+    }
+
+    /// FALLOFF_*, also how scene files write it.
+    pub fn gpu(self) -> u32 {
+        match self {
+            Falloff::None => FALLOFF_NONE,
+            Falloff::Linear => FALLOFF_LINEAR,
+            Falloff::Quadratic => FALLOFF_QUADRATIC,
+        }
+    }
+
+    pub fn from_gpu(falloff: u32) -> Option<Falloff> {
+        match falloff {
+            FALLOFF_NONE => Some(Falloff::None),
+            FALLOFF_LINEAR => Some(Falloff::Linear),
+            FALLOFF_QUADRATIC => Some(Falloff::Quadratic),
+            _ => None,
+        }
+    }
+}
+
+/// A radial push, an explosion or a shockwave. Particles closer than `radius` to `pos` get
+/// `strength` (weakened by `falloff`) over their mass added to their velocity, away from `pos`.
+/// A static particle is only knocked loose, and then pushed, when the impulse it gets is above its `stable`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Impulse {
+    pub pos: [f32; 2],
+    pub radius: f32,
+    pub strength: f32,
+    pub falloff: Falloff,
+    pub debris: bool, // what it knocks loose turns into powder instead of keeping its tags
+    pub ignite: bool, // flammable particles in range catch fire
+}
+
+impl Impulse {
+    /// What the explode brush sets off at `pos`.
+    pub fn brush(pos: [f32; 2]) -> Impulse {
+        Impulse {
+            pos,
+            radius: 48.0,
+            strength: 400.0,
+            falloff: Falloff::Quadratic,
+            debris: true,
+            ignite: true,
+        }
+    }
+
+    /// The impulse a particle `distance` from the centre gets, 0 outside the radius.
+    pub fn strength_at(&self, distance: f32) -> f32 {
+        if distance >= self.radius {
+            return 0.0;
+        }
+        self.strength * self.falloff.at(1.0 - distance / self.radius)
+    }
+}
+
+impl From<&Impulse> for sand_shader::Impulse {
+    fn from(impulse: &Impulse) -> sand_shader::Impulse {
+        sand_shader::Impulse {
+            pos: impulse.pos,
+            radius: impulse.radius,
+            strength: impulse.strength,
+            falloff: impulse.falloff.gpu(),
+            effects: if impulse.debris { IMPULSE_DEBRIS } else { 0 }
+                | if impulse.ignite { IMPULSE_IGNITE } else { 0 },
+        }
+    }
+}
+
+/// The tick state's unused impulse slots.
+pub const NO_IMPULSE: sand_shader::Impulse = sand_shader::Impulse {
+    pos: [0.0, 0.0],
+    radius: 0.0,
+    strength: 0.0,
+    falloff: FALLOFF_NONE,
+    effects: 0,
+};
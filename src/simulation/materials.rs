@@ -0,0 +1,294 @@
+use vulkano::padded::Padded;
+
+use super::sand::{sand_shader, KIND_PADDING};
+use super::tags::Tags;
+
+// indices into KINDS, stored in Material.kind
+pub const SAND: u32 = 0;
+pub const WATER: u32 = 1;
+pub const STONE: u32 = 2;
+pub const WOOD: u32 = 3;
+pub const ICE: u32 = 4;
+pub const STEAM: u32 = 5;
+pub const HEATER: u32 = 6;
+pub const COOLER: u32 = 7;
+pub const LAVA: u32 = 8;
+pub const ACID: u32 = 9;
+pub const METAL: u32 = 10;
+pub const FIRE: u32 = 11;
+pub const SMOKE: u32 = 12;
+pub const OIL: u32 = 13;
+
+// MaterialKind.solver on the gpu, mirrors material.glsl
+pub const SOLVER_GRANULAR: u32 = 0;
+pub const SOLVER_SPH: u32 = 1;
+
+pub const AMBIENT_TEMPERATURE: f32 = 20.0;
+/// Where `Tags::FLAMMABLE` particles turn into fire, mirrored in tags.glsl.
+pub const IGNITION_TEMPERATURE: f32 = 300.0;
+
+/// How particles of a kind move.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Solver {
+    /// Every particle on its own, sand and anything solid.
+    Granular,
+    /// Smoothed particle hydrodynamics with the other sph particles, so it flows like a fluid.
+    Sph(Fluid),
+}
+
+/// Sph parameters, densities are in mass per square pixel with `simulation.sph_radius` smoothing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fluid {
+    pub rest_density: f32, // pressure pushes towards this
+    pub stiffness: f32,    // pressure per unit of density above rest
+    pub viscosity: f32,    // how much neighbours' velocities are averaged in
+}
+
+/// What every particle of a kind has in common. Uploaded as the kind table the tick reads.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MaterialKind {
+    pub name: &'static str,
+    pub colour: [f32; 3],
+    pub conductivity: f32,  // how fast heat moves to and from neighbours
+    pub heat_capacity: f32, // how much heat it takes to change temperature
+    pub temperature: f32,   // what the brush paints it at, and forever with Tags::FIXED_TEMPERATURE
+    pub tags: Tags,
+    pub colder: Option<(u32, f32)>, // (kind, below this temperature)
+    pub hotter: Option<(u32, f32)>, // (kind, above this temperature)
+    pub gas: bool,
+    pub solver: Solver,
+    pub stable: f32, // the impulse it takes to knock a Tags::STATIC particle loose, see impulse.rs
+}
+
+const DEFAULT: MaterialKind = MaterialKind {
+    name: "",
+    colour: [1.0, 0.0, 1.0],
+    conductivity: 1.0,
+    heat_capacity: 1.0,
+    temperature: AMBIENT_TEMPERATURE,
+    tags: Tags::CONDUCTIVE,
+    colder: None,
+    hotter: None,
+    gas: false,
+    solver: Solver::Granular,
+    stable: 0.0,
+};
+
+/// In brush order, the number keys pick from here and tab steps through all of them.
+pub const KINDS: [MaterialKind; 14] = [
+    MaterialKind {
+        name: "sand",
+        colour: [0.86, 0.72, 0.45],
+        conductivity: 0.5,
+        heat_capacity: 0.8,
+        tags: Tags::CONDUCTIVE.union(Tags::POWDER),
+        ..DEFAULT
+    },
+    MaterialKind {
+        name: "water",
+        colour: [0.25, 0.45, 0.85],
+        conductivity: 2.0,
+        heat_capacity: 4.2,
+        colder: Some((ICE, 0.0)),
+        hotter: Some((STEAM, 100.0)),
+        tags: Tags::CONDUCTIVE.union(Tags::LIQUID),
+        solver: Solver::Sph(Fluid {
+            rest_density: 0.1,
+            stiffness: 2000.0,
+            viscosity: 2.0,
+        }),
+        ..DEFAULT
+    },
+    MaterialKind {
+        name: "stone",
+        colour: [0.5, 0.5, 0.52],
+        conductivity: 1.5,
+        heat_capacity: 0.9,
+        tags: Tags::CONDUCTIVE.union(Tags::STATIC),
+        stable: 150.0,
+        ..DEFAULT
+    },
+    MaterialKind {
+        name: "wood",
+        colour: [0.55, 0.35, 0.2],
+        conductivity: 0.2,
+        heat_capacity: 1.7,
+        tags: Tags::CONDUCTIVE.union(Tags::STATIC).union(Tags::FLAMMABLE),
+        stable: 80.0,
+        ..DEFAULT
+    },
+    MaterialKind {
+        name: "ice",
+        colour: [0.75, 0.9, 0.95],
+        conductivity: 2.2,
+        heat_capacity: 2.1,
+        temperature: -10.0,
+        hotter: Some((WATER, 0.0)),
+        tags: Tags::CONDUCTIVE.union(Tags::POWDER),
+        ..DEFAULT
+    },
+    MaterialKind {
+        name: "steam",
+        colour: [0.85, 0.85, 0.9],
+        conductivity: 0.3,
+        heat_capacity: 2.0,
+        temperature: 110.0,
+        colder: Some((WATER, 100.0)),
+        gas: true,
+        ..DEFAULT
+    },
+    MaterialKind {
+        name: "heater",
+        colour: [0.95, 0.3, 0.1],
+        conductivity: 4.0,
+        temperature: 400.0,
+        tags: Tags::CONDUCTIVE
+            .union(Tags::STATIC)
+            .union(Tags::FIXED_TEMPERATURE)
+            .union(Tags::INDESTRUCTIBLE),
+        ..DEFAULT
+    },
+    MaterialKind {
+        name: "cooler",
+        colour: [0.3, 0.8, 1.0],
+        conductivity: 4.0,
+        temperature: -40.0,
+        tags: Tags::CONDUCTIVE
+            .union(Tags::STATIC)
+            .union(Tags::FIXED_TEMPERATURE)
+            .union(Tags::INDESTRUCTIBLE),
+        ..DEFAULT
+    },
+    MaterialKind {
+        name: "lava",
+        colour: [1.0, 0.35, 0.05],
+        temperature: 1200.0,
+        colder: Some((STONE, 700.0)),
+        tags: Tags::CONDUCTIVE.union(Tags::LIQUID).union(Tags::STICKY),
+        solver: Solver::Sph(Fluid {
+            rest_density: 0.12,
+            stiffness: 3000.0,
+            viscosity: 40.0,
+        }),
+        ..DEFAULT
+    },
+    MaterialKind {
+        name: "acid",
+        colour: [0.6, 0.95, 0.2],
+        conductivity: 0.8,
+        heat_capacity: 3.0,
+        tags: Tags::CONDUCTIVE.union(Tags::LIQUID),
+        solver: Solver::Sph(Fluid {
+            rest_density: 0.1,
+            stiffness: 2000.0,
+            viscosity: 2.0,
+        }),
+        ..DEFAULT
+    },
+    MaterialKind {
+        name: "metal",
+        colour: [0.7, 0.72, 0.78],
+        conductivity: 5.0,
+        heat_capacity: 0.5,
+        tags: Tags::CONDUCTIVE.union(Tags::STATIC),
+        stable: 600.0,
+        ..DEFAULT
+    },
+    MaterialKind {
+        name: "fire",
+        colour: [1.0, 0.6, 0.1],
+        heat_capacity: 0.5,
+        temperature: 600.0,
+        colder: Some((SMOKE, 300.0)),
+        gas: true,
+        ..DEFAULT
+    },
+    MaterialKind {
+        name: "smoke",
+        colour: [0.3, 0.3, 0.3],
+        conductivity: 0.1,
+        temperature: 150.0,
+        gas: true,
+        tags: Tags::empty(),
+        ..DEFAULT
+    },
+    // lighter than water and thicker
+    MaterialKind {
+        name: "oil",
+        colour: [0.35, 0.25, 0.05],
+        conductivity: 0.3,
+        heat_capacity: 2.0,
+        tags: Tags::CONDUCTIVE.union(Tags::LIQUID).union(Tags::FLAMMABLE),
+        solver: Solver::Sph(Fluid {
+            rest_density: 0.08,
+            stiffness: 2000.0,
+            viscosity: 10.0,
+        }),
+        ..DEFAULT
+    },
+];
+
+pub fn kind(kind: u32) -> &'static MaterialKind {
+    &KINDS[kind as usize]
+}
+
+/// The index of the kind called `name`, data files name kinds rather than numbering them.
+pub fn by_name(name: &str) -> Option<u32> {
+    KINDS.iter().position(|kind| kind.name == name).map(|i| i as u32)
+}
+
+impl MaterialKind {
+    /// A particle of this kind at `pos`, `World::spawn` gives it an id.
+    pub fn particle(&self, kind: u32, pos: [f32; 2]) -> sand_shader::Material {
+        sand_shader::Material {
+            colour: self.colour,
+            pos,
+            kind,
+            temperature: self.temperature,
+            gas: self.gas as u32,
+            tags: self.tags.bits(),
+            stable: self.stable,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&MaterialKind> for sand_shader::Kind {
+    fn from(kind: &MaterialKind) -> sand_shader::Kind {
+        // no transition is a threshold that can't be crossed, so the shader doesn't need to branch on it
+        let (colder_kind, colder_below) = kind.colder.unwrap_or((0, f32::NEG_INFINITY));
+        let (hotter_kind, hotter_above) = kind.hotter.unwrap_or((0, f32::INFINITY));
+        let (solver, fluid) = match kind.solver {
+            Solver::Granular => (
+                SOLVER_GRANULAR,
+                Fluid {
+                    rest_density: 0.0,
+                    stiffness: 0.0,
+                    viscosity: 0.0,
+                },
+            ),
+            Solver::Sph(fluid) => (SOLVER_SPH, fluid),
+        };
+        sand_shader::Kind {
+            colour: kind.colour,
+            conductivity: kind.conductivity,
+            heat_capacity: kind.heat_capacity,
+            fixed_temperature: kind.temperature,
+            colder_kind,
+            colder_below,
+            hotter_kind,
+            hotter_above,
+            gas: kind.gas as u32,
+            tags: kind.tags.bits(),
+            solver,
+            rest_density: fluid.rest_density,
+            stiffness: fluid.stiffness,
+            viscosity: fluid.viscosity,
+            stable: kind.stable,
+        }
+    }
+}
+
+pub fn gpu_table() -> Vec<Padded<sand_shader::Kind, KIND_PADDING>> {
+    KINDS.iter().map(|kind| Padded(sand_shader::Kind::from(kind))).collect()
+}
@@ -1 +1,14 @@
-pub mod sand;
\ No newline at end of file
+pub mod bodies;
+pub mod cells;
+pub mod fields;
+pub mod geometry;
+pub mod impulse;
+pub mod materials;
+pub mod reactions;
+pub mod reference;
+pub mod rng;
+pub mod sand;
+pub mod scene;
+pub mod sleep;
+pub mod tags;
+pub mod world;
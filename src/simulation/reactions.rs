@@ -0,0 +1,254 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::materials;
+use super::sand::reaction_shader;
+use crate::error::{Result, SandError};
+
+const DEFAULT_REACTIONS: &str = include_str!("../../reactions.toml");
+
+// reactions.toml documents the format
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReactionFile {
+    #[serde(default)]
+    reaction: Vec<ReactionRule>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReactionRule {
+    reactants: [String; 2],
+    products: [String; 2],
+    probability: f32,
+    temperature: Option<f32>,
+}
+
+/// What one kind does next to another, the half of a rule that applies to one side of the pair.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reaction {
+    pub product: u32,
+    pub probability: f32,
+    pub min_temperature: f32,
+}
+
+/// The rules compiled into a lookup by (kind, neighbour kind), uploaded as is for the reaction stage.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReactionTable {
+    lookup: Vec<Option<Reaction>>, // KINDS.len() squared
+}
+
+impl ReactionTable {
+    /// Parses and validates, the error has every problem with the file not just the first.
+    pub fn load(path: &Path) -> Result<ReactionTable> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| SandError::Config(format!("couldn't read reactions {}: {e}", path.display())))?;
+        ReactionTable::parse(&text).map_err(|problems| problems_error(&path.display().to_string(), problems))
+    }
+
+    /// The file given with --reactions, or the built in reactions.toml.
+    pub fn load_or_default(path: Option<&Path>) -> Result<ReactionTable> {
+        match path {
+            Some(path) => ReactionTable::load(path),
+            None => ReactionTable::parse(DEFAULT_REACTIONS)
+                .map_err(|problems| problems_error("the built in reactions.toml", problems)),
+        }
+    }
+
+    /// No reactions at all.
+    pub fn empty() -> ReactionTable {
+        ReactionTable {
+            lookup: vec![None; materials::KINDS.len() * materials::KINDS.len()],
+        }
+    }
+
+    fn parse(text: &str) -> std::result::Result<ReactionTable, Vec<String>> {
+        let file: ReactionFile = toml::from_str(text).map_err(|e| vec![e.to_string()])?;
+        let mut table = ReactionTable::empty();
+        let mut problems = Vec::new();
+        let kind = |name: &str, problems: &mut Vec<String>| {
+            let kind = materials::by_name(name);
+            if kind.is_none() {
+                problems.push(format!("there's no kind called {name:?}"));
+            }
+            kind
+        };
+        for rule in &file.reaction {
+            let [a, b] = &rule.reactants;
+            let name = format!("{a} + {b}");
+            let reactants = [kind(a, &mut problems), kind(b, &mut problems)];
+            let products = [kind(&rule.products[0], &mut problems), kind(&rule.products[1], &mut problems)];
+            if !(rule.probability > 0f32 && rule.probability <= 1f32) {
+                problems.push(format!("{name}: probability {} has to be above 0 and at most 1", rule.probability));
+            }
+            if rule.temperature.is_some_and(f32::is_nan) {
+                problems.push(format!("{name}: temperature can't be nan"));
+            }
+            let ([Some(a), Some(b)], [Some(product_a), Some(product_b)]) = (reactants, products) else {
+                continue;
+            };
+            // a pair of the same kind has one entry, so both sides have to turn into the same thing
+            if a == b && product_a != product_b {
+                problems.push(format!("{name}: both reactants are the same kind so the products have to be too"));
+            }
+            if table.lookup[index(a, b)].is_some() {
+                problems.push(format!("{name}: there's already a rule for this pair"));
+                continue;
+            }
+            let min_temperature = rule.temperature.unwrap_or(f32::NEG_INFINITY);
+            for (kind, other, product) in [(a, b, product_a), (b, a, product_b)] {
+                table.lookup[index(kind, other)] = Some(Reaction {
+                    product,
+                    probability: rule.probability,
+                    min_temperature,
+                });
+            }
+        }
+        if problems.is_empty() {
+            Ok(table)
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// What `kind` turns into next to `other`, if anything.
+    pub fn get(&self, kind: u32, other: u32) -> Option<&Reaction> {
+        self.lookup[index(kind, other)].as_ref()
+    }
+
+    pub fn gpu_table(&self) -> Vec<reaction_shader::Reaction> {
+        self.lookup
+            .iter()
+            .map(|reaction| match reaction {
+                Some(reaction) => reaction_shader::Reaction {
+                    product: reaction.product,
+                    probability: reaction.probability,
+                    min_temperature: reaction.min_temperature,
+                },
+                // probability 0 is what the shader skips
+                None => reaction_shader::Reaction {
+                    product: 0,
+                    probability: 0f32,
+                    min_temperature: 0f32,
+                },
+            })
+            .collect()
+    }
+}
+
+fn problems_error(origin: &str, problems: Vec<String>) -> SandError {
+    SandError::Config(format!("{origin}:\n  {}", problems.join("\n  ")))
+}
+
+// the same layout the shader indexes with
+fn index(kind: u32, other: u32) -> usize {
+    kind as usize * materials::KINDS.len() + other as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReactionTable;
+    use crate::simulation::materials;
+
+    // one of the problems has to mention `expected`
+    fn rejects(text: &str, expected: &str) {
+        let problems = ReactionTable::parse(text).expect_err("should have been rejected");
+        assert!(
+            problems.iter().any(|problem| problem.contains(expected)),
+            "no problem mentions {expected:?}: {problems:?}"
+        );
+    }
+
+    #[test]
+    fn the_built_in_reactions_parse() {
+        let table = ReactionTable::parse(super::DEFAULT_REACTIONS).unwrap();
+        let lava = table.get(materials::WATER, materials::LAVA).unwrap();
+        assert_eq!(lava.product, materials::STONE);
+        assert_eq!(table.get(materials::LAVA, materials::WATER).unwrap().product, materials::STEAM);
+        assert!(table.get(materials::SAND, materials::SAND).is_none());
+    }
+
+    #[test]
+    fn duplicate_pairs_are_rejected_in_either_order() {
+        rejects(
+            r#"
+            [[reaction]]
+            reactants = ["water", "lava"]
+            products = ["stone", "steam"]
+            probability = 0.5
+            [[reaction]]
+            reactants = ["lava", "water"]
+            products = ["steam", "stone"]
+            probability = 0.1
+            "#,
+            "already a rule for this pair",
+        );
+    }
+
+    #[test]
+    fn unknown_kinds_are_rejected() {
+        rejects(
+            r#"
+            [[reaction]]
+            reactants = ["water", "unobtainium"]
+            products = ["stone", "steam"]
+            probability = 0.5
+            "#,
+            "\"unobtainium\"",
+        );
+        rejects(
+            r#"
+            [[reaction]]
+            reactants = ["water", "lava"]
+            products = ["stone", "gold"]
+            probability = 0.5
+            "#,
+            "\"gold\"",
+        );
+    }
+
+    #[test]
+    fn probabilities_outside_0_to_1_are_rejected() {
+        for probability in ["0.0", "-0.5", "1.5", "nan"] {
+            rejects(
+                &format!(
+                    r#"
+                    [[reaction]]
+                    reactants = ["water", "lava"]
+                    products = ["stone", "steam"]
+                    probability = {probability}
+                    "#
+                ),
+                "probability",
+            );
+        }
+        assert!(ReactionTable::parse(
+            r#"
+            [[reaction]]
+            reactants = ["water", "lava"]
+            products = ["stone", "steam"]
+            probability = 1.0
+            "#
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn malformed_toml_is_rejected() {
+        let problems = ReactionTable::parse("[[reaction]\nreactants = [\"water\"").unwrap_err();
+        assert_eq!(problems.len(), 1);
+        // a missing field or an unknown one is a toml error too
+        assert!(ReactionTable::parse("[[reaction]]\nreactants = [\"water\", \"lava\"]\n").is_err());
+        rejects(
+            r#"
+            [[reaction]]
+            reactants = ["water", "lava"]
+            products = ["stone", "steam"]
+            probability = 0.5
+            chance = 0.5
+            "#,
+            "chance",
+        );
+    }
+}
@@ -0,0 +1,535 @@
+use super::cells::{self, Cells};
+use super::fields::{self, Field};
+use super::geometry::Geometry;
+use super::impulse::{self, Impulse};
+use super::materials::{self, Solver};
+use super::reactions::ReactionTable;
+use super::rng::{checksum, pcg, random};
+use super::sand::sand_shader::Material;
+use super::sleep::{self, Sleep};
+use super::tags::Tags;
+use crate::config::SimulationConfig;
+
+// a line by line copy of sand_sleep.glsl, sand_activity.glsl, sand_heat.glsl, sand_reaction.glsl, sand_density.glsl, sand_sph_force.glsl,
+// sph.glsl, sand_impulse.glsl, sand_body.glsl and sand_particle.glsl (geometry.glsl is in geometry.rs, fields.glsl in fields.rs,
+// sand_random.glsl and checksum.glsl in rng.rs, the cell list stages are cells::Cells), any change to the shaders has to land here too or tests/gpu_reference.rs fails
+
+/// One tick on the cpu, the ground truth the gpu tick is tested against. Slow, it's only for tests.
+/// `tick` is the number of ticks run before this one, like `World` counts them. `impulses` are the ones
+/// `World::impulse` queued for this tick, only the first `MAX_IMPULSES` are applied like on the gpu.
+/// `sleep` carries which particles are asleep from tick to tick, start it with `Sleep::new` and call
+/// `Sleep::wake_all` wherever the gpu world had its force fields or config changed.
+pub fn tick(
+    particles: &mut [Material],
+    config: &SimulationConfig,
+    reactions: &ReactionTable,
+    geometry: &Geometry,
+    fields: &[Field],
+    impulses: &[Impulse],
+    sleep: &mut Sleep,
+    seed: u32,
+    tick: u32,
+) {
+    let awake = settle(particles, config, impulses, sleep, tick);
+    let cells = Cells::new(particles, config);
+    let mut temperatures = heat(particles, &awake, &cells, config);
+    let mut reacted = react(particles, &awake, &cells, &temperatures, config, reactions, sleep, seed, tick);
+    let density_pressure = sph_density(particles, &cells, config);
+    let accelerations = sph_force(particles, &cells, &density_pressure, config);
+    for (((particle, temperature), reacted), &awake) in particles
+        .iter_mut()
+        .zip(&mut temperatures)
+        .zip(&mut reacted)
+        .zip(&awake)
+    {
+        if awake {
+            apply_impulses(particle, temperature, reacted, impulses);
+        }
+    }
+    let bodies = fit_bodies(particles);
+    for ((((particle, temperature), reacted), acceleration), awake) in particles
+        .iter_mut()
+        .zip(temperatures)
+        .zip(reacted)
+        .zip(accelerations)
+        .zip(awake)
+    {
+        if !awake {
+            continue; // empty or asleep
+        }
+        let tags = Tags::from_bits_retain(particle.tags);
+        if particle.body != 0 {
+            let body = &bodies[particle.body as usize];
+            let field = fields::acceleration(fields, body.centre);
+            let mut vel = [0f32, 0f32];
+            let mut centre = [0f32, 0f32];
+            for axis in 0..2 {
+                vel[axis] = (body.vel[axis] + (config.gravity[axis] + field[axis]) * config.timestep)
+                    * config.damping;
+                centre[axis] = body.centre[axis] + vel[axis] * config.timestep;
+            }
+            let spin = body.spin * config.damping;
+            let turned = [
+                body.rotation[0] - body.rotation[1] * (spin * config.timestep),
+                body.rotation[1] + body.rotation[0] * (spin * config.timestep),
+            ];
+            let length = (turned[0] * turned[0] + turned[1] * turned[1]).sqrt();
+            let rotation = [turned[0] / length, turned[1] / length];
+            let rest = particle.rest;
+            let offset = [
+                rotation[0] * rest[0] - rotation[1] * rest[1],
+                rotation[1] * rest[0] + rotation[0] * rest[1],
+            ];
+            particle.pos = [centre[0] + offset[0], centre[1] + offset[1]];
+            particle.vel = [vel[0] - offset[1] * spin, vel[1] + offset[0] * spin];
+            collide(particle, tags, geometry);
+        } else if tags.contains(Tags::STATIC) {
+            particle.vel = [0f32, 0f32];
+        } else {
+            let kick = [
+                random(seed, tick, particle.id, 0) * 2f32 - 1f32,
+                random(seed, tick, particle.id, 1) * 2f32 - 1f32,
+            ];
+            let field = fields::acceleration(fields, particle.pos);
+            for axis in 0..2 {
+                particle.vel[axis] = (particle.vel[axis]
+                    + (config.gravity[axis] + acceleration[axis] + field[axis]) * config.timestep
+                    + kick[axis] * config.jitter)
+                    * config.damping;
+                particle.pos[axis] += particle.vel[axis] * config.timestep;
+            }
+            collide(particle, tags, geometry);
+        }
+
+        particle.temperature = temperature;
+        let kind = materials::kind(reacted);
+        let next_kind = if tags.contains(Tags::INDESTRUCTIBLE) {
+            reacted
+        } else if tags.contains(Tags::FLAMMABLE)
+            && particle.temperature > materials::IGNITION_TEMPERATURE
+        {
+            materials::FIRE
+        } else {
+            match (kind.colder, kind.hotter) {
+                (Some((colder, below)), _) if particle.temperature < below => colder,
+                (_, Some((hotter, above))) if particle.temperature > above => hotter,
+                _ => reacted,
+            }
+        };
+        if next_kind != particle.kind {
+            let next = materials::kind(next_kind);
+            particle.kind = next_kind;
+            particle.colour = next.colour;
+            particle.gas = next.gas as u32;
+            particle.tags = next.tags.bits();
+            particle.stable = next.stable;
+        }
+    }
+}
+
+// sand_sleep.glsl then sand_activity.glsl, which particles the rest of the tick runs for
+fn settle(
+    particles: &[Material],
+    config: &SimulationConfig,
+    impulses: &[Impulse],
+    sleep: &mut Sleep,
+    tick: u32,
+) -> Vec<bool> {
+    let stamp = tick.wrapping_add(1);
+    let wake_all = std::mem::take(&mut sleep.wake_all);
+    for (m, sleeper) in particles.iter().zip(&mut sleep.sleepers) {
+        if m.id == 0 {
+            continue;
+        }
+        let speed_squared = m.vel[0] * m.vel[0] + m.vel[1] * m.vel[1];
+        let under_impulse = impulses
+            .iter()
+            .take(impulse::MAX_IMPULSES)
+            .any(|impulse| distance(m.pos, impulse.pos) < impulse.radius);
+        let moving = wake_all
+            || sleeper.id != m.id
+            || sleeper.kind != m.kind
+            || (m.temperature - sleeper.temperature).abs() > sleep::SLEEP_TEMPERATURE
+            || speed_squared > config.sleep_speed * config.sleep_speed
+            || under_impulse;
+        let restless = moving || sleeper.busy || m.body != 0 || is_sph(m);
+        sleeper.id = m.id;
+        sleeper.kind = m.kind;
+        sleeper.temperature = m.temperature;
+        sleeper.still = if restless { 0 } else { (sleeper.still + 1).min(config.sleep_ticks) };
+        if !moving {
+            continue;
+        }
+        let chunk = cells::chunk_of(config, m.pos);
+        for y in -1..=1 {
+            for x in -1..=1 {
+                sleep.woken[cells::chunk_slot([chunk[0] + x, chunk[1] + y])] = stamp;
+            }
+        }
+    }
+    particles
+        .iter()
+        .zip(&mut sleep.sleepers)
+        .map(|(m, sleeper)| {
+            if m.id == 0 {
+                return false;
+            }
+            if sleep.woken[cells::chunk_slot(cells::chunk_of(config, m.pos))] == stamp {
+                sleeper.still = 0;
+            }
+            config.sleep_ticks == 0 || sleeper.still < config.sleep_ticks
+        })
+        .collect()
+}
+
+// sand_impulse.glsl, straight into the particle and its heat and reaction results
+fn apply_impulses(
+    particle: &mut Material,
+    temperature: &mut f32,
+    reacted: &mut u32,
+    impulses: &[Impulse],
+) {
+    if Tags::from_bits_retain(particle.tags).contains(Tags::INDESTRUCTIBLE) {
+        return;
+    }
+    for impulse in impulses.iter().take(impulse::MAX_IMPULSES) {
+        let d = distance(particle.pos, impulse.pos);
+        if d >= impulse.radius {
+            continue;
+        }
+        let strength = impulse.strength_at(d);
+        let mut tags = Tags::from_bits_retain(particle.tags);
+        if impulse.ignite && tags.contains(Tags::FLAMMABLE) {
+            *reacted = materials::FIRE;
+            *temperature = temperature.max(materials::kind(materials::FIRE).temperature);
+        }
+        let mut knocked_loose = false;
+        if tags.contains(Tags::STATIC) && particle.body == 0 {
+            if strength <= particle.stable {
+                continue;
+            }
+            tags.remove(Tags::STATIC);
+            knocked_loose = true;
+        }
+        if particle.body != 0 && strength > particle.stable {
+            particle.body = 0;
+            tags.remove(Tags::STATIC);
+            knocked_loose = true;
+        }
+        if knocked_loose && impulse.debris {
+            tags = (tags | Tags::POWDER) - Tags::STICKY;
+        }
+        particle.tags = tags.bits();
+        if d > 0f32 {
+            for axis in 0..2 {
+                particle.vel[axis] +=
+                    (particle.pos[axis] - impulse.pos[axis]) / d * (strength / particle.mass);
+            }
+        }
+    }
+}
+
+// sand_body.glsl's fit of one body
+#[derive(Clone, Copy, Default)]
+struct Body {
+    centre: [f32; 2],
+    vel: [f32; 2],
+    rotation: [f32; 2],
+    spin: f32,
+    mass: f32,
+}
+
+fn cross2(a: [f32; 2], b: [f32; 2]) -> f32 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+fn dot(a: [f32; 2], b: [f32; 2]) -> f32 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+// sand_body.glsl, indexed by body number, adding up in slot order like every invocation there does through its body's cell
+fn fit_bodies(particles: &[Material]) -> Vec<Body> {
+    let count = particles.iter().map(|m| m.body as usize + 1).max().unwrap_or(1);
+    let mut bodies = vec![Body::default(); count];
+    let mut moments = vec![[0f32, 0f32]; count];
+    let mut momenta = vec![[0f32, 0f32]; count];
+    for m in particles.iter().filter(|m| m.id != 0 && m.body != 0) {
+        let b = m.body as usize;
+        bodies[b].mass += m.mass;
+        for axis in 0..2 {
+            moments[b][axis] += m.pos[axis] * m.mass;
+            momenta[b][axis] += m.vel[axis] * m.mass;
+        }
+    }
+    for (b, body) in bodies.iter_mut().enumerate() {
+        if body.mass != 0f32 {
+            body.centre = [moments[b][0] / body.mass, moments[b][1] / body.mass];
+            body.vel = [momenta[b][0] / body.mass, momenta[b][1] / body.mass];
+        }
+    }
+
+    let mut fits = vec![[0f32, 0f32]; count];
+    let mut inertias = vec![0f32; count];
+    let mut angular_momenta = vec![0f32; count];
+    for m in particles.iter().filter(|m| m.id != 0 && m.body != 0) {
+        let b = m.body as usize;
+        let body = bodies[b];
+        let offset = [m.pos[0] - body.centre[0], m.pos[1] - body.centre[1]];
+        fits[b][0] += dot(m.rest, offset) * m.mass;
+        fits[b][1] += cross2(m.rest, offset) * m.mass;
+        inertias[b] += dot(offset, offset) * m.mass;
+        angular_momenta[b] += cross2(offset, [m.vel[0] - body.vel[0], m.vel[1] - body.vel[1]]) * m.mass;
+    }
+    for (b, body) in bodies.iter_mut().enumerate() {
+        let fit = fits[b];
+        body.rotation = if fit == [0f32, 0f32] {
+            [1f32, 0f32]
+        } else {
+            let length = (fit[0] * fit[0] + fit[1] * fit[1]).sqrt();
+            [fit[0] / length, fit[1] / length]
+        };
+        body.spin = if inertias[b] == 0f32 { 0f32 } else { angular_momenta[b] / inertias[b] };
+    }
+    bodies
+}
+
+fn is_sph(m: &Material) -> bool {
+    matches!(materials::kind(m.kind).solver, Solver::Sph(_))
+}
+
+fn fluid(m: &Material) -> materials::Fluid {
+    match materials::kind(m.kind).solver {
+        Solver::Sph(fluid) => fluid,
+        Solver::Granular => unreachable!("only called for sph particles"),
+    }
+}
+
+// sph.glsl
+fn pow5(h: f32) -> f32 {
+    let h2 = h * h;
+    h2 * h2 * h
+}
+
+fn poly6(r: f32, h: f32) -> f32 {
+    let x = h * h - r * r;
+    4f32 / (std::f32::consts::PI * pow5(h) * h * h * h) * x * x * x
+}
+
+fn spiky_gradient(r: f32, h: f32) -> f32 {
+    30f32 / (std::f32::consts::PI * pow5(h)) * (h - r) * (h - r)
+}
+
+fn viscosity_laplacian(r: f32, h: f32) -> f32 {
+    40f32 / (std::f32::consts::PI * pow5(h)) * (h - r)
+}
+
+// sand_density.glsl, [density, pressure] per particle, 0 for anything that isn't sph
+fn sph_density(particles: &[Material], cells: &Cells, config: &SimulationConfig) -> Vec<[f32; 2]> {
+    let h = config.sph_radius;
+    particles
+        .iter()
+        .map(|m| {
+            if m.id == 0 || !is_sph(m) {
+                return [0f32, 0f32];
+            }
+            let mut density = 0f32;
+            for other in cells.around(config, m.pos) {
+                let n = &particles[other];
+                if !is_sph(n) {
+                    continue;
+                }
+                let r = distance(m.pos, n.pos);
+                if r >= h {
+                    continue;
+                }
+                density += n.mass * poly6(r, h);
+            }
+            let fluid = fluid(m);
+            [density, fluid.stiffness * (density - fluid.rest_density).max(0f32)]
+        })
+        .collect()
+}
+
+// sand_sph_force.glsl, the acceleration from pressure and viscosity
+fn sph_force(
+    particles: &[Material],
+    cells: &Cells,
+    density_pressure: &[[f32; 2]],
+    config: &SimulationConfig,
+) -> Vec<[f32; 2]> {
+    let h = config.sph_radius;
+    particles
+        .iter()
+        .enumerate()
+        .map(|(idx, m)| {
+            if m.id == 0 || !is_sph(m) {
+                return [0f32, 0f32];
+            }
+            let own = density_pressure[idx];
+            let mut pressure = [0f32, 0f32];
+            let mut viscosity = [0f32, 0f32];
+            for other in cells.around(config, m.pos) {
+                let n = &particles[other];
+                if other == idx || !is_sph(n) {
+                    continue;
+                }
+                let r = distance(m.pos, n.pos);
+                if r >= h || r == 0f32 {
+                    continue;
+                }
+                let theirs = density_pressure[other];
+                let push = n.mass * (own[1] + theirs[1]) / (2f32 * theirs[0]) * spiky_gradient(r, h);
+                let blend = n.mass / theirs[0] * viscosity_laplacian(r, h);
+                for axis in 0..2 {
+                    pressure[axis] += (m.pos[axis] - n.pos[axis]) / r * push;
+                    viscosity[axis] += (n.vel[axis] - m.vel[axis]) * blend;
+                }
+            }
+            let fluid = fluid(m);
+            [
+                (pressure[0] + viscosity[0] * fluid.viscosity) / own[0],
+                (pressure[1] + viscosity[1] * fluid.viscosity) / own[0],
+            ]
+        })
+        .collect()
+}
+
+// sand_particle.glsl's collide
+fn collide(particle: &mut Material, tags: Tags, geometry: &Geometry) {
+    for shape in &geometry.shapes {
+        let d = geometry.distance(shape, particle.pos);
+        if d >= 0f32 {
+            continue;
+        }
+        let normal = geometry.normal(shape, particle.pos);
+        particle.pos = [particle.pos[0] - normal[0] * d, particle.pos[1] - normal[1] * d];
+        let into = particle.vel[0] * normal[0] + particle.vel[1] * normal[1];
+        if into >= 0f32 {
+            continue;
+        }
+        if particle.body != 0 && -into * particle.mass > particle.stable {
+            particle.body = 0;
+            particle.tags = (Tags::from_bits_retain(particle.tags) - Tags::STATIC).bits();
+        }
+        let restitution = if tags.contains(Tags::POWDER) { 0f32 } else { shape.restitution };
+        let friction = if tags.contains(Tags::LIQUID) { 0f32 } else { shape.friction };
+        particle.vel = if tags.contains(Tags::STICKY) {
+            [0f32, 0f32]
+        } else {
+            let along = [particle.vel[0] - normal[0] * into, particle.vel[1] - normal[1] * into];
+            [
+                along[0] * (1f32 - friction) - normal[0] * into * restitution,
+                along[1] * (1f32 - friction) - normal[1] * into * restitution,
+            ]
+        };
+    }
+}
+
+// sand_heat.glsl, every particle's next temperature from the world as it was before the tick
+fn heat(particles: &[Material], awake: &[bool], cells: &Cells, config: &SimulationConfig) -> Vec<f32> {
+    particles
+        .iter()
+        .enumerate()
+        .map(|(idx, m)| {
+            if !awake[idx] {
+                return m.temperature;
+            }
+            let kind = materials::kind(m.kind);
+            let tags = Tags::from_bits_retain(m.tags);
+            if tags.contains(Tags::FIXED_TEMPERATURE) {
+                return kind.temperature;
+            }
+            if !tags.contains(Tags::CONDUCTIVE) {
+                return m.temperature;
+            }
+            let mut flow = 0f32;
+            for other in cells.around(config, m.pos) {
+                let n = &particles[other];
+                if other == idx || !Tags::from_bits_retain(n.tags).contains(Tags::CONDUCTIVE) {
+                    continue;
+                }
+                let dist = distance(m.pos, n.pos);
+                if dist >= config.heat_radius {
+                    continue;
+                }
+                let conductance = kind.conductivity.min(materials::kind(n.kind).conductivity)
+                    * (1f32 - dist / config.heat_radius);
+                flow += conductance * (n.temperature - m.temperature);
+            }
+            m.temperature + flow * config.timestep / kind.heat_capacity
+        })
+        .collect()
+}
+
+// sand_reaction.glsl, what every particle turns into, from the world as it was before the tick.
+// also which awake particles have a partner they could react with
+fn react(
+    particles: &[Material],
+    awake: &[bool],
+    cells: &Cells,
+    temperatures: &[f32],
+    config: &SimulationConfig,
+    reactions: &ReactionTable,
+    sleep: &mut Sleep,
+    seed: u32,
+    tick: u32,
+) -> Vec<u32> {
+    particles
+        .iter()
+        .enumerate()
+        .map(|(idx, m)| {
+            if !awake[idx] {
+                return m.kind;
+            }
+            let mut product = m.kind;
+            let mut busy = false;
+            if !Tags::from_bits_retain(m.tags).contains(Tags::INDESTRUCTIBLE) {
+                // the lowest slot with a rule, like the shader's early outs end up with
+                let partner = cells
+                    .around(config, m.pos)
+                    .filter_map(|other| {
+                        let n = &particles[other];
+                        let reaction = reactions.get(m.kind, n.kind)?;
+                        (other != idx && distance(m.pos, n.pos) < config.reaction_radius).then_some((other, reaction))
+                    })
+                    .min_by_key(|(other, _)| *other);
+                if let Some((other, reaction)) = partner {
+                    let n = &particles[other];
+                    let hottest = temperatures[idx].max(temperatures[other]);
+                    let pair = pcg(m.id.min(n.id) ^ pcg(m.id.max(n.id)));
+                    busy = hottest >= reaction.min_temperature;
+                    if busy && random(seed, tick, pair, 2) < reaction.probability {
+                        product = reaction.product;
+                    }
+                }
+            }
+            sleep.sleepers[idx].busy = busy;
+            product
+        })
+        .collect()
+}
+
+fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+}
+
+/// Runs ticks `first_tick..first_tick + ticks` without impulses, returns the checksum after each like `World::run_ticks`.
+pub fn run_ticks(
+    particles: &mut [Material],
+    config: &SimulationConfig,
+    reactions: &ReactionTable,
+    geometry: &Geometry,
+    fields: &[Field],
+    sleep: &mut Sleep,
+    seed: u32,
+    first_tick: u32,
+    ticks: u64,
+) -> Vec<u32> {
+    (0..ticks)
+        .map(|i| {
+            let tick_i = first_tick.wrapping_add(i as u32);
+            tick(particles, config, reactions, geometry, fields, &[], sleep, seed, tick_i);
+            checksum(particles)
+        })
+        .collect()
+}
@@ -0,0 +1,55 @@
+use super::sand::sand_shader::Material;
+
+// sand_random.glsl and checksum.glsl on the cpu, shared by the reference tick, the cell list and anything
+// comparing a world to the gpu's checksum
+
+pub fn pcg(v: u32) -> u32 {
+    let state = v.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+/// [0, 1), the same value the shader draws for this particle, tick and stream.
+pub fn random(seed: u32, tick: u32, id: u32, stream: u32) -> f32 {
+    let h = pcg(seed ^ pcg(tick ^ pcg(id ^ pcg(stream))));
+    (h >> 8) as f32 / 16777216f32
+}
+
+/// What the shaders leave in `TickState.checksum`, empty slots don't count. Equal checksums
+/// mean bitwise equal worlds (as far as a 32 bit hash can tell).
+pub fn checksum(particles: &[Material]) -> u32 {
+    particles
+        .iter()
+        .enumerate()
+        .filter(|(_, particle)| particle.id != 0)
+        .fold(0, |checksum, (idx, particle)| {
+            checksum ^ hash_particle(idx as u32, particle)
+        })
+}
+
+// checksum.glsl
+fn hash_particle(idx: u32, m: &Material) -> u32 {
+    let words = [
+        m.colour[0].to_bits(),
+        m.colour[1].to_bits(),
+        m.colour[2].to_bits(),
+        m.id,
+        m.pos[0].to_bits(),
+        m.pos[1].to_bits(),
+        m.vel[0].to_bits(),
+        m.vel[1].to_bits(),
+        m.target[0].to_bits(),
+        m.target[1].to_bits(),
+        m.mass.to_bits(),
+        m.force.to_bits(),
+        m.stable.to_bits(),
+        m.tags,
+        m.gas,
+        m.kind,
+        m.temperature.to_bits(),
+        m.body,
+        m.rest[0].to_bits(),
+        m.rest[1].to_bits(),
+    ];
+    words.iter().fold(pcg(idx), |h, word| pcg(h ^ word))
+}
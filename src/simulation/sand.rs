@@ -1,13 +1,21 @@
 use std::sync::Arc;
 
 use vulkano::buffer::Subbuffer;
-use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage};
-use vulkano::command_buffer::{CommandBufferExecFuture, PrimaryAutoCommandBuffer};
-use vulkano::device::{Device, Queue};
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::{CommandBufferExecFuture, DispatchIndirectCommand, PrimaryAutoCommandBuffer};
+use vulkano::device::Queue;
 use vulkano::padded::Padded;
-use vulkano::sync::future::{FenceSignalFuture, NowFuture};
+use vulkano::sync::future::SemaphoreSignalFuture;
+use vulkano::sync::{GpuFuture, Sharing};
 
+use super::fields::{self, Field};
+use super::geometry::Geometry;
+use super::impulse;
+use super::materials;
+use super::reactions::ReactionTable;
+use crate::config::SimulationConfig;
 use crate::deploy_shader;
+use crate::error::{Result, SandError};
 use vulkano::memory::allocator::{AllocationCreateInfo, MemoryAllocator, MemoryUsage};
 
 pub mod sand_shader {
@@ -18,7 +26,87 @@ pub mod sand_shader {
     }
 }
 
-pub const PADDING: usize = 4;
+// the tick's first four stages, which put particles to sleep and build the cell list, see cells.glsl
+pub mod sleep_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/shaders/sand_sleep.glsl",
+    }
+}
+
+pub mod cells_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/shaders/sand_cells.glsl",
+    }
+}
+
+pub mod activity_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/shaders/sand_activity.glsl",
+    }
+}
+
+pub mod cell_sort_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/shaders/sand_cell_sort.glsl",
+    }
+}
+
+// the fifth, see sand_heat.glsl
+pub mod heat_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/shaders/sand_heat.glsl",
+    }
+}
+
+// the sixth, see sand_reaction.glsl
+pub mod reaction_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/shaders/sand_reaction.glsl",
+        custom_derives: [Debug,Clone,Copy,],
+    }
+}
+
+// the seventh and eighth, see sph.glsl
+pub mod density_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/shaders/sand_density.glsl",
+    }
+}
+
+pub mod sph_force_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/shaders/sand_sph_force.glsl",
+    }
+}
+
+// the ninth, see sand_impulse.glsl
+pub mod impulse_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/shaders/sand_impulse.glsl",
+    }
+}
+
+// the tenth, see sand_body.glsl
+pub mod body_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "src/shaders/sand_body.glsl",
+    }
+}
+
+// Material fills its 80 bytes since it has a body and rest position, see material.glsl
+pub const PADDING: usize = 0;
+// Kind is 68 bytes but 80 apart in the kind table
+pub const KIND_PADDING: usize = 12;
 
 impl Default for sand_shader::Material {
     fn default() -> sand_shader::Material {
@@ -31,28 +119,43 @@ impl Default for sand_shader::Material {
             target: [0f32, 0f32],
             force: 0f32,
             stable: 0f32,
-            tags: 0,
+            tags: materials::kind(materials::SAND).tags.bits(),
             gas: 0,
+            kind: materials::SAND,
+            temperature: materials::AMBIENT_TEMPERATURE,
+            body: 0,
+            rest: [0f32, 0f32],
         }
     }
 }
 
-pub fn tick(
-    device: &Arc<Device>,
+/// Runs one tick after `after`, anything chained onto the returned future waits on its semaphore so it sees the whole tick.
+pub fn tick<F: GpuFuture>(
+    after: F,
     queue: &Arc<Queue>,
     command: Arc<PrimaryAutoCommandBuffer>,
-) -> FenceSignalFuture<CommandBufferExecFuture<NowFuture>> {
-    deploy_shader::deploy(device.clone(), queue.clone(), command)
+) -> Result<SemaphoreSignalFuture<CommandBufferExecFuture<F>>> {
+    deploy_shader::deploy(after, queue.clone(), command)
 }
 
+/// `queue_families` are all the families that touch the buffer, more than one makes it concurrent
+/// so the compute and graphics queues don't need ownership transfers every frame.
 pub fn upload_device_buffer(
     memory_allocator: &(impl MemoryAllocator + ?Sized),
     size: u64,
-) -> Subbuffer<[Padded<sand_shader::Material, PADDING>]> {
+    queue_families: &[u32],
+) -> Result<Subbuffer<[Padded<sand_shader::Material, PADDING>]>> {
+    let sharing = if queue_families.len() > 1 {
+        Sharing::Concurrent(queue_families.into())
+    } else {
+        Sharing::Exclusive
+    };
     Buffer::new_slice(
         memory_allocator,
         BufferCreateInfo {
-            usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST, // you need to be able to copy to a device only buffer so this is fine
+            // copied to and from, the world into its snapshots
+            usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_SRC | BufferUsage::TRANSFER_DST,
+            sharing,
             ..Default::default()
         },
         AllocationCreateInfo {
@@ -61,7 +164,7 @@ pub fn upload_device_buffer(
         },
         size,
     )
-    .expect("failed to create buffer")
+    .map_err(|e| SandError::allocation("world buffer", e))
 }
 ///! Slow and generally shouldn't be used, use a device and transfer buffer with download.
 // pub fn upload_standard_buffer(
@@ -86,7 +189,7 @@ pub fn upload_device_buffer(
 pub fn upload_transfer_source_buffer(
     data: Vec<Padded<sand_shader::Material, PADDING>>,
     memory_allocator: &(impl MemoryAllocator + ?Sized),
-) -> Subbuffer<[Padded<sand_shader::Material, PADDING>]> {
+) -> Result<Subbuffer<[Padded<sand_shader::Material, PADDING>]>> {
     Buffer::from_iter(
         memory_allocator,
         BufferCreateInfo {
@@ -99,5 +202,213 @@ pub fn upload_transfer_source_buffer(
         },
         data,
     )
-    .expect("failed to create buffer")
+    .map_err(|e| SandError::allocation("world staging buffer", e))
+}
+
+impl From<&SimulationConfig> for sand_shader::Params {
+    fn from(config: &SimulationConfig) -> sand_shader::Params {
+        sand_shader::Params {
+            gravity: config.gravity,
+            damping: config.damping,
+            timestep: config.timestep,
+            jitter: config.jitter,
+            heat_radius: config.heat_radius,
+            reaction_radius: config.reaction_radius,
+            sph_radius: config.sph_radius,
+            sleep_speed: config.sleep_speed,
+            sleep_ticks: config.sleep_ticks,
+        }
+    }
+}
+
+/// One per frame slot, host visible as the cpu writes the tick number before every submission
+/// and reads the checksum back after it.
+pub fn upload_tick_state_buffers(
+    memory_allocator: &(impl MemoryAllocator + ?Sized),
+    count: usize,
+) -> Result<Vec<Subbuffer<sand_shader::TickState>>> {
+    (0..count)
+        .map(|_| {
+            Buffer::from_data(
+                memory_allocator,
+                BufferCreateInfo {
+                    usage: BufferUsage::STORAGE_BUFFER,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    usage: MemoryUsage::Upload,
+                    ..Default::default()
+                },
+                sand_shader::TickState {
+                    tick: 0,
+                    seed: 0,
+                    checksum: 0,
+                    impulse_count: 0,
+                    wake_all: 0,
+                    awake: 0,
+                    bodies: 0,
+                    impulses: [impulse::NO_IMPULSE; impulse::MAX_IMPULSES],
+                },
+            )
+            .map_err(|e| SandError::allocation("tick state buffer", e))
+        })
+        .collect()
+}
+
+/// Host visible so a config reload can write it, only do that while no tick is in flight.
+pub fn upload_params_buffer(
+    memory_allocator: &(impl MemoryAllocator + ?Sized),
+    config: &SimulationConfig,
+) -> Result<Subbuffer<sand_shader::Params>> {
+    Buffer::from_data(
+        memory_allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::UNIFORM_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::Upload,
+            ..Default::default()
+        },
+        sand_shader::Params::from(config),
+    )
+    .map_err(|e| SandError::allocation("simulation params buffer", e))
+}
+
+pub fn upload_kind_table(
+    memory_allocator: &(impl MemoryAllocator + ?Sized),
+) -> Result<Subbuffer<[Padded<sand_shader::Kind, KIND_PADDING>]>> {
+    Buffer::from_iter(
+        memory_allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::Upload,
+            ..Default::default()
+        },
+        materials::gpu_table(),
+    )
+    .map_err(|e| SandError::allocation("kind table", e))
+}
+
+pub fn upload_reaction_table(
+    memory_allocator: &(impl MemoryAllocator + ?Sized),
+    reactions: &ReactionTable,
+) -> Result<Subbuffer<[reaction_shader::Reaction]>> {
+    Buffer::from_iter(
+        memory_allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::Upload,
+            ..Default::default()
+        },
+        reactions.gpu_table(),
+    )
+    .map_err(|e| SandError::allocation("reaction table", e))
+}
+
+/// Read only after this, the scene's geometry never changes while it runs.
+pub fn upload_geometry(
+    memory_allocator: &(impl MemoryAllocator + ?Sized),
+    geometry: &Geometry,
+) -> Result<(Subbuffer<[sand_shader::Shape]>, Subbuffer<[[f32; 2]]>)> {
+    let create_info = || BufferCreateInfo {
+        usage: BufferUsage::STORAGE_BUFFER,
+        ..Default::default()
+    };
+    let allocation_info = || AllocationCreateInfo {
+        usage: MemoryUsage::Upload,
+        ..Default::default()
+    };
+    let shapes = Buffer::from_iter(memory_allocator, create_info(), allocation_info(), geometry.gpu_shapes())
+        .map_err(|e| SandError::allocation("geometry shapes", e))?;
+    let vertices = Buffer::from_iter(memory_allocator, create_info(), allocation_info(), geometry.gpu_vertices())
+        .map_err(|e| SandError::allocation("geometry vertices", e))?;
+    Ok((shapes, vertices))
+}
+
+/// Device only, `World` copies the fields in from `upload_field_staging_buffer` whenever they change.
+/// Zeroed it's all FIELD_NONE.
+pub fn upload_field_buffer(
+    memory_allocator: &(impl MemoryAllocator + ?Sized),
+) -> Result<Subbuffer<[sand_shader::Field]>> {
+    Buffer::new_slice(
+        memory_allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::DeviceOnly,
+            ..Default::default()
+        },
+        fields::MAX_FIELDS as u64,
+    )
+    .map_err(|e| SandError::allocation("force fields", e))
+}
+
+pub fn upload_field_staging_buffer(
+    memory_allocator: &(impl MemoryAllocator + ?Sized),
+    fields: &[Field],
+) -> Result<Subbuffer<[sand_shader::Field]>> {
+    Buffer::from_iter(
+        memory_allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::Upload,
+            ..Default::default()
+        },
+        fields::gpu_fields(fields),
+    )
+    .map_err(|e| SandError::allocation("force field staging buffer", e))
+}
+
+/// Where a stage counts the work groups for a later one, see `deploy_shader::Dispatch`.
+pub fn upload_dispatch_buffer(
+    memory_allocator: &(impl MemoryAllocator + ?Sized),
+    what: &'static str,
+) -> Result<Subbuffer<[DispatchIndirectCommand]>> {
+    Buffer::new_slice(
+        memory_allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER | BufferUsage::INDIRECT_BUFFER | BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::DeviceOnly,
+            ..Default::default()
+        },
+        1,
+    )
+    .map_err(|e| SandError::allocation(what, e))
+}
+
+/// Where a stage leaves its results for the next one. Only the compute queue touches it.
+/// A transfer destination too, for the ones that have to start out zeroed.
+pub fn upload_scratch_buffer<T: BufferContents>(
+    memory_allocator: &(impl MemoryAllocator + ?Sized),
+    size: u64,
+    what: &'static str,
+) -> Result<Subbuffer<[T]>> {
+    Buffer::new_slice(
+        memory_allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::DeviceOnly,
+            ..Default::default()
+        },
+        size,
+    )
+    .map_err(|e| SandError::allocation(what, e))
 }
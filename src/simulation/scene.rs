@@ -0,0 +1,517 @@
+use std::fmt::Write as _;
+use std::path::Path;
+
+use super::bodies;
+use super::fields::{Field, MAX_FIELDS};
+use super::geometry::{self, Geometry, Surface};
+use super::impulse::Falloff;
+use super::materials;
+use super::sand::sand_shader::Material;
+use super::tags::Tags;
+use crate::error::{Result, SandError};
+
+// one entry per line, blank lines and # comments are skipped:
+//   particle <x> <y> [<r> <g> <b> [<vx> <vy> [<kind> <temperature> [<tags> <stable> [<body>]]]]]
+//   surface <restitution> <friction>        applies to the shapes after it
+//   box <cx> <cy> <half_width> <half_height>
+//   circle <cx> <cy> <radius>
+//   capsule <ax> <ay> <bx> <by> <radius>
+//   polygon <x> <y> <x> <y> <x> <y> ...     at least three corners, either winding
+//   bounds <x0> <y0> <x1> <y1>              particles are kept inside
+//   wind <cx> <cy> <half_width> <half_height> <ax> <ay>
+//   attractor <cx> <cy> <radius> <strength> [<falloff>]   a negative strength repels
+//   vortex <cx> <cy> <radius> <strength> [<falloff>]      anticlockwise for a positive strength
+// falloff is 0 for none, 1 for linear (the default) or 2 for quadratic, see fields.rs for the force fields.
+// missing particle values take Material::default, ids are handed out in file order starting at 1.
+// kind indexes materials::KINDS and is written as a number like everything else. tags (Tags bits) and stable
+// default to the kind's, save writes them so a particle knocked loose doesn't come back static. particles with
+// the same body number above 0 make one rigid body shaped the way they're placed, see bodies.rs
+
+/// Everything a scene file describes.
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    pub particles: Vec<Material>,
+    pub geometry: Geometry,
+    pub fields: Vec<Field>,
+}
+
+/// Reads a scene file, errors point at the line that's wrong.
+pub fn load(path: &Path) -> Result<Scene> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| SandError::Config(format!("couldn't read scene {}: {e}", path.display())))?;
+    parse(&text, &path.display().to_string())
+}
+
+// `origin` is the file for error messages
+fn parse(text: &str, origin: &str) -> Result<Scene> {
+    let mut scene = Scene::default();
+    let mut surface = Surface::default();
+    for (line_i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut words = line.split_whitespace();
+        let Some(entry) = words.next() else {
+            continue;
+        };
+        let bad_line = |reason: String| {
+            SandError::Config(format!("{origin}:{}: {reason}", line_i + 1))
+        };
+        let values = words
+            .map(|word| {
+                word.parse::<f32>()
+                    .map_err(|_| bad_line(format!("{word:?} isn't a number")))
+            })
+            .collect::<Result<Vec<f32>>>()?;
+        let expect = |fits: bool, form: &str| {
+            if fits {
+                Ok(())
+            } else {
+                Err(bad_line(format!("expected {entry} {form}, got {} numbers", values.len())))
+            }
+        };
+        let v = &values;
+        match entry {
+            "particle" => {
+                expect(
+                    matches!(v.len(), 2 | 5 | 7 | 9 | 11 | 12),
+                    "x y [r g b [vx vy [kind temperature [tags stable [body]]]]]",
+                )?;
+                let id = scene.particles.len() as u32 + 1;
+                scene.particles.push(particle(id, v).map_err(&bad_line)?);
+            }
+            "surface" => {
+                expect(v.len() == 2, "restitution friction")?;
+                if !v.iter().all(|value| (0f32..=1f32).contains(value)) {
+                    return Err(bad_line("restitution and friction have to be between 0 and 1".to_string()));
+                }
+                surface = Surface {
+                    restitution: v[0],
+                    friction: v[1],
+                };
+            }
+            "box" => {
+                expect(v.len() == 4, "cx cy half_width half_height")?;
+                // written so a nan is rejected too
+                if !(v[2] > 0f32 && v[3] > 0f32) {
+                    return Err(bad_line("a box needs a half width and half height above 0".to_string()));
+                }
+                scene.geometry.add_box([v[0], v[1]], [v[2], v[3]], surface);
+            }
+            "circle" => {
+                expect(v.len() == 3, "cx cy radius")?;
+                if !(v[2] > 0f32) {
+                    return Err(bad_line("a circle needs a radius above 0".to_string()));
+                }
+                scene.geometry.add_circle([v[0], v[1]], v[2], surface);
+            }
+            "capsule" => {
+                expect(v.len() == 5, "ax ay bx by radius")?;
+                if !(v[4] > 0f32) {
+                    return Err(bad_line("a capsule needs a radius above 0".to_string()));
+                }
+                if [v[0], v[1]] == [v[2], v[3]] {
+                    return Err(bad_line("a capsule's ends can't be the same point".to_string()));
+                }
+                scene.geometry.add_capsule([v[0], v[1]], [v[2], v[3]], v[4], surface);
+            }
+            "polygon" => {
+                expect(v.len() >= 6 && v.len() % 2 == 0, "x y x y x y ...")?;
+                let corners: Vec<[f32; 2]> = v.chunks(2).map(|corner| [corner[0], corner[1]]).collect();
+                // the last corner joins back up to the first
+                let repeated = (0..corners.len()).find(|&i| corners[i] == corners[(i + 1) % corners.len()]);
+                if let Some(i) = repeated {
+                    let corner = (i + 1) % corners.len() + 1;
+                    return Err(bad_line(format!("polygon corner {corner} repeats the one before it")));
+                }
+                scene.geometry.add_polygon(&corners, surface);
+            }
+            "bounds" => {
+                expect(v.len() == 4, "x0 y0 x1 y1")?;
+                if v[2] <= v[0] || v[3] <= v[1] {
+                    return Err(bad_line("bounds need x1 above x0 and y1 above y0".to_string()));
+                }
+                scene.geometry.add_bounds([v[0], v[1]], [v[2], v[3]], surface);
+            }
+            "wind" | "attractor" | "vortex" => {
+                if scene.fields.len() == MAX_FIELDS {
+                    return Err(bad_line(format!("a scene can have at most {MAX_FIELDS} force fields")));
+                }
+                scene.fields.push(parse_field(entry, v).map_err(&bad_line)?);
+            }
+            other => return Err(bad_line(format!("unknown entry {other:?}"))),
+        }
+    }
+    // renumbered from 1 in the order they first show up, body numbers index a buffer on the gpu
+    let mut groups: Vec<(u32, Vec<usize>)> = Vec::new();
+    for (i, particle) in scene.particles.iter().enumerate().filter(|(_, p)| p.body != 0) {
+        match groups.iter_mut().find(|(label, _)| *label == particle.body) {
+            Some((_, members)) => members.push(i),
+            None => groups.push((particle.body, vec![i])),
+        }
+    }
+    if groups.len() >= bodies::MAX_BODIES {
+        return Err(SandError::Config(format!(
+            "{origin} has {} bodies but a world holds at most {}",
+            groups.len(),
+            bodies::MAX_BODIES - 1
+        )));
+    }
+    for (number, (_, members)) in groups.iter().enumerate() {
+        let mut body: Vec<Material> = members.iter().map(|&i| scene.particles[i]).collect();
+        bodies::make_body(&mut body, number as u32 + 1);
+        for (&i, particle) in members.iter().zip(body) {
+            scene.particles[i] = particle;
+        }
+    }
+    Ok(scene)
+}
+
+fn particle(id: u32, values: &[f32]) -> std::result::Result<Material, String> {
+    let mut particle = Material {
+        id,
+        pos: [values[0], values[1]],
+        ..Default::default()
+    };
+    if values.len() >= 5 {
+        particle.colour = [values[2], values[3], values[4]];
+    }
+    if values.len() >= 7 {
+        particle.vel = [values[5], values[6]];
+    }
+    if values.len() >= 9 {
+        let kind = values[7];
+        if kind.fract() != 0f32 || kind < 0f32 || kind as usize >= materials::KINDS.len() {
+            return Err(format!("kind {kind} isn't one of 0 to {}", materials::KINDS.len() - 1));
+        }
+        particle.kind = kind as u32;
+        particle.gas = materials::kind(particle.kind).gas as u32;
+        particle.tags = materials::kind(particle.kind).tags.bits();
+        particle.stable = materials::kind(particle.kind).stable;
+        particle.temperature = values[8];
+    }
+    if values.len() >= 11 {
+        let (tags, stable) = (values[9], values[10]);
+        let known = Tags::from_bits(tags as u32).filter(|_| tags.fract() == 0f32 && tags >= 0f32);
+        let Some(tags) = known else {
+            return Err(format!("tags {tags} aren't a combination of the Tags bits"));
+        };
+        if !(stable >= 0f32) {
+            return Err(format!("stable {stable} can't be negative"));
+        }
+        particle.tags = tags.bits();
+        particle.stable = stable;
+    }
+    if values.len() == 12 {
+        let body = values[11];
+        if body.fract() != 0f32 || body < 0f32 {
+            return Err(format!("body {body} isn't a whole number"));
+        }
+        particle.body = body as u32;
+    }
+    Ok(particle)
+}
+
+/// A wind, attractor or vortex entry's values, recordings store placed fields the same way.
+pub fn parse_field(entry: &str, values: &[f32]) -> std::result::Result<Field, String> {
+    let v = values;
+    let falloff = |values: &[f32]| match values.get(4) {
+        None => Ok(Falloff::Linear),
+        Some(&falloff) => Falloff::from_gpu(falloff as u32)
+            .filter(|_| falloff.fract() == 0f32 && falloff >= 0f32)
+            .ok_or_else(|| format!("falloff {falloff} isn't 0, 1 or 2")),
+    };
+    match entry {
+        "wind" if v.len() == 6 => {
+            // written so a nan is rejected too
+            if !(v[2] > 0f32 && v[3] > 0f32) {
+                return Err("a wind needs a half width and half height above 0".to_string());
+            }
+            Ok(Field::Wind {
+                centre: [v[0], v[1]],
+                half_size: [v[2], v[3]],
+                acceleration: [v[4], v[5]],
+            })
+        }
+        "wind" => Err(format!("expected wind cx cy half_width half_height ax ay, got {} numbers", v.len())),
+        "attractor" | "vortex" if matches!(v.len(), 4 | 5) => {
+            if !(v[2] > 0f32) {
+                return Err(format!("a {entry} needs a radius above 0"));
+            }
+            let (centre, radius, strength, falloff) = ([v[0], v[1]], v[2], v[3], falloff(v)?);
+            Ok(if entry == "attractor" {
+                Field::Attractor { centre, radius, strength, falloff }
+            } else {
+                Field::Vortex { centre, radius, strength, falloff }
+            })
+        }
+        "attractor" | "vortex" => Err(format!(
+            "expected {entry} cx cy radius strength [falloff], got {} numbers",
+            v.len()
+        )),
+        other => Err(format!("{other:?} isn't a force field")),
+    }
+}
+
+/// One line of a scene file, `parse_field` reads it back.
+pub fn field_line(field: &Field) -> String {
+    match *field {
+        Field::Wind {
+            centre,
+            half_size,
+            acceleration,
+        } => format!(
+            "wind {} {} {} {} {} {}",
+            centre[0], centre[1], half_size[0], half_size[1], acceleration[0], acceleration[1]
+        ),
+        Field::Attractor {
+            centre,
+            radius,
+            strength,
+            falloff,
+        } => format!("attractor {} {} {radius} {strength} {}", centre[0], centre[1], falloff.gpu()),
+        Field::Vortex {
+            centre,
+            radius,
+            strength,
+            falloff,
+        } => format!("vortex {} {} {radius} {strength} {}", centre[0], centre[1], falloff.gpu()),
+    }
+}
+
+/// Writes particles, geometry and force fields in the format `load` reads, so a run can be picked up again.
+pub fn save(path: &Path, particles: &[Material], geometry: &Geometry, fields: &[Field]) -> Result<()> {
+    let mut text = String::new();
+    let mut surface = Surface::default();
+    for shape in &geometry.shapes {
+        if (shape.restitution, shape.friction) != (surface.restitution, surface.friction) {
+            surface = Surface {
+                restitution: shape.restitution,
+                friction: shape.friction,
+            };
+            writeln!(text, "surface {} {}", surface.restitution, surface.friction).unwrap();
+        }
+        let [a, b] = [shape.a, shape.b];
+        match shape.kind {
+            geometry::SHAPE_BOX => writeln!(text, "box {} {} {} {}", a[0], a[1], b[0], b[1]),
+            geometry::SHAPE_CIRCLE => writeln!(text, "circle {} {} {}", a[0], a[1], shape.radius),
+            geometry::SHAPE_CAPSULE => {
+                writeln!(text, "capsule {} {} {} {} {}", a[0], a[1], b[0], b[1], shape.radius)
+            }
+            geometry::SHAPE_POLYGON => {
+                let first = shape.first_vertex as usize;
+                let corners = &geometry.vertices[first..first + shape.vertex_count as usize];
+                let corners: Vec<String> = corners.iter().map(|c| format!("{} {}", c[0], c[1])).collect();
+                writeln!(text, "polygon {}", corners.join(" "))
+            }
+            geometry::SHAPE_BOUNDS => writeln!(
+                text,
+                "bounds {} {} {} {}",
+                a[0] - b[0],
+                a[1] - b[1],
+                a[0] + b[0],
+                a[1] + b[1]
+            ),
+            _ => Ok(()),
+        }
+        .unwrap();
+    }
+    for field in fields {
+        writeln!(text, "{}", field_line(field)).unwrap();
+    }
+    text.push_str("# particle x y r g b vx vy kind temperature tags stable body\n");
+    for particle in particles {
+        writeln!(
+            text,
+            "particle {} {} {} {} {} {} {} {} {} {} {} {}",
+            particle.pos[0],
+            particle.pos[1],
+            particle.colour[0],
+            particle.colour[1],
+            particle.colour[2],
+            particle.vel[0],
+            particle.vel[1],
+            particle.kind,
+            particle.temperature,
+            particle.tags,
+            particle.stable,
+            particle.body,
+        )
+        .unwrap();
+    }
+    std::fs::write(path, text)
+        .map_err(|e| SandError::Config(format!("couldn't write {}: {e}", path.display())))
+}
+
+/// The default scene, a row of `count` grey particles, `seed` varies the shade of each one.
+pub fn generate(count: u32, seed: u64) -> Vec<Material> {
+    let mut rng = SplitMix64(seed);
+    (1..=count)
+        .map(|i| {
+            let i_f = i as f32;
+            let shade = i_f / 100f32 * (0.85 + 0.15 * rng.next_f32());
+            Material {
+                id: i,
+                colour: [shade, shade, shade],
+                pos: [i_f, 100f32],
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+// small and good enough for scene generation, not for anything on the gpu
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // [0, 1)
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the error has to point at `line` and mention `expected`
+    fn rejects(text: &str, line: usize, expected: &str) {
+        match parse(text, "test.scene") {
+            Err(SandError::Config(message)) => {
+                assert!(message.starts_with(&format!("test.scene:{line}: ")), "{message}");
+                assert!(message.contains(expected), "no {expected:?} in {message}");
+            }
+            Err(e) => panic!("{text:?} gave {e}"),
+            Ok(_) => panic!("{text:?} should have been rejected"),
+        }
+    }
+
+    #[test]
+    fn every_entry_parses() {
+        let scene = parse(
+            "# a comment\n\
+             \n\
+             surface 0.5 0.2\n\
+             box 0 0 10 5\n\
+             circle 10 10 5 # trailing comment\n\
+             capsule 0 0 10 0 2\n\
+             polygon 0 0 10 0 10 10\n\
+             bounds 0 0 100 50\n\
+             wind 0 0 10 10 1 0\n\
+             attractor 0 0 10 5\n\
+             vortex 0 0 10 5 2\n\
+             particle 1 2\n\
+             particle 1 2 0.5 0.5 0.5 3 4 1 20\n",
+            "test.scene",
+        )
+        .unwrap();
+        assert_eq!(scene.geometry.shapes.len(), 5);
+        assert_eq!(scene.geometry.vertices.len(), 3);
+        assert!(scene.geometry.shapes.iter().all(|shape| shape.restitution == 0.5 && shape.friction == 0.2));
+        assert_eq!(scene.fields.len(), 3);
+        assert_eq!(scene.particles.len(), 2);
+        assert_eq!(scene.particles[1].id, 2);
+        assert_eq!(scene.particles[1].vel, [3f32, 4f32]);
+        assert_eq!(scene.particles[1].kind, 1);
+    }
+
+    #[test]
+    fn bad_lines_are_rejected_with_their_line_number() {
+        rejects("box 0 0 1 1\n# fine so far\nwall 0 0 1 1\n", 3, "unknown entry \"wall\"");
+        rejects("box 0 x 1 1", 1, "\"x\" isn't a number");
+        rejects("circle 0 0", 1, "expected circle cx cy radius, got 2 numbers");
+    }
+
+    #[test]
+    fn bad_particles_are_rejected() {
+        rejects("particle 1", 1, "expected particle");
+        rejects("particle 1 2 3", 1, "got 3 numbers");
+        rejects("particle 0 0 1 1 1 0 0 99 0", 1, "kind 99");
+        rejects("particle 0 0 1 1 1 0 0 1.5 0", 1, "kind 1.5");
+        rejects("particle 0 0 1 1 1 0 0 0 0 0.5 0", 1, "tags 0.5");
+        rejects("particle 0 0 1 1 1 0 0 0 0 0 -1", 1, "stable -1");
+        rejects("particle 0 0 1 1 1 0 0 0 0 0 0 -2", 1, "body -2");
+    }
+
+    #[test]
+    fn bad_shapes_are_rejected() {
+        rejects("surface 1.5 0", 1, "between 0 and 1");
+        rejects("box 0 0 0 5", 1, "half width and half height above 0");
+        rejects("box 0 0 NaN 5", 1, "half width and half height above 0");
+        rejects("circle 0 0 -1", 1, "radius above 0");
+        rejects("capsule 0 0 1 1 0", 1, "radius above 0");
+        rejects("capsule 1 1 1 1 2", 1, "can't be the same point");
+        rejects("polygon 0 0 1 1", 1, "x y x y x y");
+        rejects("polygon 0 0 1 0 1", 1, "x y x y x y");
+        rejects("polygon 0 0 0 0 1 1", 1, "corner 2 repeats");
+        rejects("polygon 0 0 1 0 1 1 0 0", 1, "corner 1 repeats");
+        rejects("bounds 10 0 0 10", 1, "x1 above x0");
+        rejects("bounds 0 0 10 0", 1, "y1 above y0");
+    }
+
+    #[test]
+    fn too_many_fields_are_rejected() {
+        let text = "wind 0 0 1 1 1 0\n".repeat(MAX_FIELDS + 1);
+        rejects(&text, MAX_FIELDS + 1, "at most");
+        assert!(parse(&"wind 0 0 1 1 1 0\n".repeat(MAX_FIELDS), "test.scene").is_ok());
+    }
+
+    fn rejects_field(entry: &str, values: &[f32], expected: &str) {
+        match parse_field(entry, values) {
+            Err(message) => assert!(message.contains(expected), "no {expected:?} in {message}"),
+            Ok(field) => panic!("{entry} {values:?} gave {field:?}"),
+        }
+    }
+
+    #[test]
+    fn bad_fields_are_rejected() {
+        rejects_field("wind", &[0f32, 0f32, 0f32, 1f32, 1f32, 0f32], "half width and half height above 0");
+        rejects_field("wind", &[0f32, 0f32, 1f32, -1f32, 1f32, 0f32], "half width and half height above 0");
+        rejects_field("wind", &[0f32, 0f32, f32::NAN, 1f32, 1f32, 0f32], "half width and half height above 0");
+        rejects_field("wind", &[0f32, 0f32, 1f32, 1f32, 1f32], "got 5 numbers");
+        rejects_field("attractor", &[0f32, 0f32, 0f32, 5f32], "radius above 0");
+        rejects_field("attractor", &[0f32, 0f32, f32::NAN, 5f32], "radius above 0");
+        rejects_field("vortex", &[0f32, 0f32, -1f32, 5f32], "radius above 0");
+        rejects_field("vortex", &[0f32, 0f32, 10f32], "got 3 numbers");
+        rejects_field("attractor", &[0f32, 0f32, 10f32, 5f32, 3f32], "falloff 3");
+        rejects_field("attractor", &[0f32, 0f32, 10f32, 5f32, 1.5f32], "falloff 1.5");
+        rejects_field("vortex", &[0f32, 0f32, 10f32, 5f32, -1f32], "falloff -1");
+        rejects_field("fan", &[0f32, 0f32, 10f32, 5f32], "isn't a force field");
+    }
+
+    #[test]
+    fn fields_read_back_what_field_line_writes() {
+        let fields = [
+            Field::wind_brush([10f32, 20f32]),
+            Field::repulsor_brush([-5f32, 0.5f32]),
+            Field::Vortex {
+                centre: [1f32, 2f32],
+                radius: 30f32,
+                strength: -7.25f32,
+                falloff: Falloff::Quadratic,
+            },
+            Field::Attractor {
+                centre: [0f32, 0f32],
+                radius: 1f32,
+                strength: 1f32,
+                falloff: Falloff::None,
+            },
+        ];
+        for field in fields {
+            let line = field_line(&field);
+            let mut words = line.split_whitespace();
+            let entry = words.next().unwrap();
+            let values: Vec<f32> = words.map(|word| word.parse().unwrap()).collect();
+            assert_eq!(parse_field(entry, &values), Ok(field), "{line}");
+        }
+        // the falloff is optional
+        let field = parse_field("vortex", &[0f32, 0f32, 10f32, 5f32]).unwrap();
+        assert!(matches!(field, Field::Vortex { falloff: Falloff::Linear, .. }));
+    }
+}
@@ -0,0 +1,37 @@
+use super::cells::CHUNK_COUNT;
+
+// mirrors sand_sleep.glsl
+pub const SLEEP_TEMPERATURE: f32 = 0.01;
+
+/// Sleeper in material.glsl.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sleeper {
+    pub id: u32,
+    pub kind: u32,
+    pub temperature: f32,
+    pub still: u32,
+    pub busy: bool,
+}
+
+/// What `World` keeps on the gpu between ticks to put particles to sleep, for `reference::tick`.
+pub struct Sleep {
+    pub sleepers: Vec<Sleeper>,
+    pub woken: Vec<u32>, // tick + 1 per chunk stamp
+    pub wake_all: bool,  // for the next tick
+}
+
+impl Sleep {
+    /// For a world of `len` particle slots, everything in it counts as new on the first tick like on the gpu.
+    pub fn new(len: usize) -> Sleep {
+        Sleep {
+            sleepers: vec![Sleeper::default(); len],
+            woken: vec![0; CHUNK_COUNT],
+            wake_all: false,
+        }
+    }
+
+    /// Wakes everything on the next tick, `World` does this when the force fields or the config change.
+    pub fn wake_all(&mut self) {
+        self.wake_all = true;
+    }
+}
@@ -0,0 +1,60 @@
+use bitflags::bitflags;
+
+bitflags! {
+    /// `Material.tags`, every bit is mirrored in tags.glsl. A particle gets its
+    /// kind's tags when it's spawned and whenever it turns into another kind.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct Tags: u32 {
+        /// Never moves, integration leaves it where it is.
+        const STATIC = 1 << 0;
+        /// Turns into fire once it's hotter than `materials::IGNITION_TEMPERATURE`.
+        const FLAMMABLE = 1 << 1;
+        /// Exchanges heat with neighbours, heat only flows between two conductive particles.
+        const CONDUCTIVE = 1 << 2;
+        /// Slides along geometry without friction, drawn see-through.
+        const LIQUID = 1 << 3;
+        /// Doesn't bounce off geometry, so it piles up.
+        const POWDER = 1 << 4;
+        /// Stops dead on touching geometry.
+        const STICKY = 1 << 5;
+        /// Never reacts or changes phase.
+        const INDESTRUCTIBLE = 1 << 6;
+        /// A heat source or sink, always at its kind's temperature.
+        const FIXED_TEMPERATURE = 1 << 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tags;
+    use crate::simulation::materials;
+
+    const TAGS_GLSL: &str = include_str!("../shaders/tags.glsl");
+
+    // the value of `const <type> <name> = <value>;`, a number or `1u << n`
+    fn glsl_const(name: &str) -> f64 {
+        let line = TAGS_GLSL
+            .lines()
+            .find(|line| line.starts_with("const ") && line.split_whitespace().nth(2) == Some(name))
+            .unwrap_or_else(|| panic!("tags.glsl has no const {name}"));
+        let value = line.split('=').nth(1).and_then(|v| v.split(';').next()).unwrap().trim();
+        let number = |n: &str| n.trim().trim_end_matches('u').parse::<f64>().unwrap();
+        match value.split_once("<<") {
+            Some((base, shift)) => ((number(base) as u32) << number(shift) as u32) as f64,
+            None => number(value),
+        }
+    }
+
+    #[test]
+    fn tag_bits_match_the_shader() {
+        for (name, tag) in Tags::all().iter_names() {
+            assert_eq!(glsl_const(&format!("TAG_{name}")), tag.bits() as f64, "TAG_{name}");
+        }
+    }
+
+    #[test]
+    fn fire_and_ignition_match_the_shader() {
+        assert_eq!(glsl_const("KIND_FIRE"), materials::FIRE as f64);
+        assert_eq!(glsl_const("IGNITION_TEMPERATURE"), materials::IGNITION_TEMPERATURE as f64);
+    }
+}
@@ -0,0 +1,578 @@
+use std::sync::Arc;
+
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBufferExecFuture, CommandBufferUsage, CopyBufferInfo,
+    PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract,
+};
+use vulkano::device::{Device, Queue};
+use vulkano::memory::allocator::{
+    AllocationCreateInfo, MemoryAllocator, MemoryUsage, StandardMemoryAllocator,
+};
+use vulkano::padded::Padded;
+use vulkano::sync::future::SemaphoreSignalFuture;
+use vulkano::sync::GpuFuture;
+
+use super::bodies::{self, MAX_BODIES};
+use super::cells;
+use super::fields::{Field, MAX_FIELDS};
+use super::geometry::Geometry;
+use super::impulse::{self, Impulse};
+use super::reactions::ReactionTable;
+use super::sand::{self, sand_shader::Material, sand_shader::Params, sand_shader::Shape, sand_shader::TickState, PADDING};
+use crate::config::SimulationConfig;
+use crate::debug;
+use crate::deploy_shader::{self, Dispatch};
+use crate::error::{Result, SandError};
+use crate::profiler::{self, GpuProfiler, Stage};
+
+/// The particles on the gpu plus everything needed to tick them, this is the part to embed
+/// when you don't want the window.
+pub struct World {
+    /// Device only, what the ticks work on. Only the compute queue touches it, draw a snapshot instead.
+    pub buffer: Subbuffer<[Padded<Material, PADDING>]>,
+    /// Copies of the particles after every tick, alternating so one can be drawn while the next tick
+    /// writes the other. `snapshot` is the latest, bind it to read the particles from your own shaders.
+    pub snapshots: [Subbuffer<[Padded<Material, PADDING>]>; 2],
+    /// The level geometry, bind these to draw it. See geometry.glsl.
+    pub shapes: Subbuffer<[Shape]>,
+    pub vertices: Subbuffer<[[f32; 2]]>,
+    params: Subbuffer<Params>,
+    tick_states: Vec<Subbuffer<TickState>>, // one per deploy command
+    seed: u32,
+    ticks: u64, // submitted so far, the next tick's number
+    spawned: Vec<Padded<Material, PADDING>>, // uploaded right before the next tick
+    impulses: Vec<Impulse>, // written into the next tick's state, MAX_IMPULSES at a time
+    fields_buffer: Subbuffer<[sand::sand_shader::Field]>,
+    fields: Vec<Field>,
+    fields_changed: bool, // uploaded right before the next tick
+    wake_all: bool, // for the next tick, after anything that changes how every particle moves
+    next_free: u64, // slots before this are taken, particles are never removed yet
+    next_id: u32,
+    next_body: u32, // body numbers index the body buffer, so they stay below MAX_BODIES
+    upload_allocator: StandardMemoryAllocator, // for the small per tick spawn uploads
+    device: Arc<Device>,
+    compute_queue: Arc<Queue>,
+    deploy_commands: Vec<Arc<PrimaryAutoCommandBuffer>>,
+    command_buffer_allocator: StandardCommandBufferAllocator,
+}
+
+impl World {
+    /// Uploads `particles` and builds the tick commands. `queue_families` are every family that will
+    /// touch the buffer (compute first), the upload runs on `compute_queue` and is waited on.
+    /// `seed` keys the shader's rng, the same seed, particles, geometry, config and reactions always give the same world.
+    pub fn new(
+        device: &Arc<Device>,
+        compute_queue: &Arc<Queue>,
+        queue_families: &[u32],
+        memory_allocator: &(impl MemoryAllocator + ?Sized),
+        particles: Vec<Padded<Material, PADDING>>,
+        work_groups: [u32; 3],
+        config: &SimulationConfig,
+        reactions: &ReactionTable,
+        geometry: &Geometry,
+        seed: u32,
+        profiler: Option<&GpuProfiler>,
+    ) -> Result<World> {
+        let next_free = particles.len() as u64;
+        let next_id = particles.iter().map(|particle| particle.id).max().unwrap_or(0) + 1;
+        let next_body = particles.iter().map(|particle| particle.body).max().unwrap_or(0) + 1;
+        if next_body as usize > MAX_BODIES {
+            return Err(SandError::Config(format!(
+                "body number {} but a world holds at most {} bodies",
+                next_body - 1,
+                MAX_BODIES - 1
+            )));
+        }
+        let staging = sand::upload_transfer_source_buffer(particles, memory_allocator)?;
+        let buffer = sand::upload_device_buffer(
+            memory_allocator,
+            (work_groups[0] * 64) as u64,
+            &[compute_queue.queue_family_index()],
+        )?;
+        let snapshots = [
+            sand::upload_device_buffer(memory_allocator, buffer.len(), queue_families)?,
+            sand::upload_device_buffer(memory_allocator, buffer.len(), queue_families)?,
+        ];
+        let params = sand::upload_params_buffer(memory_allocator, config)?;
+        debug::name(staging.buffer().as_ref(), "world staging");
+        debug::name(buffer.buffer().as_ref(), "world");
+        debug::name(snapshots[0].buffer().as_ref(), "world snapshot 0");
+        debug::name(snapshots[1].buffer().as_ref(), "world snapshot 1");
+        debug::name(params.buffer().as_ref(), "simulation params");
+        // only the deploy commands' descriptor sets need to keep these alive
+        let kinds = sand::upload_kind_table(memory_allocator)?;
+        let reaction_table = sand::upload_reaction_table(memory_allocator, reactions)?;
+        let heat = sand::upload_scratch_buffer::<f32>(memory_allocator, buffer.len(), "heat scratch")?;
+        let reacted = sand::upload_scratch_buffer::<u32>(memory_allocator, buffer.len(), "reaction scratch")?;
+        // zeroed the counts start out right, sand_cells.glsl resets them every tick after that
+        let cells =
+            sand::upload_scratch_buffer::<sand::cells_shader::Cell>(memory_allocator, cells::CELL_COUNT as u64, "cells")?;
+        // every particle is filed under its chunk slot and body particles under their body too
+        let cell_entries = sand::upload_scratch_buffer::<u32>(memory_allocator, buffer.len() * 2, "cell entries")?;
+        debug::name(kinds.buffer().as_ref(), "kind table");
+        debug::name(reaction_table.buffer().as_ref(), "reaction table");
+        debug::name(heat.buffer().as_ref(), "heat scratch");
+        debug::name(reacted.buffer().as_ref(), "reaction scratch");
+        debug::name(cells.buffer().as_ref(), "cells");
+        debug::name(cell_entries.buffer().as_ref(), "cell entries");
+        let density = sand::upload_scratch_buffer::<[f32; 2]>(memory_allocator, buffer.len(), "sph density scratch")?;
+        let acceleration = sand::upload_scratch_buffer::<[f32; 2]>(memory_allocator, buffer.len(), "sph force scratch")?;
+        debug::name(density.buffer().as_ref(), "sph density scratch");
+        debug::name(acceleration.buffer().as_ref(), "sph force scratch");
+        let bodies =
+            sand::upload_scratch_buffer::<sand::sand_shader::Body>(memory_allocator, MAX_BODIES as u64, "bodies")?;
+        let body_dispatch = sand::upload_dispatch_buffer(memory_allocator, "body dispatch")?;
+        debug::name(bodies.buffer().as_ref(), "bodies");
+        debug::name(body_dispatch.buffer().as_ref(), "body dispatch");
+        // zeroed every slot counts as a new particle on the first tick, so nothing starts asleep
+        let sleepers =
+            sand::upload_scratch_buffer::<sand::sleep_shader::Sleeper>(memory_allocator, buffer.len(), "sleepers")?;
+        let chunks = sand::upload_scratch_buffer::<u32>(memory_allocator, cells::CHUNK_COUNT as u64, "sleep chunks")?;
+        let active = sand::upload_scratch_buffer::<u32>(memory_allocator, buffer.len(), "active particles")?;
+        let active_dispatch = sand::upload_dispatch_buffer(memory_allocator, "active dispatch")?;
+        debug::name(sleepers.buffer().as_ref(), "sleepers");
+        debug::name(chunks.buffer().as_ref(), "sleep chunks");
+        debug::name(active.buffer().as_ref(), "active particles");
+        debug::name(active_dispatch.buffer().as_ref(), "active dispatch");
+        let (shapes, vertices) = sand::upload_geometry(memory_allocator, geometry)?;
+        debug::name(shapes.buffer().as_ref(), "geometry shapes");
+        debug::name(vertices.buffer().as_ref(), "geometry vertices");
+        let fields_buffer = sand::upload_field_buffer(memory_allocator)?;
+        debug::name(fields_buffer.buffer().as_ref(), "force fields");
+        let tick_states =
+            sand::upload_tick_state_buffers(memory_allocator, profiler::FRAME_SLOTS as usize)?;
+
+        let command_buffer_allocator =
+            StandardCommandBufferAllocator::new(device.clone(), Default::default());
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &command_buffer_allocator,
+            compute_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .map_err(|e| SandError::command("world upload", e))?;
+        if let Some(profiler) = profiler {
+            profiler.begin(&mut builder, Stage::Copy, 0)?;
+        }
+        // slots past the uploaded particles stay id 0, which the shaders skip
+        builder
+            .fill_buffer(buffer.clone().reinterpret::<[u32]>(), 0)
+            .map_err(|e| SandError::command("world upload", e))?
+            .fill_buffer(cells.clone().reinterpret::<[u32]>(), 0)
+            .map_err(|e| SandError::command("world upload", e))?
+            .fill_buffer(body_dispatch.clone().reinterpret::<[u32]>(), 0)
+            .map_err(|e| SandError::command("world upload", e))?
+            .fill_buffer(fields_buffer.clone().reinterpret::<[u32]>(), 0)
+            .map_err(|e| SandError::command("world upload", e))?
+            .fill_buffer(sleepers.clone().reinterpret::<[u32]>(), 0)
+            .map_err(|e| SandError::command("world upload", e))?
+            .fill_buffer(chunks.clone(), 0)
+            .map_err(|e| SandError::command("world upload", e))?
+            .fill_buffer(active_dispatch.clone().reinterpret::<[u32]>(), 0)
+            .map_err(|e| SandError::command("world upload", e))?
+            .copy_buffer(CopyBufferInfo::buffers(staging, buffer.clone()))
+            .map_err(|e| SandError::command("world upload", e))?
+            .copy_buffer(CopyBufferInfo::buffers(buffer.clone(), snapshots[0].clone()))
+            .map_err(|e| SandError::command("world upload", e))?
+            .copy_buffer(CopyBufferInfo::buffers(buffer.clone(), snapshots[1].clone()))
+            .map_err(|e| SandError::command("world upload", e))?;
+        if let Some(profiler) = profiler {
+            profiler.end(&mut builder, Stage::Copy, 0)?;
+        }
+        let command_buffer = builder
+            .build()
+            .map_err(|e| SandError::command("world upload", e))?;
+
+        // Execute copy and wait for copy to complete before proceeding.
+        command_buffer
+            .execute(compute_queue.clone())
+            .map_err(|e| SandError::submission("world upload", e))?
+            .then_signal_fence_and_flush()
+            .map_err(|e| SandError::submission("world upload", e))?
+            .wait(None)
+            .map_err(|e| SandError::submission("world upload", e))?;
+
+        let sleep_shader_loaded = sand::sleep_shader::load(device.clone())
+            .map_err(|e| SandError::pipeline("sand sleep shader", e))?;
+        let cells_shader_loaded = sand::cells_shader::load(device.clone())
+            .map_err(|e| SandError::pipeline("sand cells shader", e))?;
+        let activity_shader_loaded = sand::activity_shader::load(device.clone())
+            .map_err(|e| SandError::pipeline("sand activity shader", e))?;
+        let cell_sort_shader_loaded = sand::cell_sort_shader::load(device.clone())
+            .map_err(|e| SandError::pipeline("sand cell sort shader", e))?;
+        let heat_shader_loaded = sand::heat_shader::load(device.clone())
+            .map_err(|e| SandError::pipeline("sand heat shader", e))?;
+        let reaction_shader_loaded = sand::reaction_shader::load(device.clone())
+            .map_err(|e| SandError::pipeline("sand reaction shader", e))?;
+        let density_shader_loaded = sand::density_shader::load(device.clone())
+            .map_err(|e| SandError::pipeline("sand sph density shader", e))?;
+        let sph_force_shader_loaded = sand::sph_force_shader::load(device.clone())
+            .map_err(|e| SandError::pipeline("sand sph force shader", e))?;
+        let impulse_shader_loaded = sand::impulse_shader::load(device.clone())
+            .map_err(|e| SandError::pipeline("sand impulse shader", e))?;
+        let body_shader_loaded = sand::body_shader::load(device.clone())
+            .map_err(|e| SandError::pipeline("sand body shader", e))?;
+        let compute_shader_loaded = sand::sand_shader::load(device.clone())
+            .map_err(|e| SandError::pipeline("sand compute shader", e))?;
+        let deploy_commands = deploy_shader::get_deploy_commands(
+            // only the awake particles past the cell list, bodies are fitted per body number
+            &[
+                ("sleep", sleep_shader_loaded, Dispatch::All),
+                ("cells", cells_shader_loaded, Dispatch::Groups([1, 1, 1])),
+                ("activity", activity_shader_loaded, Dispatch::All),
+                ("cell sort", cell_sort_shader_loaded, Dispatch::Groups([cells::CELL_COUNT as u32 / 64, 1, 1])),
+                ("heat", heat_shader_loaded, Dispatch::Indirect(active_dispatch.clone())),
+                ("reaction", reaction_shader_loaded, Dispatch::Indirect(active_dispatch.clone())),
+                ("sph density", density_shader_loaded, Dispatch::Indirect(active_dispatch.clone())),
+                ("sph force", sph_force_shader_loaded, Dispatch::Indirect(active_dispatch.clone())),
+                ("impulse", impulse_shader_loaded, Dispatch::Indirect(active_dispatch.clone())),
+                ("body", body_shader_loaded, Dispatch::Indirect(body_dispatch.clone())),
+                ("particle", compute_shader_loaded, Dispatch::Indirect(active_dispatch.clone())),
+            ],
+            device,
+            compute_queue,
+            // see sand_bindings.glsl
+            &[
+                (0, buffer.as_bytes().clone()),
+                (1, params.as_bytes().clone()),
+                (3, kinds.as_bytes().clone()),
+                (4, heat.as_bytes().clone()),
+                (5, cells.as_bytes().clone()),
+                (6, cell_entries.as_bytes().clone()),
+                (7, reaction_table.as_bytes().clone()),
+                (8, reacted.as_bytes().clone()),
+                (9, shapes.as_bytes().clone()),
+                (10, vertices.as_bytes().clone()),
+                (11, density.as_bytes().clone()),
+                (12, acceleration.as_bytes().clone()),
+                (13, fields_buffer.as_bytes().clone()),
+                (14, bodies.as_bytes().clone()),
+                (15, body_dispatch.as_bytes().clone()),
+                (16, sleepers.as_bytes().clone()),
+                (17, chunks.as_bytes().clone()),
+                (18, active.as_bytes().clone()),
+                (19, active_dispatch.as_bytes().clone()),
+            ],
+            &tick_states,
+            work_groups,
+            profiler,
+        )?;
+
+        Ok(World {
+            buffer,
+            snapshots,
+            shapes,
+            vertices,
+            params,
+            tick_states,
+            seed,
+            ticks: 0,
+            spawned: Vec::new(),
+            impulses: Vec::new(),
+            fields_buffer,
+            fields: Vec::new(),
+            fields_changed: false,
+            wake_all: false,
+            next_free,
+            next_id,
+            next_body,
+            upload_allocator: StandardMemoryAllocator::new_default(device.clone()),
+            device: device.clone(),
+            compute_queue: compute_queue.clone(),
+            deploy_commands,
+            command_buffer_allocator,
+        })
+    }
+
+    /// Runs one tick after `after` and copies the result into the other snapshot, which becomes `snapshot`.
+    /// `after` has to include the last tick and whatever is still reading the snapshot being written,
+    /// nothing else, so the tick can run while the latest snapshot is drawn.
+    /// `slot` picks the command buffer (and profiler queries), use a different one for every frame in
+    /// flight, the swapchain image index works. The slot's previous tick has to be finished, its tick state
+    /// is rewritten here.
+    /// Particles from `spawn` and changed force fields are uploaded first so this tick already uses them,
+    /// and it applies the first `MAX_IMPULSES` impulses from `impulse`. Only awake particles are simulated,
+    /// see sand_sleep.glsl, new particles and impulses wake whatever is around them.
+    pub fn tick<F: GpuFuture + 'static>(
+        &mut self,
+        after: F,
+        slot: u32,
+    ) -> Result<CommandBufferExecFuture<SemaphoreSignalFuture<CommandBufferExecFuture<Box<dyn GpuFuture>>>>> {
+        let mut after: Box<dyn GpuFuture> = after.boxed();
+        if !self.spawned.is_empty() {
+            let upload = self.upload_spawned()?;
+            after = after
+                .then_execute(self.compute_queue.clone(), upload)
+                .map_err(|e| SandError::submission("spawn upload", e))?
+                .boxed();
+        }
+        if self.fields_changed {
+            let upload = self.upload_fields()?;
+            after = after
+                .then_execute(self.compute_queue.clone(), upload)
+                .map_err(|e| SandError::submission("force field upload", e))?
+                .boxed();
+        }
+        let slot = slot as usize % self.deploy_commands.len();
+        let mut impulses = [impulse::NO_IMPULSE; impulse::MAX_IMPULSES];
+        let impulse_count = self.impulses.len().min(impulse::MAX_IMPULSES);
+        for (gpu, queued) in impulses.iter_mut().zip(self.impulses.drain(..impulse_count)) {
+            *gpu = (&queued).into();
+        }
+        *self.tick_states[slot]
+            .write()
+            .map_err(|e| SandError::submission("tick state update", e))? = TickState {
+            tick: self.ticks as u32,
+            seed: self.seed,
+            checksum: 0,
+            impulse_count: impulse_count as u32,
+            wake_all: std::mem::take(&mut self.wake_all) as u32,
+            awake: 0,
+            bodies: self.next_body,
+            impulses,
+        };
+        let snapshot = self.copy_snapshot(self.ticks as usize % 2)?;
+        self.ticks += 1;
+        // the semaphore makes the copy wait for the tick's writes, they're separate command buffers
+        sand::tick(after, &self.compute_queue, self.deploy_commands[slot].clone())?
+            .then_execute(self.compute_queue.clone(), snapshot)
+            .map_err(|e| SandError::submission("world snapshot", e))
+    }
+
+    /// Which of `snapshots` has the particles as of the last tick, the one before it has the tick before.
+    pub fn snapshot(&self) -> usize {
+        (self.ticks as usize + 1) % 2
+    }
+
+    // only the slots in use, everything past them is empty in both the world and its snapshots
+    fn copy_snapshot(&self, snapshot: usize) -> Result<PrimaryAutoCommandBuffer> {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.compute_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .map_err(|e| SandError::command("world snapshot", e))?;
+        if self.next_free > 0 {
+            builder
+                .copy_buffer(CopyBufferInfo::buffers(
+                    self.buffer.clone().slice(..self.next_free),
+                    self.snapshots[snapshot].clone().slice(..self.next_free),
+                ))
+                .map_err(|e| SandError::command("world snapshot", e))?;
+        }
+        builder
+            .build()
+            .map_err(|e| SandError::command("world snapshot", e))
+    }
+
+    /// `(tick, checksum)` of the last tick run in `slot`, only valid once that tick has finished.
+    /// The checksum matches `rng::checksum` of the world right after that tick.
+    pub fn checksum(&self, slot: u32) -> Result<(u32, u32)> {
+        let tick_state = self.tick_states[slot as usize % self.tick_states.len()]
+            .read()
+            .map_err(|e| SandError::submission("tick state readback", e))?;
+        Ok((tick_state.tick, tick_state.checksum))
+    }
+
+    /// How many particles the last tick run in `slot` moved, the rest were asleep.
+    /// Only valid once that tick has finished.
+    pub fn awake(&self, slot: u32) -> Result<u32> {
+        let tick_state = self.tick_states[slot as usize % self.tick_states.len()]
+            .read()
+            .map_err(|e| SandError::submission("tick state readback", e))?;
+        Ok(tick_state.awake)
+    }
+
+    /// Queues a particle for the next tick, its id is replaced with a fresh one. False when the world is full.
+    pub fn spawn(&mut self, mut particle: Material) -> bool {
+        if self.next_free + self.spawned.len() as u64 >= self.len() {
+            return false;
+        }
+        particle.id = self.next_id;
+        self.next_id += 1;
+        self.spawned.push(Padded(particle));
+        true
+    }
+
+    /// Queues `particles` as one rigid body for the next tick, shaped the way they are placed, see
+    /// `bodies::make_body`. Their ids are replaced like `spawn`. False when the world can't fit them all
+    /// or already has `MAX_BODIES` bodies.
+    pub fn spawn_body(&mut self, mut particles: Vec<Material>) -> bool {
+        let free = self.len() - self.next_free - self.spawned.len() as u64;
+        if particles.is_empty() || particles.len() as u64 > free || self.next_body as usize >= MAX_BODIES {
+            return false;
+        }
+        bodies::make_body(&mut particles, self.next_body);
+        self.next_body += 1;
+        particles.into_iter().all(|particle| self.spawn(particle))
+    }
+
+    /// Queues an impulse for the next tick, past `MAX_IMPULSES` in one tick they wait for the ones after.
+    pub fn impulse(&mut self, impulse: Impulse) {
+        self.impulses.push(impulse);
+    }
+
+    /// The force fields as of the next tick.
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// Replaces every force field from the next tick on, at most `MAX_FIELDS` of them.
+    pub fn set_fields(&mut self, fields: Vec<Field>) -> Result<()> {
+        if fields.len() > MAX_FIELDS {
+            return Err(SandError::Config(format!(
+                "{} force fields but a world holds at most {MAX_FIELDS}",
+                fields.len()
+            )));
+        }
+        self.fields = fields;
+        self.fields_changed = true;
+        self.wake_all = true;
+        Ok(())
+    }
+
+    /// Adds a force field from the next tick on. False when the world already has `MAX_FIELDS`.
+    pub fn add_field(&mut self, field: Field) -> bool {
+        if self.fields.len() >= MAX_FIELDS {
+            return false;
+        }
+        self.fields.push(field);
+        self.fields_changed = true;
+        self.wake_all = true;
+        true
+    }
+
+    pub fn clear_fields(&mut self) {
+        self.fields.clear();
+        self.fields_changed = true;
+        self.wake_all = true;
+    }
+
+    fn upload_fields(&mut self) -> Result<PrimaryAutoCommandBuffer> {
+        let staging = sand::upload_field_staging_buffer(&self.upload_allocator, &self.fields)?;
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.compute_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .map_err(|e| SandError::command("force field upload", e))?;
+        builder
+            .copy_buffer(CopyBufferInfo::buffers(staging, self.fields_buffer.clone()))
+            .map_err(|e| SandError::command("force field upload", e))?;
+        self.fields_changed = false;
+        builder
+            .build()
+            .map_err(|e| SandError::command("force field upload", e))
+    }
+
+    fn upload_spawned(&mut self) -> Result<PrimaryAutoCommandBuffer> {
+        let count = self.spawned.len() as u64;
+        let staging = sand::upload_transfer_source_buffer(
+            std::mem::take(&mut self.spawned),
+            &self.upload_allocator,
+        )?;
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.compute_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .map_err(|e| SandError::command("spawn upload", e))?;
+        builder
+            .copy_buffer(CopyBufferInfo::buffers(
+                staging,
+                self.buffer.clone().slice(self.next_free..self.next_free + count),
+            ))
+            .map_err(|e| SandError::command("spawn upload", e))?;
+        self.next_free += count;
+        builder
+            .build()
+            .map_err(|e| SandError::command("spawn upload", e))
+    }
+
+    /// Ticks submitted so far.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Runs `ticks` ticks back to back, waiting for each one, and returns the checksum after each.
+    /// For headless runs and tests, a window chains ticks into its frames with `tick` instead.
+    pub fn run_ticks(&mut self, ticks: u64) -> Result<Vec<u32>> {
+        (0..ticks)
+            .map(|tick| {
+                // waiting every tick keeps a command buffer from being resubmitted while it's still pending
+                self.tick(vulkano::sync::now(self.device.clone()), tick as u32)?
+                    .then_signal_fence_and_flush()
+                    .map_err(|e| SandError::submission("sand tick", e))?
+                    .wait(None)
+                    .map_err(|e| SandError::submission("sand tick", e))?;
+                Ok(self.checksum(tick as u32)?.1)
+            })
+            .collect()
+    }
+
+    /// Takes effect from the next tick, only call it while no tick is in flight (wait on every fence first).
+    /// Wakes every particle, new gravity or sleep settings apply to them all.
+    pub fn set_params(&mut self, config: &SimulationConfig) -> Result<()> {
+        *self
+            .params
+            .write()
+            .map_err(|e| SandError::submission("simulation params update", e))? = Params::from(config);
+        self.wake_all = true;
+        Ok(())
+    }
+
+    /// Copies the particles back to the cpu and waits for it, slow so keep it for tests and tools.
+    pub fn download(
+        &self,
+        memory_allocator: &(impl MemoryAllocator + ?Sized),
+    ) -> Result<Vec<Material>> {
+        let readback: Subbuffer<[Padded<Material, PADDING>]> = Buffer::new_slice(
+            memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Download,
+                ..Default::default()
+            },
+            self.buffer.len(),
+        )
+        .map_err(|e| SandError::allocation("world readback buffer", e))?;
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.compute_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .map_err(|e| SandError::command("world download", e))?;
+        builder
+            .copy_buffer(CopyBufferInfo::buffers(self.buffer.clone(), readback.clone()))
+            .map_err(|e| SandError::command("world download", e))?;
+        let command_buffer = builder
+            .build()
+            .map_err(|e| SandError::command("world download", e))?;
+
+        vulkano::sync::now(self.device.clone())
+            .then_execute(self.compute_queue.clone(), command_buffer)
+            .map_err(|e| SandError::submission("world download", e))?
+            .then_signal_fence_and_flush()
+            .map_err(|e| SandError::submission("world download", e))?
+            .wait(None)
+            .map_err(|e| SandError::submission("world download", e))?;
+
+        let contents = readback
+            .read()
+            .map_err(|e| SandError::submission("world download", e))?;
+        Ok(contents.iter().map(|particle| **particle).collect())
+    }
+
+    pub fn len(&self) -> u64 {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.len() == 0
+    }
+}
@@ -1,150 +1,271 @@
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Instant;
 
-use crate::deploy_shader;
-use crate::pass_structs::WindowInitialized;
-use crate::simulation::sand::{self, sand_shader::Material, PADDING};
-use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
-use vulkano::command_buffer::{
-    AutoCommandBufferBuilder, CommandBufferExecFuture, CommandBufferUsage, CopyBufferInfo,
-    PrimaryCommandBufferAbstract,
-};
-use vulkano::device::physical::PhysicalDevice;
-use vulkano::device::{physical, Device, Queue};
-use vulkano::memory::allocator::GenericMemoryAllocator;
+use crate::config::{Config, ConfigWatcher};
+use crate::error::{Result, SandError};
+use crate::input::{Input, InputState, Recorder, Replay};
+use crate::pass_structs::GpuContext;
+use crate::profiler::{GpuProfiler, Stage};
+use crate::simulation::fields::Field;
+use crate::simulation::geometry::Geometry;
+use crate::simulation::materials;
+use crate::simulation::reactions::ReactionTable;
+use crate::simulation::sand::{sand_shader::Material, PADDING};
+use crate::simulation::scene;
+use crate::simulation::world::World;
+use vulkano::memory::allocator::StandardMemoryAllocator;
 use vulkano::padded::Padded;
-use vulkano::swapchain::{acquire_next_image, SwapchainPresentInfo};
-use vulkano::swapchain::{AcquireError, Surface};
-use vulkano::sync::future::{FenceSignalFuture, NowFuture};
-use vulkano::sync::{self, FlushError, GpuFuture};
-use vulkano::VulkanLibrary;
-use winit::dpi::PhysicalSize;
-use winit::event::{Event, WindowEvent};
-use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::Window;
+use vulkano::swapchain::{acquire_next_image, AcquireError, SwapchainPresentInfo};
+use vulkano::sync::{FlushError, GpuFuture};
+use winit::event::{ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent};
+use winit::event_loop::ControlFlow;
 
-mod fps;
+use self::frame_stats::FrameStats;
+
+mod frame_stats;
+mod frames;
 mod init;
 mod utils;
 
-const FPS_DISPLAY: bool = true;
+const FPS_DISPLAY_EVERY: u64 = 15; // frames
+
+/// Everything about the window that can be picked from the command line.
+#[derive(Clone, Debug)]
+pub struct WindowOptions {
+    pub present_preference: PresentPreference, // V cycles through them at runtime
+    pub prefer_hdr: bool,                      // extended srgb linear, only if the surface offers it
+    pub fps_display: bool,
+    pub gpu_timings_display: bool,
+    pub frame_csv: Option<PathBuf>,    // per frame timings, to benchmark across gpus
+    pub world_output: Option<PathBuf>, // scene file written when the window is closed
+    pub config: Option<PathBuf>,       // watched, edits are applied without a restart
+    pub record: Option<PathBuf>,       // every input, tagged with the tick it applies to
+    pub replay: Option<PathBuf>,       // a recording fed back in before live input takes over
+}
+
+impl Default for WindowOptions {
+    fn default() -> WindowOptions {
+        WindowOptions {
+            present_preference: PresentPreference::LowLatency,
+            prefer_hdr: false,
+            fps_display: true,
+            gpu_timings_display: true,
+            frame_csv: None,
+            world_output: None,
+            config: None,
+            record: None,
+            replay: None,
+        }
+    }
+}
 
+/// Opens the window and runs the simulation in it until it's closed, never returns on success.
 pub fn make_window(
-    library: Arc<VulkanLibrary>,
-    compute_memory_allocator: GenericMemoryAllocator<
-        std::sync::Arc<vulkano::memory::allocator::FreeListAllocator>,
-    >,
-    device: Arc<Device>,
-    compute_queue: Arc<Queue>,
-    world: Vec<Padded<Material, PADDING>>,
+    context: GpuContext,
+    memory_allocator: StandardMemoryAllocator,
+    particles: Vec<Padded<Material, PADDING>>,
+    geometry: Geometry,
+    fields: Vec<Field>,
     work_groups: [u32; 3],
-    physical_device: Arc<PhysicalDevice>,
-    window: Arc<Window>,
-    surface: Arc<Surface>,
-    event_loop: EventLoop<()>,
-    window_size_start: PhysicalSize<u32>,
-) {
-    // let WindowInitialized {
-    //     physical_device,
-    //     surface,
-    //     device,
-    //     window,
-    //     window_size,
-    //     event_loop,
-    //     queue,
-    // } = init::initialize_window_from_preexisting(
-    //     physical_device,
-    //     compute_device.clone(),
-    //     compute_queue.clone(),
-    //     &library,
-    // );
+    reactions: ReactionTable,
+    seed: u32,
+    options: WindowOptions,
+) -> Result<()> {
+    let GpuContext {
+        physical_device,
+        device,
+        render_queue,
+        compute_queue,
+        window,
+        surface,
+        event_loop,
+        window_size,
+        debug_messenger: _debug_messenger, // run never returns so this lives long enough
+        ..
+    } = context;
 
-    //fps
-    let mut frames = [0f64; 15];
-    let mut cur_frame = 0;
-    let mut time = 0f64;
+    //frame timing
+    let mut frame_stats = match &options.frame_csv {
+        Some(path) => FrameStats::with_csv(frame_stats::DEFAULT_WINDOW, path).unwrap_or_else(|e| {
+            log::error!("couldn't create frame csv {}: {e}", path.display());
+            FrameStats::new(frame_stats::DEFAULT_WINDOW)
+        }),
+        None => FrameStats::new(frame_stats::DEFAULT_WINDOW),
+    };
+    //profiling, None if the queue can't do timestamps
+    let mut profiler = GpuProfiler::new(&device, &[&compute_queue, &render_queue]);
+    // separate queues means the tick can run while the last frame is being presented
+    let async_compute = compute_queue.queue_family_index() != render_queue.queue_family_index();
     //compute
-    let world_buffer_accessible =
-        sand::upload_transfer_source_buffer(world, &compute_memory_allocator);
-    let world_buffer_inaccessible =
-        sand::upload_device_buffer(&compute_memory_allocator, (work_groups[0] * 64) as u64);
+    let mut world_queue_families = vec![compute_queue.queue_family_index()];
+    if async_compute {
+        world_queue_families.push(render_queue.queue_family_index());
+    }
+    // a replay only reproduces the world with the seed it was recorded with
+    let mut replay = options.replay.as_deref().map(Replay::load).transpose()?;
+    let seed = replay.as_ref().map_or(seed, |replay| replay.seed);
+    let mut recorder = match &options.record {
+        Some(path) => Some(Recorder::create(path, seed)?),
+        None => None,
+    };
+    let mut input_state = InputState::default();
+    let mut live_inputs: Vec<Input> = Vec::new();
+    let mut cursor = [0f32; 2];
+    let mut painting = false;
 
-    // Create one-time command to copy between the buffers.
-    let command_buffer_allocator =
-        StandardCommandBufferAllocator::new(device.clone(), Default::default());
-    let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
-        &command_buffer_allocator,
-        compute_queue.queue_family_index(),
-        CommandBufferUsage::OneTimeSubmit,
-    )
-    .unwrap();
-    command_buffer_builder
-        .copy_buffer(CopyBufferInfo::buffers(
-            world_buffer_accessible,
-            world_buffer_inaccessible.clone(),
-        ))
-        .unwrap();
-    let command_buffer = command_buffer_builder.build().unwrap();
-
-    // Execute copy and wait for copy to complete before proceeding.
-    command_buffer
-        .execute(compute_queue.clone())
-        .unwrap()
-        .then_signal_fence_and_flush()
-        .unwrap()
-        .wait(None)
-        .unwrap();
-    // Transfer complete
-    let compute_shader_loaded =
-        sand::sand_shader::load(device.clone()).expect("Failed to create compute shader.");
-    let deploy_command = Arc::new(deploy_shader::get_deploy_command(
-        &compute_shader_loaded,
+    let config = Config::load_or_default(options.config.as_deref());
+    let mut config_watcher = options.config.clone().map(ConfigWatcher::new);
+    let mut world = World::new(
         &device,
         &compute_queue,
-        &world_buffer_inaccessible,
+        &world_queue_families,
+        &memory_allocator,
+        particles,
         work_groups,
-    ));
+        &config.simulation,
+        &reactions,
+        &geometry,
+        seed,
+        profiler.as_ref(),
+    )?;
+    world.set_fields(fields)?;
+    let render_params = init::upload_render_params(&memory_allocator, &config.render)?;
+    // the config file's, plus whatever keys have toggled since
+    let mut render_config = config.render.clone();
+    let mut render_config_changed = false;
+    if let Some(profiler) = &mut profiler {
+        profiler.resolve(Stage::Copy, 0);
+    }
 
-    let mut window_size = window_size_start;
+    let mut present_preference = options.present_preference;
     let (
         mut swapchain,
         mut recreate_swapchain,
         mut command_buffers,
         mut viewport,
         render_pass,
-        vs,
-        fs,
+        render_shaders,
         vertex_buffer,
-        mut fences,
-        mut previous_fence_i,
+        mut frames,
     ) = init::initialize_swapchain_screen(
         physical_device,
         device.clone(),
         window.clone(),
         surface,
         window_size,
-        compute_queue.clone(),
-        &world_buffer_inaccessible,
-    );
-
-    let mut next_future: Option<FenceSignalFuture<CommandBufferExecFuture<NowFuture>>> = None;
+        render_queue.clone(),
+        &world,
+        &render_params,
+        profiler.as_ref(),
+        present_preference,
+        options.prefer_hdr,
+    )?;
 
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
             event: WindowEvent::CloseRequested,
             ..
         } => {
+            frame_stats.flush();
+            if let Some(path) = &options.world_output {
+                // nothing can be writing the world while it's copied back
+                if let Err(e) = frames.wait_all() {
+                    return exit_with(control_flow, e);
+                }
+                let saved = world
+                    .download(&memory_allocator)
+                    .and_then(|particles| scene::save(path, &particles, &geometry, world.fields()));
+                if let Err(e) = saved {
+                    return exit_with(control_flow, e);
+                }
+                log::info!("world written to {}", path.display());
+            }
             *control_flow = ControlFlow::Exit;
         }
         Event::WindowEvent {
             event:
                 WindowEvent::CursorMoved {
                     device_id: _,
-                    position: _,
+                    position,
+                    ..
+                },
+            ..
+        } => {
+            // physical pixels, the same space particle positions are in
+            cursor = [position.x as f32, position.y as f32];
+        }
+        Event::WindowEvent {
+            event:
+                WindowEvent::MouseInput {
+                    state,
+                    button: MouseButton::Left,
+                    ..
+                },
+            ..
+        } => {
+            painting = state == ElementState::Pressed;
+        }
+        Event::WindowEvent {
+            event:
+                WindowEvent::MouseInput {
+                    state: ElementState::Pressed,
+                    button: MouseButton::Right,
+                    ..
+                },
+            ..
+        } => {
+            live_inputs.push(Input::Explode { pos: cursor });
+        }
+        Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::V),
+                            ..
+                        },
                     ..
                 },
             ..
         } => {
-            // println!("{position:?}");
+            present_preference = present_preference.next();
+            log::info!("present preference: {present_preference:?}");
+            recreate_swapchain = true;
+        }
+        Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::G),
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } => {
+            render_config.show_geometry = !render_config.show_geometry;
+            render_config_changed = true;
+        }
+        Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(key),
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } => {
+            if let Some(input) = key_input(key, &input_state, cursor) {
+                live_inputs.push(input);
+            }
         }
         Event::WindowEvent {
             event: WindowEvent::Resized(_),
@@ -153,26 +274,49 @@ pub fn make_window(
             recreate_swapchain = true;
         }
         Event::RedrawEventsCleared => {
+            let frame_start = Instant::now();
+            if let Some(config) = config_watcher.as_mut().and_then(ConfigWatcher::poll) {
+                render_config = config.render;
+                render_config_changed = true;
+                // an input so a recording has it at the tick it landed before, a replay drops it like
+                // any other live input
+                live_inputs.push(Input::Params(config.simulation));
+            }
+            if render_config_changed {
+                render_config_changed = false;
+                // the params buffer is read by every frame in flight, so let them all finish first
+                if let Err(e) = frames.wait_all() {
+                    return exit_with(control_flow, e);
+                }
+                let applied = render_params
+                    .write()
+                    .map(|mut params| *params = (&render_config).into())
+                    .map_err(|e| SandError::submission("render params update", e));
+                if let Err(e) = applied {
+                    return exit_with(control_flow, e);
+                }
+            }
             if recreate_swapchain {
                 // println!("recreating swapchain (slow)");
                 recreate_swapchain = false;
-                window_size = window.inner_size();
-                utils::recreate_swapchain(
+                let recreated = utils::recreate_swapchain(
                     &window,
                     &render_pass,
                     &mut swapchain,
                     &mut viewport,
                     &device,
-                    &compute_queue,
+                    &render_queue,
                     &vertex_buffer,
                     &mut command_buffers,
-                    &vs,
-                    &fs,
-                    &world_buffer_inaccessible,
-                    init::fs::PushType {
-                        dims: [window_size.width as f32, window_size.height as f32],
-                    },
+                    &render_shaders,
+                    &world,
+                    &render_params,
+                    profiler.as_ref(),
+                    present_preference,
                 );
+                if let Err(e) = recreated {
+                    return exit_with(control_flow, e);
+                }
             }
 
             let (image_i, suboptimal, acquire_future) =
@@ -182,79 +326,188 @@ pub fn make_window(
                         recreate_swapchain = true;
                         return;
                     }
-                    Err(e) => panic!("failed to acquire next image: {e}"),
+                    Err(e) => {
+                        return exit_with(control_flow, SandError::swapchain("image acquire", e))
+                    }
                 };
             if suboptimal {
                 recreate_swapchain = true;
             }
 
-            // wait for the fence related to this image to finish (normally this would be the oldest fence)
-            if let Some(image_fence) = &fences[image_i as usize] {
-                image_fence.wait(None).unwrap();
+            // wait for the last frame drawn to this image to finish (normally this would be the oldest one)
+            let image_drawn = match frames.wait_image(image_i as usize) {
+                Ok(drawn) => drawn,
+                Err(e) => return exit_with(control_flow, e),
+            };
+            if image_drawn {
+                if let Some(profiler) = &mut profiler {
+                    profiler.resolve(Stage::Tick, image_i);
+                    profiler.resolve(Stage::Render, image_i);
+                }
+                // compare these between runs with --deterministic to find where they diverge
+                if log::log_enabled!(target: "checksum", log::Level::Debug) {
+                    match world.checksum(image_i) {
+                        Ok((tick, checksum)) => {
+                            log::debug!(target: "checksum", "tick {tick} {checksum:08x}")
+                        }
+                        Err(e) => log::warn!("couldn't read the checksum: {e}"),
+                    }
+                }
+                // the tick's cost follows this rather than the world's size
+                if log::log_enabled!(target: "sleep", log::Level::Debug) {
+                    match world.awake(image_i) {
+                        Ok(awake) => {
+                            log::debug!(target: "sleep", "{awake} of {} awake", world.len())
+                        }
+                        Err(e) => log::warn!("couldn't read the awake count: {e}"),
+                    }
+                }
             }
 
-            let previous_future = match fences[previous_fence_i as usize].clone() {
-                // Create a NowFuture
+            // inputs are tagged with the tick they land before, a replay hands back the same ones
+            let tick = world.ticks();
+            let inputs = match &mut replay {
+                Some(playing) => {
+                    live_inputs.clear();
+                    let inputs = playing.take(tick);
+                    if playing.is_done() {
+                        log::info!("replay finished at tick {tick}, back to live input");
+                        replay = None;
+                    }
+                    inputs
+                }
                 None => {
-                    let mut now = sync::now(device.clone());
-                    now.cleanup_finished();
-
-                    now.boxed()
+                    if painting {
+                        live_inputs.push(Input::Paint { pos: cursor });
+                    }
+                    std::mem::take(&mut live_inputs)
                 }
-                // Use the existing FenceSignalFuture
-                Some(fence) => fence.boxed(),
             };
+            if let Some(active) = &mut recorder {
+                if let Err(e) = inputs
+                    .iter()
+                    .try_for_each(|input| active.record(tick, input))
+                {
+                    log::error!("{e}, recording stopped");
+                    recorder = None;
+                }
+            }
+            // the simulation params are read by every tick in flight, same as the render's
+            if inputs.iter().any(|input| matches!(input, Input::Params(_))) {
+                if let Err(e) = frames.wait_all() {
+                    return exit_with(control_flow, e);
+                }
+            }
+            for input in &inputs {
+                if let Err(e) = input_state.apply(input, &mut world) {
+                    return exit_with(control_flow, e);
+                }
+            }
 
-            let future = previous_future
-                .join(acquire_future)
-                .then_execute(
-                    compute_queue.clone(),
-                    command_buffers[image_i as usize].clone(),
-                )
-                .unwrap()
+            // the tick writes the snapshot the frame before last drew, so it runs while the last frame is
+            // still drawing the other one. they only wait on each other on the gpu
+            if input_state.should_tick() {
+                let snapshot = 1 - world.snapshot();
+                let ticked = frames.before_tick(&device, snapshot).and_then(|after| {
+                    world
+                        .tick(after, image_i)?
+                        .boxed()
+                        .then_signal_fence_and_flush()
+                        .map_err(|e| SandError::submission("sand tick", e))
+                });
+                let ticked =
+                    ticked.and_then(|tick| frames.ticked(image_i as usize, snapshot, Arc::new(tick)));
+                if let Err(e) = ticked {
+                    return exit_with(control_flow, e);
+                }
+            }
+            // the render waits on the semaphore of the tick that wrote its snapshot, so it only ever reads a
+            // finished world
+            let snapshot = world.snapshot();
+            let before_render = frames.before_render(&device, snapshot);
+            let render_future = match before_render.join(acquire_future).then_execute(
+                render_queue.clone(),
+                command_buffers[image_i as usize][snapshot].clone(),
+            ) {
+                Ok(future) => future,
+                Err(e) => return exit_with(control_flow, SandError::submission("render", e)),
+            };
+            let future = render_future
                 .then_swapchain_present(
-                    compute_queue.clone(),
+                    render_queue.clone(),
                     SwapchainPresentInfo::swapchain_image_index(swapchain.clone(), image_i),
                 )
+                .boxed()
                 .then_signal_fence_and_flush();
 
-            fences[image_i as usize] = match future {
+            let present = match future {
                 Ok(value) => Some(Arc::new(value)),
                 Err(FlushError::OutOfDate) => {
                     recreate_swapchain = true;
                     None
                 }
                 Err(e) => {
-                    println!("failed to flush future: {e}");
+                    log::error!("failed to flush future: {e}");
                     None
                 }
             };
-            previous_fence_i = image_i;
-            if FPS_DISPLAY {
-                fps::do_fps(&mut frames, &mut cur_frame, &mut time);
-            }
-            if next_future.is_some() {
-                match next_future.as_ref().unwrap().wait(None) {
-                    Ok(_) => {}
-                    Err(err) => {
-                        panic!("{err:?}")
-                    }
+            frames.rendered(image_i as usize, snapshot, present);
+            let frame_cpu_ms = frame_start.elapsed().as_secs_f64() * 1000f64;
+
+            // gpu timings are a frame behind but much closer to the truth, tick and render overlap
+            // on the gpu now so the cpu can't tell them apart
+            match &profiler {
+                Some(profiler) => {
+                    let timings = profiler.timings();
+                    frame_stats.end_frame(timings.tick_ms, timings.render_ms);
                 }
-                // let _binding = world_buffer_inaccessible.read().unwrap();
-                // for (key, val) in binding.iter().enumerate() {
-                //     if key <= 1 {
-                //         println!("{val:?}");
-                //     }
-                // }
+                None => frame_stats.end_frame(0f64, frame_cpu_ms),
+            }
+            if options.fps_display && frame_stats.frame() % FPS_DISPLAY_EVERY == 0 {
+                let gpu_timings = match &profiler {
+                    Some(profiler) if options.gpu_timings_display => profiler.readout(),
+                    _ => String::new(),
+                };
+                print!("\r{}   {gpu_timings}   ", frame_stats.summary());
             }
-
-            next_future = Option::from(sand::tick(
-                // 1 frame of lag
-                &device.clone(),
-                &compute_queue.clone(),
-                deploy_command.clone(),
-            ));
         }
         _ => (),
     });
 }
+
+// space pauses, . steps while paused, 1-9 and 0 pick the brush material from materials::KINDS and tab
+// steps through all of them, hold the left button to paint and click the right one to set off an explosion.
+// a, r, o and w place an attractor, repulsor, vortex or wind at the cursor and c clears them, b drops a
+// rigid block of the brush material there
+fn key_input(key: VirtualKeyCode, input_state: &InputState, cursor: [f32; 2]) -> Option<Input> {
+    let material = match key {
+        VirtualKeyCode::Space if input_state.paused => return Some(Input::Resume),
+        VirtualKeyCode::Space => return Some(Input::Pause),
+        VirtualKeyCode::Period => return Some(Input::Step),
+        VirtualKeyCode::A => return Some(Input::Field(Field::attractor_brush(cursor))),
+        VirtualKeyCode::R => return Some(Input::Field(Field::repulsor_brush(cursor))),
+        VirtualKeyCode::O => return Some(Input::Field(Field::vortex_brush(cursor))),
+        VirtualKeyCode::W => return Some(Input::Field(Field::wind_brush(cursor))),
+        VirtualKeyCode::C => return Some(Input::ClearFields),
+        VirtualKeyCode::B => return Some(Input::Body { pos: cursor }),
+        VirtualKeyCode::Key1 => 0,
+        VirtualKeyCode::Key2 => 1,
+        VirtualKeyCode::Key3 => 2,
+        VirtualKeyCode::Key4 => 3,
+        VirtualKeyCode::Key5 => 4,
+        VirtualKeyCode::Key6 => 5,
+        VirtualKeyCode::Key7 => 6,
+        VirtualKeyCode::Key8 => 7,
+        VirtualKeyCode::Key9 => 8,
+        VirtualKeyCode::Key0 => 9,
+        VirtualKeyCode::Tab => (input_state.material + 1) % materials::KINDS.len() as u32,
+        _ => return None,
+    };
+    ((material as usize) < materials::KINDS.len()).then_some(Input::Material(material))
+}
+
+// only for errors inside the event loop, everything before it is returned to main
+fn exit_with(control_flow: &mut ControlFlow, error: SandError) {
+    log::error!("{error}");
+    *control_flow = ControlFlow::ExitWithCode(1);
+}
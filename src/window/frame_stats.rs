@@ -0,0 +1,243 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+// enough frames that the 0.1% low is an actual frame and not just the max
+pub const DEFAULT_WINDOW: usize = 1000;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameSample {
+    pub frame_ms: f64,
+    pub tick_ms: f64,
+    pub render_ms: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameSummary {
+    pub min_ms: f64,
+    pub avg_ms: f64,
+    pub max_ms: f64,
+    pub avg_fps: f64,
+    pub low_1_fps: f64,  // average fps of the slowest 1% of frames
+    pub low_01_fps: f64, // ^ slowest 0.1%
+    pub tick_ms: f64,
+    pub render_ms: f64,
+}
+
+impl fmt::Display for FrameSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "FPS: {:.0} (1% {:.0}, 0.1% {:.0}) | frame {:.2}/{:.2}/{:.2}ms | tick {:.3}ms render {:.3}ms",
+            self.avg_fps,
+            self.low_1_fps,
+            self.low_01_fps,
+            self.min_ms,
+            self.avg_ms,
+            self.max_ms,
+            self.tick_ms,
+            self.render_ms,
+        )
+    }
+}
+
+pub struct FrameStats {
+    last: Option<Instant>,
+    samples: VecDeque<FrameSample>,
+    window: usize,
+    frame: u64,
+    csv: Option<BufWriter<File>>,
+}
+
+impl FrameStats {
+    pub fn new(window: usize) -> FrameStats {
+        FrameStats {
+            last: None,
+            samples: VecDeque::with_capacity(window),
+            window: window.max(1),
+            frame: 0,
+            csv: None,
+        }
+    }
+
+    /// Also writes every frame to a csv at `path`, truncating whatever was there.
+    pub fn with_csv(window: usize, path: impl AsRef<Path>) -> io::Result<FrameStats> {
+        let mut csv = BufWriter::new(File::create(path)?);
+        writeln!(csv, "frame,frame_ms,tick_ms,render_ms")?;
+        let mut stats = FrameStats::new(window);
+        stats.csv = Some(csv);
+        Ok(stats)
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Call once per presented frame, the frame time is measured from the previous call.
+    /// The first call only starts the clock.
+    pub fn end_frame(&mut self, tick_ms: f64, render_ms: f64) {
+        let now = Instant::now();
+        let Some(last) = self.last.replace(now) else {
+            return;
+        };
+        self.push(FrameSample {
+            frame_ms: now.duration_since(last).as_secs_f64() * 1000f64,
+            tick_ms,
+            render_ms,
+        });
+    }
+
+    fn push(&mut self, sample: FrameSample) {
+        self.frame += 1;
+
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+
+        if let Some(csv) = &mut self.csv {
+            let written = writeln!(
+                csv,
+                "{},{:.4},{:.4},{:.4}",
+                self.frame, sample.frame_ms, sample.tick_ms, sample.render_ms
+            );
+            if let Err(e) = written {
+                log::error!("failed to write frame csv, disabling it: {e}");
+                self.csv = None;
+            }
+        }
+    }
+
+    pub fn summary(&self) -> FrameSummary {
+        if self.samples.is_empty() {
+            return FrameSummary::default();
+        }
+        let count = self.samples.len() as f64;
+        let mut sorted: Vec<f64> = self.samples.iter().map(|s| s.frame_ms).collect();
+        sorted.sort_by(|a, b| b.total_cmp(a)); // slowest first
+
+        let avg_ms = sorted.iter().sum::<f64>() / count;
+        FrameSummary {
+            min_ms: sorted[sorted.len() - 1],
+            avg_ms,
+            max_ms: sorted[0],
+            avg_fps: to_fps(avg_ms),
+            low_1_fps: to_fps(slowest_average(&sorted, 0.01)),
+            low_01_fps: to_fps(slowest_average(&sorted, 0.001)),
+            tick_ms: self.samples.iter().map(|s| s.tick_ms).sum::<f64>() / count,
+            render_ms: self.samples.iter().map(|s| s.render_ms).sum::<f64>() / count,
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(csv) = &mut self.csv {
+            if let Err(e) = csv.flush() {
+                log::error!("failed to flush frame csv: {e}");
+            }
+        }
+    }
+}
+
+impl Drop for FrameStats {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+// average of the slowest `fraction` of frames, always at least one frame
+fn slowest_average(sorted_slowest_first: &[f64], fraction: f64) -> f64 {
+    let count = ((sorted_slowest_first.len() as f64 * fraction).ceil() as usize).max(1);
+    sorted_slowest_first[..count].iter().sum::<f64>() / count as f64
+}
+
+fn to_fps(ms: f64) -> f64 {
+    if ms > 0f64 {
+        1000f64 / ms
+    } else {
+        0f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(window: usize, frame_ms: &[f64]) -> FrameStats {
+        let mut stats = FrameStats::new(window);
+        for &frame_ms in frame_ms {
+            stats.push(FrameSample {
+                frame_ms,
+                tick_ms: frame_ms / 4f64,
+                render_ms: frame_ms / 2f64,
+            });
+        }
+        stats
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn an_empty_window_is_all_zero() {
+        let summary = FrameStats::new(10).summary();
+        assert_eq!(summary.avg_fps, 0f64);
+        assert_eq!(summary.low_1_fps, 0f64);
+        assert_eq!(summary.max_ms, 0f64);
+    }
+
+    #[test]
+    fn one_sample_is_every_statistic() {
+        let summary = stats(10, &[20f64]).summary();
+        assert_eq!(summary.min_ms, 20f64);
+        assert_eq!(summary.avg_ms, 20f64);
+        assert_eq!(summary.max_ms, 20f64);
+        assert_eq!(summary.avg_fps, 50f64);
+        assert_eq!(summary.low_1_fps, 50f64);
+        assert_eq!(summary.low_01_fps, 50f64);
+        assert_eq!(summary.tick_ms, 5f64);
+        assert_eq!(summary.render_ms, 10f64);
+    }
+
+    #[test]
+    fn lows_average_the_slowest_frames() {
+        // 200 frames, the slowest two are the 1% and the slowest one alone the 0.1%
+        let mut frame_ms = vec![10f64; 197];
+        frame_ms.extend([40f64, 100f64, 25f64]);
+        let summary = stats(DEFAULT_WINDOW, &frame_ms).summary();
+        assert_eq!(summary.min_ms, 10f64);
+        assert_eq!(summary.max_ms, 100f64);
+        assert!(close(summary.avg_ms, (1970f64 + 165f64) / 200f64));
+        assert!(close(summary.low_1_fps, 1000f64 / 70f64), "{}", summary.low_1_fps);
+        assert!(close(summary.low_01_fps, 10f64), "{}", summary.low_01_fps);
+    }
+
+    #[test]
+    fn lows_round_the_frame_count_up() {
+        // 1% of 150 frames is 1.5, so the two slowest count
+        let mut frame_ms = vec![10f64; 148];
+        frame_ms.extend([20f64, 30f64]);
+        let summary = stats(DEFAULT_WINDOW, &frame_ms).summary();
+        assert!(close(summary.low_1_fps, 40f64), "{}", summary.low_1_fps);
+        assert!(close(summary.low_01_fps, 1000f64 / 30f64), "{}", summary.low_01_fps);
+    }
+
+    #[test]
+    fn old_frames_leave_the_window() {
+        let stats = stats(3, &[100f64, 1f64, 2f64, 3f64]);
+        let summary = stats.summary();
+        assert_eq!(stats.frame(), 4);
+        assert_eq!(summary.max_ms, 3f64);
+        assert_eq!(summary.min_ms, 1f64);
+        assert!(close(summary.avg_ms, 2f64));
+    }
+
+    #[test]
+    fn a_zero_window_still_keeps_the_last_frame() {
+        let summary = stats(0, &[5f64, 8f64]).summary();
+        assert_eq!(summary.avg_ms, 8f64);
+    }
+}
@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use vulkano::device::Device;
+use vulkano::sync::future::FenceSignalFuture;
+use vulkano::sync::{self, GpuFuture};
+
+use crate::error::{Result, SandError};
+
+// the tick + render + present chain is boxed, its full type changes every time a stage is added
+pub type FenceExpanded = Option<Arc<FenceSignalFuture<Box<dyn GpuFuture>>>>;
+type TickFence = Arc<FenceSignalFuture<Box<dyn GpuFuture>>>;
+
+/// Everything still in flight, so each submission waits on the gpu for only what it has to.
+/// A tick waits for the last tick and for the last frame that drew the snapshot it writes over, not for
+/// the frame before it, that one draws the other snapshot while the tick runs. See `World::snapshots`.
+pub struct Frames {
+    presents: Vec<FenceExpanded>, // per swapchain image, the last frame drawn to it
+    ticks: Vec<FenceExpanded>,    // per swapchain image, the tick in that frame
+    // the tick that wrote each snapshot and the last frame that drew it. vulkano only lets a tick write
+    // a snapshot that's still being drawn if the future it runs after has the tick that wrote it last,
+    // so those stay uncleaned until they're overwritten
+    written: [FenceExpanded; 2],
+    drawn: [FenceExpanded; 2],
+    retired: Vec<TickFence>, // overwritten, cleaned once they've finished so the chains don't grow forever
+}
+
+impl Frames {
+    pub fn new(images: usize) -> Frames {
+        Frames {
+            presents: vec![None; images],
+            ticks: vec![None; images],
+            written: [None, None],
+            drawn: [None, None],
+            retired: Vec::new(),
+        }
+    }
+
+    /// Waits for the last frame drawn to `image`, so its command buffers and tick slot can be used again.
+    /// False when nothing has been drawn to it yet.
+    pub fn wait_image(&mut self, image: usize) -> Result<bool> {
+        // the tick's command buffer is only released once its fence is waited on, and a frame that
+        // couldn't be flushed still has its tick in flight
+        if let Some(tick) = &self.ticks[image] {
+            tick.wait(None)
+                .map_err(|e| SandError::submission("sand tick", e))?;
+        }
+        let Some(present) = &self.presents[image] else {
+            return Ok(false);
+        };
+        present
+            .wait(None)
+            .map_err(|e| SandError::submission("frame", e))?;
+        Ok(true)
+    }
+
+    /// Waits for everything in flight, for when the cpu has to write something the gpu reads.
+    pub fn wait_all(&mut self) -> Result<()> {
+        let ticks = self
+            .written
+            .iter_mut()
+            .chain(self.ticks.iter_mut())
+            .filter_map(Option::take);
+        for fence in ticks.chain(self.retired.drain(..)) {
+            fence
+                .wait(None)
+                .map_err(|e| SandError::submission("sand tick", e))?;
+        }
+        let presents = self
+            .presents
+            .iter_mut()
+            .chain(self.drawn.iter_mut())
+            .filter_map(Option::take);
+        for fence in presents {
+            fence
+                .wait(None)
+                .map_err(|e| SandError::submission("frame", e))?;
+        }
+        Ok(())
+    }
+
+    /// What the tick writing `snapshot` runs after, the last tick and the last frame that drew `snapshot`.
+    pub fn before_tick(&self, device: &Arc<Device>, snapshot: usize) -> Result<Box<dyn GpuFuture>> {
+        let last_tick = match &self.written[1 - snapshot] {
+            Some(tick) => tick.clone().boxed(),
+            None => sync::now(device.clone()).boxed(),
+        };
+        let drawing = match &self.drawn[snapshot] {
+            Some(present)
+                if !present
+                    .is_signaled()
+                    .map_err(|e| SandError::submission("frame", e))? =>
+            {
+                Some(present.clone())
+            }
+            _ => None,
+        };
+        Ok(match drawing {
+            // the render is on another queue, a semaphore makes the tick wait for it there
+            Some(present) => last_tick.join(present.then_signal_semaphore()).boxed(),
+            None => last_tick,
+        })
+    }
+
+    /// `tick` wrote `snapshot` in the frame drawn to `image`.
+    pub fn ticked(&mut self, image: usize, snapshot: usize, tick: TickFence) -> Result<()> {
+        self.ticks[image] = Some(tick.clone());
+        if let Some(overwritten) = self.written[snapshot].replace(tick) {
+            self.retired.push(overwritten);
+        }
+        let mut running = Vec::new();
+        for tick in self.retired.drain(..) {
+            if tick
+                .is_signaled()
+                .map_err(|e| SandError::submission("sand tick", e))?
+            {
+                tick.wait(None)
+                    .map_err(|e| SandError::submission("sand tick", e))?;
+            } else {
+                running.push(tick);
+            }
+        }
+        self.retired = running;
+        Ok(())
+    }
+
+    /// What drawing `snapshot` runs after, the tick that wrote it.
+    pub fn before_render(&self, device: &Arc<Device>, snapshot: usize) -> Box<dyn GpuFuture> {
+        match &self.written[snapshot] {
+            Some(tick) => tick.clone().then_signal_semaphore().boxed(),
+            None => sync::now(device.clone()).boxed(),
+        }
+    }
+
+    /// `present` drew `snapshot` to `image`, None when it couldn't be flushed.
+    pub fn rendered(&mut self, image: usize, snapshot: usize, present: FenceExpanded) {
+        if present.is_some() {
+            self.drawn[snapshot] = present.clone();
+        }
+        self.presents[image] = present;
+    }
+}
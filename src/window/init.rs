@@ -1,7 +1,6 @@
 use std::sync::Arc;
 
 use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
-use vulkano::command_buffer::CommandBufferExecFuture;
 use vulkano::device::physical::PhysicalDevice;
 use vulkano::device::Queue;
 use vulkano::device::{
@@ -13,34 +12,30 @@ use vulkano::instance::{Instance, InstanceCreateInfo};
 
 use vulkano::VulkanLibrary;
 
-use vulkano::memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator};
+use vulkano::memory::allocator::{
+    AllocationCreateInfo, MemoryAllocator, MemoryUsage, StandardMemoryAllocator,
+};
 use vulkano::pipeline::graphics::viewport::Viewport;
 use vulkano::render_pass::RenderPass;
-use vulkano::shader::ShaderModule;
-use vulkano::swapchain::{PresentFuture, Surface, SwapchainAcquireFuture};
-use vulkano::sync::future::{FenceSignalFuture, JoinFuture};
-use vulkano::sync::GpuFuture;
+use vulkano::swapchain::Surface;
 use vulkano_win::VkSurfaceBuild;
 
 use winit::dpi::PhysicalSize;
 use winit::event_loop::EventLoop;
 use winit::window::{Window, WindowBuilder};
 
+use crate::config::RenderConfig;
+use crate::debug;
+use crate::error::{Result, SandError};
+use crate::gpu_constructor::surface_window;
 use crate::pass_structs::WindowInitialized;
+use crate::profiler::GpuProfiler;
+use crate::simulation::world::World;
 
-use super::utils::{self, CPUVertex};
-
-type FenceExpanded = Option<
-    Arc<
-        FenceSignalFuture<
-            PresentFuture<
-                CommandBufferExecFuture<JoinFuture<Box<dyn GpuFuture>, SwapchainAcquireFuture>>,
-            >,
-        >,
-    >,
->;
+use super::frames::Frames;
+use super::utils::{self, CPUVertex, PresentPreference, RenderCommands, RenderShaders};
 
-pub fn initialize_window(library: &Arc<VulkanLibrary>) -> WindowInitialized {
+pub fn initialize_window(library: &Arc<VulkanLibrary>) -> Result<WindowInitialized> {
     let required_extensions = vulkano_win::required_extensions(library);
     let instance = Instance::new(
         library.clone(),
@@ -49,20 +44,15 @@ pub fn initialize_window(library: &Arc<VulkanLibrary>) -> WindowInitialized {
             ..Default::default()
         },
     )
-    .expect("failed to create instance");
+    .map_err(|e| SandError::Instance(e.to_string()))?;
 
     let event_loop = EventLoop::new();
 
     let surface = WindowBuilder::new()
         .build_vk_surface(&event_loop, instance.clone())
-        .unwrap();
+        .map_err(|e| SandError::Instance(e.to_string()))?;
 
-    let window = surface
-        .object()
-        .unwrap()
-        .clone()
-        .downcast::<Window>()
-        .unwrap();
+    let window = surface_window(&surface)?;
 
     let device_extensions = DeviceExtensions {
         khr_swapchain: true,
@@ -71,7 +61,7 @@ pub fn initialize_window(library: &Arc<VulkanLibrary>) -> WindowInitialized {
 
     let (physical_device, queue_family_index) = instance
         .enumerate_physical_devices()
-        .expect("failed to get devices")
+        .map_err(|e| SandError::DeviceSelection(format!("failed to get devices: {e}")))?
         .filter(|p| p.supported_extensions().contains(&device_extensions))
         .filter_map(|p| {
             p.queue_family_properties()
@@ -90,7 +80,7 @@ pub fn initialize_window(library: &Arc<VulkanLibrary>) -> WindowInitialized {
             PhysicalDeviceType::Cpu => 3,
             _ => 4,
         })
-        .expect("no device available");
+        .ok_or_else(|| SandError::DeviceSelection("no device available".to_string()))?;
 
     let (device, mut queues) = Device::new(
         physical_device.clone(),
@@ -102,23 +92,14 @@ pub fn initialize_window(library: &Arc<VulkanLibrary>) -> WindowInitialized {
             enabled_extensions: device_extensions, // new
             ..Default::default()
         },
-    ).expect("failed to create window device? how could the buffer succeed and this fail, gpu isn't plugged in?");
-
-    let queue = queues.next().unwrap();
+    ).map_err(|e| SandError::Device(format!("failed to create window device? how could the buffer succeed and this fail, gpu isn't plugged in? {e}")))?;
 
-    let caps = physical_device
-        .surface_capabilities(&surface, Default::default())
-        .expect("failed to get surface capabilities");
+    let queue = queues
+        .next()
+        .ok_or_else(|| SandError::Device("no queue was created for the window".to_string()))?;
 
     let window_size = window.inner_size();
-    let _composite_alpha = caps.supported_composite_alpha.into_iter().next().unwrap();
-    let _image_format = Some(
-        physical_device
-            .surface_formats(&surface, Default::default())
-            .unwrap()[0]
-            .0,
-    );
-    WindowInitialized {
+    Ok(WindowInitialized {
         physical_device, // cool rust feature you don't need field names if its the same
         surface,
         device,
@@ -126,7 +107,7 @@ pub fn initialize_window(library: &Arc<VulkanLibrary>) -> WindowInitialized {
         window_size,
         event_loop,
         queue,
-    }
+    })
 }
 
 pub fn initialize_window_from_preexisting(
@@ -134,7 +115,7 @@ pub fn initialize_window_from_preexisting(
     device: Arc<Device>,
     queue: Arc<Queue>,
     library: &Arc<VulkanLibrary>,
-) -> WindowInitialized {
+) -> Result<WindowInitialized> {
     let required_extensions = vulkano_win::required_extensions(library);
     let instance = Instance::new(
         library.clone(),
@@ -143,21 +124,22 @@ pub fn initialize_window_from_preexisting(
             ..Default::default()
         },
     )
-    .expect("failed to make instance");
+    .map_err(|e| SandError::Instance(e.to_string()))?;
     let event_loop = EventLoop::new();
     let surface = WindowBuilder::new()
         .build_vk_surface(&event_loop, instance.clone())
-        .unwrap();
-    let window = surface
-        .object()
-        .unwrap()
-        .clone()
-        .downcast::<Window>()
-        .unwrap();
-    let (swapchain, images) =
-        utils::get_swapchain(&physical_device, &device, &window, surface.clone());
+        .map_err(|e| SandError::Instance(e.to_string()))?;
+    let window = surface_window(&surface)?;
+    let (swapchain, images) = utils::get_swapchain(
+        &physical_device,
+        &device,
+        &window,
+        surface.clone(),
+        PresentPreference::LowLatency,
+        false,
+    )?;
     let window_size = window.inner_size();
-    WindowInitialized {
+    Ok(WindowInitialized {
         physical_device, // cool rust feature you don't need field names if its the same
         surface,
         device,
@@ -165,33 +147,41 @@ pub fn initialize_window_from_preexisting(
         window_size,
         event_loop,
         queue,
-    }
+    })
 }
 
-pub fn initialize_swapchain_screen<T>(
+pub fn initialize_swapchain_screen(
     render_physical_device: Arc<PhysicalDevice>,
     render_device: Arc<Device>,
     window: Arc<Window>,
     surface: Arc<Surface>,
     window_size: PhysicalSize<u32>,
     render_queue: Arc<Queue>,
-    buffer: &Subbuffer<[T]>,
-) -> (
+    world: &World, // the particles and geometry are drawn straight from its buffers
+    render_params: &Subbuffer<particle_vs::RenderParams>,
+    profiler: Option<&GpuProfiler>,
+    present_preference: PresentPreference,
+    prefer_hdr: bool,
+) -> Result<(
     std::sync::Arc<vulkano::swapchain::Swapchain>,
     bool,
-    std::vec::Vec<std::sync::Arc<vulkano::command_buffer::PrimaryAutoCommandBuffer>>,
+    Vec<RenderCommands>,
     Viewport,
     Arc<RenderPass>,
-    Arc<ShaderModule>,
-    Arc<ShaderModule>,
+    RenderShaders,
     Subbuffer<[CPUVertex]>,
-    Vec<FenceExpanded>,
-    u32,
-) {
-    let (swapchain, images) =
-        utils::get_swapchain(&render_physical_device, &render_device, &window, surface);
-    let render_pass = utils::get_render_pass(render_device.clone(), swapchain.clone());
-    let frame_buffers = utils::get_framebuffers(&images, render_pass.clone());
+    Frames,
+)> {
+    let (swapchain, images) = utils::get_swapchain(
+        &render_physical_device,
+        &render_device,
+        &window,
+        surface,
+        present_preference,
+        prefer_hdr,
+    )?;
+    let render_pass = utils::get_render_pass(render_device.clone(), swapchain.clone())?;
+    let frame_buffers = utils::get_framebuffers(&images, render_pass.clone())?;
 
     let render_memory_allocator = StandardMemoryAllocator::new_default(render_device.clone());
 
@@ -219,10 +209,21 @@ pub fn initialize_swapchain_screen<T>(
         },
         vec![vertex1, vertex2, vertex3],
     )
-    .unwrap();
+    .map_err(|e| SandError::allocation("vertex buffer", e))?;
+    debug::name(vertex_buffer.buffer().as_ref(), "fullscreen triangle");
 
-    let vs_loaded = vs::load(render_device.clone()).expect("failed to create shader module");
-    let fs_loaded = fs::load(render_device.clone()).expect("failed to create shader module");
+    let shaders = RenderShaders {
+        background_vs: vs::load(render_device.clone())
+            .map_err(|e| SandError::pipeline("background vertex shader", e))?,
+        background_fs: fs::load(render_device.clone())
+            .map_err(|e| SandError::pipeline("background fragment shader", e))?,
+        particle_vs: particle_vs::load(render_device.clone())
+            .map_err(|e| SandError::pipeline("particle vertex shader", e))?,
+        particle_fs: particle_fs::load(render_device.clone())
+            .map_err(|e| SandError::pipeline("particle fragment shader", e))?,
+        geometry_fs: geometry_fs::load(render_device.clone())
+            .map_err(|e| SandError::pipeline("geometry fragment shader", e))?,
+    };
 
     let viewport = Viewport {
         origin: [0.0, 0.0],
@@ -232,43 +233,73 @@ pub fn initialize_swapchain_screen<T>(
 
     let recreate_swapchain = false;
     let frames_in_flight = images.len();
-    let fences: Vec<FenceExpanded> = vec![None; frames_in_flight];
-    let previous_fence_i = 0;
+    let frames = Frames::new(frames_in_flight);
 
-    let render_pipeline = utils::get_pipeline(
+    let render_pipelines = utils::get_pipelines(
         render_device.clone(),
-        vs_loaded.clone(),
-        fs_loaded.clone(),
+        &shaders,
         render_pass.clone(),
         viewport.clone(),
-    );
-    let push_constants = fs::PushType {
-        dims: [window_size.width as f32, window_size.height as f32],
-    };
+    )?;
+    let push_constants = utils::push_constants(&swapchain, window_size.into());
     let command_buffers = utils::get_command_buffers(
         &render_device,
         &render_queue,
-        &render_pipeline,
+        &render_pipelines,
         &frame_buffers,
         &vertex_buffer,
         push_constants,
-        buffer,
-    );
+        world,
+        render_params,
+        profiler,
+    )?;
 
-    (
+    Ok((
         swapchain,
         recreate_swapchain,
         command_buffers,
         viewport,
         render_pass,
-        vs_loaded,
-        fs_loaded,
+        shaders,
         vertex_buffer,
-        fences,
-        previous_fence_i,
+        frames,
+    ))
+}
+
+impl From<&RenderConfig> for particle_vs::RenderParams {
+    fn from(config: &RenderConfig) -> particle_vs::RenderParams {
+        particle_vs::RenderParams {
+            colour_scale: config.colour_scale,
+            particle_radius: config.particle_radius,
+            show_geometry: config.show_geometry as u32,
+        }
+    }
+}
+
+/// Host visible so a config reload can write it, only do that while no frame is in flight.
+pub fn upload_render_params(
+    memory_allocator: &(impl MemoryAllocator + ?Sized),
+    config: &RenderConfig,
+) -> Result<Subbuffer<particle_vs::RenderParams>> {
+    let render_params = Buffer::from_data(
+        memory_allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::UNIFORM_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::Upload,
+            ..Default::default()
+        },
+        particle_vs::RenderParams::from(config),
     )
+    .map_err(|e| SandError::allocation("render params buffer", e))?;
+    debug::name(render_params.buffer().as_ref(), "render params");
+    Ok(render_params)
 }
 
+// the background and geometry overlay are fullscreen triangles, the particles instanced quads over the
+// background. all of them include test/render.glsl, the rust side of its structs is taken from particle_vs
 pub mod vs {
     vulkano_shaders::shader! {
         ty: "vertex",
@@ -282,3 +313,24 @@ pub mod fs {
         path:"src/shaders/test/test_frag.frag"
     }
 }
+
+pub mod particle_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path:"src/shaders/test/particle.vert"
+    }
+}
+
+pub mod particle_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path:"src/shaders/test/particle.frag"
+    }
+}
+
+pub mod geometry_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path:"src/shaders/test/geometry.frag"
+    }
+}
@@ -10,20 +10,136 @@ use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
 use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
 use vulkano::device::physical::PhysicalDevice;
 use vulkano::device::{Device, Queue};
+use vulkano::format::{Format, NumericType};
 use vulkano::image::ImageUsage;
 use vulkano::image::{view::ImageView, SwapchainImage};
+use vulkano::pipeline::graphics::color_blend::ColorBlendState;
 use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
-use vulkano::pipeline::graphics::vertex_input::Vertex;
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexBufferDescription};
 use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
 use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint};
 use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
 use vulkano::shader::ShaderModule;
 use vulkano::swapchain::{
-    PresentMode, Surface, Swapchain, SwapchainCreateInfo, SwapchainCreationError,
+    ColorSpace, PresentMode, Surface, Swapchain, SwapchainCreateInfo, SwapchainCreationError,
 };
 use winit::window::Window;
 
 use super::init;
+use crate::debug;
+use crate::error::{Result, SandError};
+use crate::profiler::{GpuProfiler, Stage};
+use crate::simulation::world::World;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresentPreference {
+    Vsync,      // never tears, caps at the refresh rate
+    LowLatency, // newest frame wins, doesn't tear if mailbox is there
+    Uncapped,   // as fast as possible, tearing is fine
+}
+
+impl PresentPreference {
+    // fifo is the only mode every device has to support so every chain ends with it
+    fn fallback_chain(self) -> &'static [PresentMode] {
+        match self {
+            PresentPreference::Vsync => &[PresentMode::Fifo],
+            PresentPreference::LowLatency => &[
+                PresentMode::Mailbox,
+                PresentMode::Immediate,
+                PresentMode::Fifo,
+            ],
+            PresentPreference::Uncapped => &[
+                PresentMode::Immediate,
+                PresentMode::Mailbox,
+                PresentMode::Fifo,
+            ],
+        }
+    }
+
+    /// `vsync`, `low-latency` or `uncapped`.
+    pub fn parse(value: &str) -> Option<PresentPreference> {
+        match value.to_ascii_lowercase().as_str() {
+            "vsync" | "fifo" => Some(PresentPreference::Vsync),
+            "low-latency" | "mailbox" => Some(PresentPreference::LowLatency),
+            "uncapped" | "immediate" => Some(PresentPreference::Uncapped),
+            _ => None,
+        }
+    }
+
+    pub fn next(self) -> PresentPreference {
+        match self {
+            PresentPreference::Vsync => PresentPreference::LowLatency,
+            PresentPreference::LowLatency => PresentPreference::Uncapped,
+            PresentPreference::Uncapped => PresentPreference::Vsync,
+        }
+    }
+}
+
+pub fn choose_present_mode(
+    physical_device: &Arc<PhysicalDevice>,
+    surface: &Surface,
+    preference: PresentPreference,
+) -> PresentMode {
+    let supported: Vec<PresentMode> = match physical_device.surface_present_modes(surface) {
+        Ok(modes) => modes.collect(),
+        Err(e) => {
+            log::warn!("failed to query present modes, using fifo: {e}");
+            return PresentMode::Fifo;
+        }
+    };
+    preference
+        .fallback_chain()
+        .iter()
+        .copied()
+        .find(|mode| supported.contains(mode))
+        .unwrap_or(PresentMode::Fifo)
+}
+
+// srgb formats get encoded by the hardware on write, so the shader can always work in linear
+const SRGB_FORMATS: [Format; 3] = [
+    Format::B8G8R8A8_SRGB,
+    Format::R8G8B8A8_SRGB,
+    Format::A8B8G8R8_SRGB_PACK32,
+];
+
+pub fn choose_surface_format(
+    physical_device: &Arc<PhysicalDevice>,
+    surface: &Surface,
+    prefer_hdr: bool,
+) -> Result<(Format, ColorSpace)> {
+    let formats = physical_device
+        .surface_formats(surface, Default::default())
+        .map_err(|e| SandError::swapchain("format query", e))?;
+
+    // only shows up when the instance has ext_swapchain_colorspace enabled
+    let hdr = formats.iter().copied().find(|&(format, color_space)| {
+        format == Format::R16G16B16A16_SFLOAT && color_space == ColorSpace::ExtendedSrgbLinear
+    });
+    let srgb = SRGB_FORMATS.iter().find_map(|srgb_format| {
+        formats.iter().copied().find(|&(format, color_space)| {
+            format == *srgb_format && color_space == ColorSpace::SrgbNonLinear
+        })
+    });
+
+    match (hdr, srgb) {
+        (Some(hdr), _) if prefer_hdr => Ok(hdr),
+        (_, Some(srgb)) => Ok(srgb),
+        // no srgb format, the fragment shader will do the encoding itself
+        _ => formats
+            .first()
+            .copied()
+            .ok_or_else(|| SandError::swapchain("format query", "the surface has no formats")),
+    }
+}
+
+pub fn push_constants(swapchain: &Swapchain, dimensions: [u32; 2]) -> init::particle_vs::PushType {
+    let encode_srgb = swapchain.image_color_space() == ColorSpace::SrgbNonLinear
+        && swapchain.image_format().type_color() != Some(NumericType::SRGB);
+    init::particle_vs::PushType {
+        dims: [dimensions[0] as f32, dimensions[1] as f32],
+        encode_srgb: encode_srgb as u32,
+    }
+}
 
 #[derive(BufferContents, Vertex)]
 #[repr(C)]
@@ -32,7 +148,7 @@ pub struct CPUVertex {
     pub position: [f32; 2],
 }
 
-pub fn get_render_pass(device: Arc<Device>, swapchain: Arc<Swapchain>) -> Arc<RenderPass> {
+pub fn get_render_pass(device: Arc<Device>, swapchain: Arc<Swapchain>) -> Result<Arc<RenderPass>> {
     vulkano::single_pass_renderpass!(
         device,
         attachments: {
@@ -48,17 +164,18 @@ pub fn get_render_pass(device: Arc<Device>, swapchain: Arc<Swapchain>) -> Arc<Re
             depth_stencil: {},
         },
     )
-    .unwrap()
+    .map_err(|e| SandError::pipeline("render pass", e))
 }
 
 pub fn get_framebuffers(
     images: &[Arc<SwapchainImage>],
     render_pass: Arc<RenderPass>,
-) -> Vec<Arc<Framebuffer>> {
+) -> Result<Vec<Arc<Framebuffer>>> {
     images
         .iter()
         .map(|image| {
-            let view = ImageView::new_default(image.clone()).unwrap();
+            let view = ImageView::new_default(image.clone())
+                .map_err(|e| SandError::swapchain("image view", e))?;
             Framebuffer::new(
                 render_pass.clone(),
                 FramebufferCreateInfo {
@@ -66,90 +183,188 @@ pub fn get_framebuffers(
                     ..Default::default()
                 },
             )
-            .unwrap()
+            .map_err(|e| SandError::swapchain("framebuffer", e))
         })
-        .collect::<Vec<_>>()
+        .collect()
+}
+
+/// Every shader the window draws with, loaded once and kept for when the swapchain is recreated.
+#[derive(Clone)]
+pub struct RenderShaders {
+    pub background_vs: Arc<ShaderModule>, // the fullscreen triangle, also under geometry_fs
+    pub background_fs: Arc<ShaderModule>,
+    pub particle_vs: Arc<ShaderModule>,
+    pub particle_fs: Arc<ShaderModule>,
+    pub geometry_fs: Arc<ShaderModule>,
 }
 
-pub fn get_pipeline(
+/// One per snapshot in `World::snapshots`, the command buffer drawing it.
+pub type RenderCommands = [Arc<PrimaryAutoCommandBuffer>; 2];
+
+/// Drawn in this order in the one subpass, each over the last.
+pub struct RenderPipelines {
+    pub background: Arc<GraphicsPipeline>,
+    pub particles: Arc<GraphicsPipeline>,
+    pub geometry: Arc<GraphicsPipeline>,
+}
+
+pub fn get_pipelines(
     device: Arc<Device>,
-    vs: Arc<ShaderModule>,
-    fs: Arc<ShaderModule>,
+    shaders: &RenderShaders,
     render_pass: Arc<RenderPass>,
     viewport: Viewport,
-) -> Arc<GraphicsPipeline> {
-    GraphicsPipeline::start()
-        .vertex_input_state(CPUVertex::per_vertex())
-        .vertex_shader(vs.entry_point("main").unwrap(), ())
-        .input_assembly_state(InputAssemblyState::new())
-        .viewport_state(ViewportState::viewport_fixed_scissor_irrelevant([viewport]))
-        .fragment_shader(fs.entry_point("main").unwrap(), ())
-        .render_pass(Subpass::from(render_pass, 0).unwrap())
-        .build(device)
-        .unwrap()
+) -> Result<RenderPipelines> {
+    let subpass = Subpass::from(render_pass, 0)
+        .ok_or_else(|| SandError::pipeline("render pipeline", "render pass has no subpass 0"))?;
+    let get_pipeline = |name: &str,
+                        vs: &Arc<ShaderModule>,
+                        fs: &Arc<ShaderModule>,
+                        vertex_buffers: Vec<VertexBufferDescription>,
+                        color_blend_state: ColorBlendState|
+     -> Result<Arc<GraphicsPipeline>> {
+        let vs_main = vs.entry_point("main").ok_or_else(|| {
+            SandError::pipeline("render pipeline", format!("{name} vertex shader has no main"))
+        })?;
+        let fs_main = fs.entry_point("main").ok_or_else(|| {
+            SandError::pipeline("render pipeline", format!("{name} fragment shader has no main"))
+        })?;
+        let pipeline = GraphicsPipeline::start()
+            .vertex_input_state(vertex_buffers)
+            .vertex_shader(vs_main, ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_fixed_scissor_irrelevant([viewport.clone()]))
+            .fragment_shader(fs_main, ())
+            .color_blend_state(color_blend_state)
+            .render_pass(subpass.clone())
+            .build(device.clone())
+            .map_err(|e| SandError::pipeline("render pipeline", e))?;
+        debug::name(pipeline.as_ref(), &format!("render {name} pipeline"));
+        Ok(pipeline)
+    };
+    Ok(RenderPipelines {
+        background: get_pipeline(
+            "background",
+            &shaders.background_vs,
+            &shaders.background_fs,
+            vec![CPUVertex::per_vertex()],
+            ColorBlendState::new(1),
+        )?,
+        // the quads' corners come from gl_VertexIndex so there's no vertex buffer
+        particles: get_pipeline(
+            "particles",
+            &shaders.particle_vs,
+            &shaders.particle_fs,
+            Vec::new(),
+            ColorBlendState::new(1).blend_alpha(),
+        )?,
+        geometry: get_pipeline(
+            "geometry",
+            &shaders.background_vs,
+            &shaders.geometry_fs,
+            vec![CPUVertex::per_vertex()],
+            ColorBlendState::new(1).blend_alpha(),
+        )?,
+    })
 }
 
-pub fn get_command_buffers<T>(
+pub fn get_command_buffers(
     device: &Arc<Device>,
     queue: &Arc<Queue>,
-    pipeline: &Arc<GraphicsPipeline>,
+    pipelines: &RenderPipelines,
     frame_buffers: &[Arc<Framebuffer>],
     vertex_buffer: &Subbuffer<[CPUVertex]>,
-    push_constants: init::fs::PushType,
-    buffer: &Subbuffer<[T]>,
-) -> Vec<Arc<PrimaryAutoCommandBuffer>> {
+    push_constants: init::particle_vs::PushType,
+    world: &World, // the particles and geometry are drawn straight from its buffers
+    render_params: &Subbuffer<init::particle_vs::RenderParams>,
+    profiler: Option<&GpuProfiler>,
+) -> Result<Vec<RenderCommands>> {
     let command_buffer_allocator =
         StandardCommandBufferAllocator::new(device.clone(), Default::default());
     frame_buffers
         .iter()
-        .map(|frame_buffer| {
-            build_render_pass(
-                frame_buffer,
-                queue,
-                pipeline,
-                vertex_buffer,
-                &command_buffer_allocator,
-                push_constants,
-                buffer,
-                device,
-            )
+        .enumerate()
+        .map(|(image_i, frame_buffer)| {
+            let record = |snapshot: usize| {
+                build_render_pass(
+                    frame_buffer,
+                    queue,
+                    pipelines,
+                    vertex_buffer,
+                    &command_buffer_allocator,
+                    push_constants,
+                    world,
+                    snapshot,
+                    render_params,
+                    device,
+                    profiler,
+                    image_i as u32,
+                )
+            };
+            Ok([record(0)?, record(1)?])
         })
         .collect()
 }
 
-fn build_render_pass<T>(
+// binds what the pipeline's shaders read of the render bindings, the layout only has the ones they use
+fn bind_render_bindings(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    pipeline: &Arc<GraphicsPipeline>,
+    descriptor_set_allocator: &StandardDescriptorSetAllocator,
+    world: &World,
+    snapshot: usize,
+    render_params: &Subbuffer<init::particle_vs::RenderParams>,
+    push_constants: init::particle_vs::PushType,
+) -> Result<()> {
+    let layout = pipeline.layout();
+    builder.bind_pipeline_graphics(pipeline.clone());
+    if let Some(descriptor_set_layout) = layout.set_layouts().get(0) {
+        log::trace!("render descriptor set layout: {descriptor_set_layout:?}");
+        let writes = [
+            WriteDescriptorSet::buffer(0, world.snapshots[snapshot].clone()), // 0 is the binding
+            WriteDescriptorSet::buffer(1, render_params.clone()),
+            WriteDescriptorSet::buffer(2, world.shapes.clone()),
+            WriteDescriptorSet::buffer(3, world.vertices.clone()),
+        ]
+        .into_iter()
+        .filter(|write| descriptor_set_layout.bindings().contains_key(&write.binding()));
+        let descriptor_set = PersistentDescriptorSet::new(
+            descriptor_set_allocator,
+            descriptor_set_layout.clone(),
+            writes,
+        )
+        .map_err(|e| SandError::pipeline("render descriptor set", e))?;
+        builder.bind_descriptor_sets(PipelineBindPoint::Graphics, layout.clone(), 0, descriptor_set);
+    }
+    builder.push_constants(layout.clone(), 0, push_constants);
+    Ok(())
+}
+
+fn build_render_pass(
     frame_buffer: &Arc<Framebuffer>,
     queue: &Arc<Queue>,
-    pipeline: &Arc<GraphicsPipeline>,
+    pipelines: &RenderPipelines,
     vertex_buffer: &Subbuffer<[CPUVertex]>,
     command_buffer_allocator: &StandardCommandBufferAllocator,
-    push_constants: init::fs::PushType,
-    buffer: &Subbuffer<[T]>,
+    push_constants: init::particle_vs::PushType,
+    world: &World,
+    snapshot: usize,
+    render_params: &Subbuffer<init::particle_vs::RenderParams>,
     device: &Arc<Device>,
-) -> Arc<PrimaryAutoCommandBuffer> {
+    profiler: Option<&GpuProfiler>,
+    image_i: u32,
+) -> Result<Arc<PrimaryAutoCommandBuffer>> {
     let mut builder = AutoCommandBufferBuilder::primary(
         command_buffer_allocator,
         queue.queue_family_index(),
         CommandBufferUsage::MultipleSubmit,
     )
-    .unwrap();
-
-    let layout = pipeline.layout();
-    let descriptor_set_layouts = layout.set_layouts();
-    println!("{descriptor_set_layouts:?}");
-    let descriptor_set_layout = descriptor_set_layouts.get(0).unwrap();
+    .map_err(|e| SandError::command("render", e))?;
 
     let descriptor_set_allocator = StandardDescriptorSetAllocator::new(device.clone());
 
-    let descriptor_set = match PersistentDescriptorSet::new(
-        &descriptor_set_allocator,
-        descriptor_set_layout.clone(),
-        [WriteDescriptorSet::buffer(0, buffer.clone())], // 0 is the binding
-    ) {
-        Ok(res) => res,
-        Err(e) => panic!("Error with {e:?}"),
-    };
-
+    if let Some(profiler) = profiler {
+        profiler.begin(&mut builder, Stage::Render, image_i)?;
+    }
     builder
         .begin_render_pass(
             RenderPassBeginInfo {
@@ -158,22 +373,62 @@ fn build_render_pass<T>(
             },
             SubpassContents::Inline,
         )
-        .unwrap()
-        .bind_pipeline_graphics(pipeline.clone())
-        .bind_vertex_buffers(0, vertex_buffer.clone())
-        .push_constants(layout.clone(), 0, push_constants)
+        .map_err(|e| SandError::command("render", e))?
+        .bind_vertex_buffers(0, vertex_buffer.clone());
+
+    bind_render_bindings(
+        &mut builder,
+        &pipelines.background,
+        &descriptor_set_allocator,
+        world,
+        snapshot,
+        render_params,
+        push_constants,
+    )?;
+    builder
         .draw(vertex_buffer.len() as u32, 1, 0, 0)
-        .unwrap()
-        .bind_descriptor_sets(
-            PipelineBindPoint::Compute,
-            layout.clone(),
-            0,
-            descriptor_set,
-        )
+        .map_err(|e| SandError::command("render", e))?;
+
+    // a quad per particle slot rather than every fragment looking through every particle,
+    // blending draws them in slot order like the loop used to
+    bind_render_bindings(
+        &mut builder,
+        &pipelines.particles,
+        &descriptor_set_allocator,
+        world,
+        snapshot,
+        render_params,
+        push_constants,
+    )?;
+    builder
+        .draw(6, world.len() as u32, 0, 0)
+        .map_err(|e| SandError::command("render", e))?;
+
+    bind_render_bindings(
+        &mut builder,
+        &pipelines.geometry,
+        &descriptor_set_allocator,
+        world,
+        snapshot,
+        render_params,
+        push_constants,
+    )?;
+    builder
+        .draw(vertex_buffer.len() as u32, 1, 0, 0)
+        .map_err(|e| SandError::command("render", e))?
         .end_render_pass()
-        .unwrap();
+        .map_err(|e| SandError::command("render", e))?;
+    if let Some(profiler) = profiler {
+        profiler.end(&mut builder, Stage::Render, image_i)?;
+    }
 
-    Arc::new(builder.build().unwrap())
+    let command_buffer = Arc::new(
+        builder
+            .build()
+            .map_err(|e| SandError::command("render", e))?,
+    );
+    debug::name(command_buffer.as_ref(), &format!("render {image_i} snapshot {snapshot}"));
+    Ok(command_buffer)
 }
 
 pub fn get_swapchain(
@@ -181,40 +436,45 @@ pub fn get_swapchain(
     render_device: &Arc<Device>,
     window: &std::sync::Arc<winit::window::Window>,
     surface: Arc<Surface>,
-) -> (Arc<Swapchain>, Vec<Arc<SwapchainImage>>) {
+    present_preference: PresentPreference,
+    prefer_hdr: bool,
+) -> Result<(Arc<Swapchain>, Vec<Arc<SwapchainImage>>)> {
     let (swapchain, images) = {
         let caps = render_physical_device
             .surface_capabilities(&surface, Default::default())
-            .expect("failed to get surface capabilities");
+            .map_err(|e| SandError::swapchain("capability query", e))?;
 
         let dimensions = window.inner_size();
-        let composite_alpha = caps.supported_composite_alpha.into_iter().next().unwrap();
-        let image_format = Some(
-            render_physical_device
-                .surface_formats(&surface, Default::default())
-                .unwrap()[0]
-                .0,
-        );
+        let composite_alpha = caps
+            .supported_composite_alpha
+            .into_iter()
+            .next()
+            .ok_or_else(|| SandError::swapchain("creation", "no supported composite alpha"))?;
+        let (image_format, image_color_space) =
+            choose_surface_format(render_physical_device, &surface, prefer_hdr)?;
+        let present_mode =
+            choose_present_mode(render_physical_device, &surface, present_preference);
 
         Swapchain::new(
             render_device.clone(),
             surface,
             SwapchainCreateInfo {
                 min_image_count: caps.min_image_count,
-                image_format,
+                image_format: Some(image_format),
+                image_color_space,
                 image_extent: dimensions.into(),
                 image_usage: ImageUsage::COLOR_ATTACHMENT,
                 composite_alpha,
-                present_mode: PresentMode::Mailbox, //TODO add support for GPUs which don't have mailbox support, apparently immediate is second best.
+                present_mode,
                 ..Default::default()
             },
         )
-        .unwrap()
+        .map_err(|e| SandError::swapchain("creation", e))?
     };
-    (swapchain, images)
+    Ok((swapchain, images))
 }
 
-pub fn recreate_swapchain<T>(
+pub fn recreate_swapchain(
     window: &Window,
     render_pass: &Arc<RenderPass>,
     swapchain: &mut Arc<Swapchain>,
@@ -222,41 +482,51 @@ pub fn recreate_swapchain<T>(
     render_device: &Arc<Device>,
     render_queue: &Arc<Queue>,
     vertex_buffer: &Subbuffer<[CPUVertex]>,
-    command_buffers: &mut Vec<Arc<PrimaryAutoCommandBuffer>>,
-    vs: &Arc<ShaderModule>,
-    fs: &Arc<ShaderModule>,
-    buffer: &Subbuffer<[T]>,
-    push_constants: init::fs::PushType,
-) {
+    command_buffers: &mut Vec<RenderCommands>,
+    shaders: &RenderShaders,
+    world: &World,
+    render_params: &Subbuffer<init::particle_vs::RenderParams>,
+    profiler: Option<&GpuProfiler>,
+    present_preference: PresentPreference,
+) -> Result<()> {
     let new_dimensions = window.inner_size();
+    let present_mode = choose_present_mode(
+        render_device.physical_device(),
+        swapchain.surface(),
+        present_preference,
+    );
 
     let (new_swapchain, new_images) = match swapchain.recreate(SwapchainCreateInfo {
         image_extent: new_dimensions.into(), // here, "image_extend" will correspond to the window dimensions
+        present_mode,
         ..swapchain.create_info()
     }) {
         Ok(r) => r,
         // This error tends to happen when the user is manually resizing the window.
         // Simply restarting the loop is the easiest way to fix this issue.
-        Err(SwapchainCreationError::ImageExtentNotSupported { .. }) => return,
-        Err(e) => panic!("failed to recreate swapchain: {e}"),
+        Err(SwapchainCreationError::ImageExtentNotSupported { .. }) => return Ok(()),
+        Err(e) => return Err(SandError::swapchain("recreation", e)),
     };
     *swapchain = new_swapchain;
-    let frame_buffers = get_framebuffers(&new_images, render_pass.clone());
+    let push_constants = push_constants(swapchain, new_dimensions.into());
+    let frame_buffers = get_framebuffers(&new_images, render_pass.clone())?;
     viewport.dimensions = new_dimensions.into();
-    let new_pipeline = get_pipeline(
+    let new_pipelines = get_pipelines(
         render_device.clone(),
-        vs.clone(),
-        fs.clone(),
+        shaders,
         render_pass.clone(),
         viewport.clone(),
-    );
+    )?;
     *command_buffers = get_command_buffers(
         render_device,
         render_queue,
-        &new_pipeline,
+        &new_pipelines,
         &frame_buffers,
         vertex_buffer,
         push_constants,
-        buffer,
-    );
+        world,
+        render_params,
+        profiler,
+    )?;
+    Ok(())
 }
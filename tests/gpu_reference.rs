@@ -0,0 +1,417 @@
+//! Runs the same world through the gpu tick and the cpu reference and compares them, the regression
+//! test for every change to sand_particle.glsl.
+//!
+//! Needs a vulkan driver, a software one is enough: with lavapipe installed run
+//! `SAND_GPU=cpu cargo test --test gpu_reference`. Without any driver the tests fail, set
+//! `SAND_ALLOW_NO_GPU=1` to skip them instead on machines that can't run them.
+
+use vulkano::memory::allocator::StandardMemoryAllocator;
+use vulkano::padded::Padded;
+
+use sand::cli::WORK_GROUP_SIZE;
+use sand::config::SimulationConfig;
+use sand::debug::DebugOptions;
+use sand::device_select::DeviceSelection;
+use sand::error::SandError;
+use sand::gpu_constructor;
+use sand::input::{Input, InputState, Recorder, Replay};
+use sand::pass_structs::ComputeContext;
+use sand::simulation::bodies;
+use sand::simulation::fields::Field;
+use sand::simulation::geometry::{Geometry, Surface};
+use sand::simulation::impulse::{Falloff, Impulse};
+use sand::simulation::materials;
+use sand::simulation::reactions::ReactionTable;
+use sand::simulation::reference;
+use sand::simulation::rng;
+use sand::simulation::sand::sand_shader::Material;
+use sand::simulation::scene;
+use sand::simulation::sleep::Sleep;
+use sand::simulation::world::World;
+
+const TICKS: u64 = 120;
+const PARTICLES: u32 = 1000; // not a multiple of the work group size, so empty slots get tested too
+const TOLERANCE: f32 = 1e-4; // relative, shouldn't be needed with precise but drivers differ
+const SEED: u32 = 0x5eed;
+const IMPULSE_TICK: u64 = TICKS / 2; // the impulses land before this tick
+
+fn config() -> SimulationConfig {
+    SimulationConfig {
+        gravity: [3f32, 98f32],
+        damping: 0.99,
+        timestep: 1f32 / 60f32,
+        jitter: 5f32,
+        heat_radius: 4f32,
+        reaction_radius: 3f32,
+        sph_radius: 8f32,
+        // quick to sleep, so what lands on the geometry dozes off and gets woken again within TICKS
+        sleep_speed: 30f32,
+        sleep_ticks: 10,
+    }
+}
+
+fn reactions() -> ReactionTable {
+    ReactionTable::load_or_default(None).unwrap()
+}
+
+// something of every shape below the row of particles, so they land on it within TICKS
+fn geometry() -> Geometry {
+    let mut geometry = Geometry::default();
+    let surface = Surface::default();
+    geometry.add_bounds([0f32, 0f32], [1100f32, 400f32], surface);
+    geometry.add_box([500f32, 230f32], [600f32, 10f32], surface);
+    geometry.add_circle([300f32, 180f32], 20f32, Surface { restitution: 0.8, friction: 0.5 });
+    geometry.add_capsule([600f32, 170f32], [700f32, 190f32], 5f32, surface);
+    geometry.add_polygon(&[[800f32, 200f32], [900f32, 150f32], [950f32, 200f32]], surface);
+    geometry
+}
+
+// one of each kind over the falling row
+fn fields() -> Vec<Field> {
+    vec![
+        Field::Wind {
+            centre: [150f32, 150f32],
+            half_size: [100f32, 80f32],
+            acceleration: [40f32, -10f32],
+        },
+        Field::Attractor {
+            centre: [450f32, 160f32],
+            radius: 60f32,
+            strength: 150f32,
+            falloff: Falloff::Linear,
+        },
+        Field::Vortex {
+            centre: [850f32, 170f32],
+            radius: 70f32,
+            strength: 120f32,
+            falloff: Falloff::Quadratic,
+        },
+    ]
+}
+
+// one on the row of static wood, so some breaks loose, turns to debris and catches fire, and one
+// over the falling water
+fn impulses() -> Vec<Impulse> {
+    vec![
+        Impulse {
+            pos: [280f32, 105f32],
+            radius: 40f32,
+            strength: 200f32,
+            falloff: Falloff::Linear,
+            debris: true,
+            ignite: true,
+        },
+        Impulse {
+            pos: [700f32, 150f32],
+            radius: 80f32,
+            strength: 300f32,
+            falloff: Falloff::Quadratic,
+            debris: false,
+            ignite: false,
+        },
+    ]
+}
+
+fn initial_particles() -> Vec<Material> {
+    let mut particles = scene::generate(PARTICLES, 1);
+    for (i, particle) in particles.iter_mut().enumerate() {
+        // a heater every so often in a row of water, so heat flows, water boils and steam condenses,
+        // and some lava, wood, fire and oil so the default reactions, static, flammable and sph particles run,
+        // and a row of stone for a rigid body
+        let kind = match i % 50 {
+            0 => materials::HEATER,
+            17 => materials::LAVA,
+            25 => materials::WOOD,
+            33 => materials::FIRE,
+            40..=44 => materials::OIL,
+            45..=49 => materials::STONE,
+            _ => materials::WATER,
+        };
+        *particle = Material {
+            id: particle.id,
+            ..materials::kind(kind).particle(kind, particle.pos)
+        };
+        particle.temperature += (i % 11) as f32 * 8f32;
+        // something other than zero so damping is actually exercised
+        particle.vel = [(i % 7) as f32 - 3f32, (i % 5) as f32 * 2f32];
+    }
+    // falls far enough that landing knocks some of it off
+    for (number, chunk) in particles.chunks_mut(50).enumerate() {
+        if chunk.len() == 50 {
+            bodies::make_body(&mut chunk[45..], number as u32 + 1);
+        }
+    }
+    particles
+}
+
+fn close(gpu: f32, cpu: f32) -> bool {
+    (gpu - cpu).abs() <= TOLERANCE * cpu.abs().max(1f32)
+}
+
+// every mismatch, not just the first, so a broken shader is easier to read
+fn compare(gpu: &[Material], cpu: &[Material]) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    for (i, (gpu, cpu)) in gpu.iter().zip(cpu).enumerate() {
+        let matches = gpu.id == cpu.id
+            && gpu.body == cpu.body
+            && gpu.tags == cpu.tags
+            && gpu.kind == cpu.kind
+            && close(gpu.temperature, cpu.temperature)
+            && (0..2).all(|axis| close(gpu.pos[axis], cpu.pos[axis]))
+            && (0..2).all(|axis| close(gpu.vel[axis], cpu.vel[axis]));
+        if !matches {
+            mismatches.push(format!("particle {i}:\n  gpu {gpu:?}\n  cpu {cpu:?}"));
+        }
+    }
+    mismatches
+}
+
+// opts in to passing without a gpu, so a runner without one can't pass by checking nothing
+const ALLOW_NO_GPU_ENV_VAR: &str = "SAND_ALLOW_NO_GPU";
+
+// None means there's no vulkan here and the test was allowed to skip
+fn headless() -> Option<ComputeContext> {
+    match gpu_constructor::construct_headless(&DeviceSelection::from_env(), DebugOptions::from_env()) {
+        Ok(context) => Some(context),
+        Err(e @ (SandError::Library(_) | SandError::DeviceSelection(_)))
+            if std::env::var_os(ALLOW_NO_GPU_ENV_VAR).is_some() =>
+        {
+            eprintln!("skipping, no usable vulkan device and {ALLOW_NO_GPU_ENV_VAR} is set: {e}");
+            None
+        }
+        Err(e @ (SandError::Library(_) | SandError::DeviceSelection(_))) => {
+            panic!("no usable vulkan device, set {ALLOW_NO_GPU_ENV_VAR}=1 to skip instead: {e}")
+        }
+        Err(e) => panic!("{e}"),
+    }
+}
+
+// what run_gpu ends up with
+struct GpuRun {
+    particles: Vec<Material>,
+    checksums: Vec<u32>, // after every tick
+    settled_awake: u32,  // awake on the tick before the impulses
+    impulse_awake: u32,  // awake on the tick they land
+}
+
+// initial_particles() in fields(), with `spare_groups` work groups of empty slots past the ones they need
+fn new_world(
+    context: &ComputeContext,
+    memory_allocator: &StandardMemoryAllocator,
+    seed: u32,
+    spare_groups: u32,
+) -> World {
+    let mut world = World::new(
+        &context.device,
+        &context.compute_queue,
+        &[context.compute_queue.queue_family_index()],
+        memory_allocator,
+        initial_particles().into_iter().map(Padded).collect(),
+        [PARTICLES.div_ceil(WORK_GROUP_SIZE) + spare_groups, 1, 1],
+        &config(),
+        &reactions(),
+        &geometry(),
+        seed,
+        None,
+    )
+    .unwrap();
+    world.set_fields(fields()).unwrap();
+    world
+}
+
+// runs the default particles in fields() for TICKS ticks with impulses() halfway through
+fn run_gpu(context: &ComputeContext, seed: u32) -> GpuRun {
+    let memory_allocator = StandardMemoryAllocator::new_default(context.device.clone());
+    let mut world = new_world(context, &memory_allocator, seed, 0);
+    let mut checksums = world.run_ticks(IMPULSE_TICK).unwrap();
+    // run_ticks numbers the slots from 0 every call
+    let settled_awake = world.awake(IMPULSE_TICK as u32 - 1).unwrap();
+    for impulse in impulses() {
+        world.impulse(impulse);
+    }
+    checksums.extend(world.run_ticks(1).unwrap());
+    let impulse_awake = world.awake(0).unwrap();
+    checksums.extend(world.run_ticks(TICKS - IMPULSE_TICK - 1).unwrap());
+    GpuRun {
+        particles: world.download(&memory_allocator).unwrap(),
+        checksums,
+        settled_awake,
+        impulse_awake,
+    }
+}
+
+#[test]
+fn gpu_tick_matches_cpu_reference() {
+    let Some(context) = headless() else {
+        return;
+    };
+    let run = run_gpu(&context, SEED);
+    let (gpu, checksums) = (run.particles, run.checksums);
+    // by the impulses what landed has gone to sleep, and they wake some of it up
+    assert!(
+        run.settled_awake < PARTICLES,
+        "all {PARTICLES} particles are still awake after {IMPULSE_TICK} ticks"
+    );
+    assert!(
+        run.impulse_awake > run.settled_awake,
+        "the impulses woke nothing, {} awake before and {} after",
+        run.settled_awake,
+        run.impulse_awake
+    );
+
+    let mut cpu = initial_particles();
+    let (config, reactions, geometry, fields) = (config(), reactions(), geometry(), fields());
+    let mut sleep = Sleep::new(cpu.len());
+    // run_gpu sets the fields, which wakes everything
+    sleep.wake_all();
+    reference::run_ticks(
+        &mut cpu,
+        &config,
+        &reactions,
+        &geometry,
+        &fields,
+        &mut sleep,
+        SEED,
+        0,
+        IMPULSE_TICK,
+    );
+    let asleep = |sleep: &Sleep| -> Vec<usize> {
+        let sleepers = sleep.sleepers.iter().enumerate();
+        sleepers.filter(|(_, sleeper)| sleeper.still >= config.sleep_ticks).map(|(i, _)| i).collect()
+    };
+    let settled = asleep(&sleep);
+    assert!(!settled.is_empty(), "nothing fell asleep on the cpu in {IMPULSE_TICK} ticks");
+    let impulse_tick = IMPULSE_TICK as u32;
+    reference::tick(
+        &mut cpu,
+        &config,
+        &reactions,
+        &geometry,
+        &fields,
+        &impulses(),
+        &mut sleep,
+        SEED,
+        impulse_tick,
+    );
+    let still_asleep = asleep(&sleep);
+    assert!(
+        settled.iter().any(|i| !still_asleep.contains(i)),
+        "the impulses didn't wake any of the {} sleepers",
+        settled.len()
+    );
+    reference::run_ticks(
+        &mut cpu,
+        &config,
+        &reactions,
+        &geometry,
+        &fields,
+        &mut sleep,
+        SEED,
+        IMPULSE_TICK as u32 + 1,
+        TICKS - IMPULSE_TICK - 1,
+    );
+    let work_groups = PARTICLES.div_ceil(WORK_GROUP_SIZE);
+
+    assert_eq!(gpu.len() as u64, (work_groups * WORK_GROUP_SIZE) as u64);
+    let mismatches = compare(&gpu[..cpu.len()], &cpu);
+    assert!(
+        mismatches.is_empty(),
+        "{} of {} particles differ after {TICKS} ticks:\n{}",
+        mismatches.len(),
+        cpu.len(),
+        mismatches.join("\n")
+    );
+    // slots past the scene have to stay empty
+    assert!(gpu[cpu.len()..].iter().all(|particle| particle.id == 0));
+    // the shader's checksum has to hash exactly what ended up in the buffer
+    assert_eq!(checksums.last().copied(), Some(rng::checksum(&gpu)));
+}
+
+#[test]
+fn same_seed_gives_the_same_world() {
+    let Some(context) = headless() else {
+        return;
+    };
+    let first = run_gpu(&context, SEED);
+    let second = run_gpu(&context, SEED);
+    let (first, first_checksums) = (first.particles, first.checksums);
+    let (second, second_checksums) = (second.particles, second.checksums);
+    assert_eq!(first_checksums, second_checksums);
+    assert!(first
+        .iter()
+        .zip(&second)
+        .all(|(a, b)| a.pos.map(f32::to_bits) == b.pos.map(f32::to_bits)
+            && a.vel.map(f32::to_bits) == b.vel.map(f32::to_bits)));
+
+    let other_checksums = run_gpu(&context, SEED + 1).checksums;
+    assert_ne!(first_checksums.last(), other_checksums.last(), "the seed isn't reaching the shader");
+}
+
+// something of every input that changes the world, at the tick it's applied before
+fn script() -> Vec<(u64, Input)> {
+    vec![
+        (3, Input::Material(materials::SAND)),
+        (3, Input::Paint { pos: [120.5, 60.25] }),
+        (3, Input::Paint { pos: [121.5, 60.25] }),
+        (10, Input::Material(materials::STONE)),
+        (10, Input::Body { pos: [640f32, 40f32] }),
+        (25, Input::Field(Field::attractor_brush([500f32, 120f32]))),
+        (40, Input::Explode { pos: [300f32, 110f32] }),
+        (55, Input::ClearFields),
+        (
+            60,
+            Input::Params(SimulationConfig {
+                gravity: [-3f32, 40f32],
+                ..config()
+            }),
+        ),
+        (70, Input::Paint { pos: [1f32 / 3f32, 80f32] }),
+    ]
+}
+
+// ticks one at a time, applying `inputs` before the tick they're for like a headless replay does.
+// returns the checksum after every tick
+fn run_inputs(world: &mut World, mut inputs: impl FnMut(u64) -> Vec<Input>) -> Vec<u32> {
+    let mut input_state = InputState::default();
+    let mut checksums = Vec::new();
+    for _ in 0..TICKS {
+        for input in inputs(world.ticks()) {
+            input_state.apply(&input, world).unwrap();
+        }
+        checksums.extend(world.run_ticks(1).unwrap());
+    }
+    checksums
+}
+
+#[test]
+fn a_replayed_recording_gives_the_same_checksums() {
+    let Some(context) = headless() else {
+        return;
+    };
+    let memory_allocator = StandardMemoryAllocator::new_default(context.device.clone());
+    let path = std::env::temp_dir().join(format!("sand-gpu-reference-{}.txt", std::process::id()));
+
+    // the body brush's block needs room past the scene
+    let mut world = new_world(&context, &memory_allocator, SEED, 1);
+    let mut recorder = Recorder::create(&path, SEED).unwrap();
+    let script = script();
+    let recorded = run_inputs(&mut world, |tick| {
+        let inputs: Vec<Input> =
+            script.iter().filter(|(at, _)| *at == tick).map(|(_, input)| input.clone()).collect();
+        for input in &inputs {
+            recorder.record(tick, input).unwrap();
+        }
+        inputs
+    });
+    drop(recorder);
+
+    let replay = Replay::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    let Ok(mut replay) = replay else {
+        panic!("the recording didn't load");
+    };
+    let mut world = new_world(&context, &memory_allocator, replay.seed, 1);
+    let replayed = run_inputs(&mut world, |tick| replay.take(tick));
+    assert!(replay.is_done(), "inputs left over after {TICKS} ticks");
+    assert_eq!(recorded, replayed);
+}